@@ -39,7 +39,7 @@ pub extern "system" fn Java_com_pos_hardware_HardwareService_startServer(
             
             let security = Arc::new(crate::security::SecurityManager::new("android_secret".to_string()));
 
-            if let Err(e) = crate::socket::run_server(port as u16, device_manager, security).await {
+            if let Err(e) = crate::socket::run_server(port as u16, device_manager, security, None).await {
                 log::error!("Android Server Failed: {}", e);
             }
         });
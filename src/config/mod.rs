@@ -1,12 +1,49 @@
 use serde::Deserialize;
 use crate::errors::ServiceError;
+use crate::hardware::printer::profile::ProfileOverrides;
+use crate::hardware::transport::network::NetworkOptions;
+use crate::hardware::transport::serial::SerialOptions;
+use crate::encoder::{CommandSet, LabelLanguage};
+use crate::queue::group::GroupPolicy;
+use crate::routing::RoutingConfig;
+use crate::security::Scope;
 use std::env;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct PrintConfig {
     pub id: String,
-    pub device_type: String, // e.g., "esc_pos_network", "serial"
-    pub connection: String,  // e.g., "192.168.1.100:9100" or "COM3"
+    pub device_type: String, // e.g., "esc_pos_network", "serial", "device_file"
+    pub connection: String,  // e.g., "192.168.1.100:9100", "COM3" or "/dev/usb/lp0"
+    pub codepage: Option<String>, // e.g., "cp858", "cp1252", "iso-8859-15" (default: "cp437")
+    pub profile: Option<String>,  // e.g., "epson_tm_t88", "generic_58mm", "xprinter_80"
+    pub command_set: Option<CommandSet>, // "escpos" (default) or "star"
+    pub overrides: Option<ProfileOverrides>, // e.g., { chars_per_line = 30, native_qr = false }
+    #[serde(default)]
+    pub network: NetworkOptions, // Network printers only, e.g., { idle_timeout_secs = 0 }
+    #[serde(default)]
+    pub serial: SerialOptions,   // Serial printers only, e.g., { flow_control = "rts_cts" }
+}
+
+// Several printers used as one (the POS prints to the group id)
+#[derive(Debug, Deserialize, Clone)]
+pub struct PrinterGroupConfig {
+    pub id: String,
+    pub members: Vec<String>, // Printer ids, e.g., ["kitchen_1", "kitchen_2"] (first = primary)
+    #[serde(default)]
+    pub policy: GroupPolicy,  // "failover" (default), "round_robin" or "broadcast"
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct LabelPrinterConfig {
+    pub id: String,
+    pub device_type: String, // "network", "serial", "device_file", "mock"
+    pub connection: String,  // e.g., "192.168.1.150:9100", "COM4:9600" or "/dev/usb/lp1"
+    pub language: LabelLanguage, // "zpl", "tspl" or "epl"
+    pub dpi: Option<u32>,    // Printer resolution: 203 (default), 300 or 600
+    #[serde(default)]
+    pub network: NetworkOptions, // Network label printers only
+    #[serde(default)]
+    pub serial: SerialOptions,   // Serial label printers only
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -14,6 +51,9 @@ pub struct DrawerConfig {
     pub id: String,
     pub device_type: String, // "printer_driven" uses 'connection' as the printer_id
     pub connection: Option<String>,
+    // Set if the drawer reports "open" on a high sensor level (most report "closed")
+    #[serde(default)]
+    pub open_when_sensor_high: bool,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -21,22 +61,101 @@ pub struct DisplayConfig {
     pub id: String,
     pub device_type: String, // "serial", "mock"
     pub connection: Option<String>, // "COM1:9600"
+    #[serde(default)]
+    pub serial: SerialOptions,      // e.g., { parity = "odd", data_bits = 7 }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct DevicesConfig {
     pub printers: Vec<PrintConfig>,
+    #[serde(default)]
+    pub printer_groups: Vec<PrinterGroupConfig>,
+    #[serde(default)]
+    pub label_printers: Vec<LabelPrinterConfig>,
     pub drawers: Vec<DrawerConfig>,
     pub displays: Vec<DisplayConfig>,
+    // Which printer makes which items of a "print_order"
+    #[serde(default)]
+    pub routing: RoutingConfig,
+}
+
+// Where print jobs wait (on disk) while their printer is offline
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SpoolConfig {
+    pub directory: String,         // e.g., "spool" (next to the logs folder)
+    pub job_expiry_secs: u64,      // Give up on a job after this long (default: 30 minutes)
+    pub retry_max_delay_secs: u64, // Longest pause between two attempts (default: 60 seconds)
+}
+
+impl Default for SpoolConfig {
+    fn default() -> Self {
+        Self {
+            directory: "spool".into(),
+            job_expiry_secs: 30 * 60,
+            retry_max_delay_secs: 60,
+        }
+    }
+}
+
+// Events pushed to subscribed clients (printer out of paper, drawer open...)
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct EventsConfig {
+    pub status_poll_secs: u64, // How often printers and drawers are checked (0 = never)
+    pub hotplug_poll_secs: u64, // How often serial ports are checked for unplugged/replugged devices (0 = never)
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self { status_poll_secs: 5, hotplug_poll_secs: 2 }
+    }
+}
+
+// A named client key with limited permissions (see `gen-key`)
+#[derive(Debug, Deserialize, Clone)]
+pub struct ApiKeyConfig {
+    pub name: String,              // e.g., "kiosk" (shown in the logs)
+    pub key_hash: String,          // "sha256:<salt>:<hash>", printed by `gen-key`
+    pub scopes: Vec<Scope>,        // "print", "drawer", "display", "status", "admin"
+    pub devices: Option<Vec<String>>, // Only these device ids (default: all)
+    // Shared secret for signed commands; with it, unsigned commands are refused
+    pub signing_secret: Option<String>,
+}
+
+// Certificate for wss:// (see `gen-cert`)
+#[derive(Debug, Deserialize, Clone)]
+pub struct TlsConfig {
+    pub cert_path: String, // e.g., "certs/localhost.pem"
+    pub key_path: String,  // e.g., "certs/localhost-key.pem"
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Settings {
     pub port: u16,
+    pub http_port: Option<u16>, // HTTP REST API next to the WebSocket (off if missing)
+    // Full-access token. Empty = only the `api_keys` below can log in.
+    #[serde(default)]
     pub auth_token: String,
+    #[serde(default)]
+    pub api_keys: Vec<ApiKeyConfig>,
+    // Web pages allowed to connect, e.g. ["https://pos.example.com", "https://*.example.com"].
+    // Empty = any page.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    // Seconds a new connection has to send "auth" (default: 10)
+    pub auth_timeout_secs: Option<u64>,
+    // How far a signed command's timestamp may be from our clock, in seconds (default: 30)
+    pub signature_max_skew_secs: Option<u64>,
     pub log_level: String,
     pub log_retention_days: Option<u64>, // Added optional field for log cleanup
     pub devices: DevicesConfig,
+    #[serde(default)]
+    pub spool: SpoolConfig,
+    #[serde(default)]
+    pub events: EventsConfig,
+    // Serve wss:// instead of ws:// (off if missing)
+    pub tls: Option<TlsConfig>,
 }
 
 impl Settings {
@@ -0,0 +1,176 @@
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(target_os = "linux")]
+use futures::{FutureExt, StreamExt};
+use crate::events::{self, Event};
+use crate::hardware::transport::{ports::PortAddress, serial::SharedSerialTransport};
+use super::DeviceManager;
+use tracing::{debug, info, warn};
+
+// =========================================================================
+// HOTPLUG WATCHER
+// =========================================================================
+// Notices when the cable of a serial/USB printer or display is pulled out or
+// plugged back in:
+//   unplugged:     the port is closed, clients get "device.disconnected"
+//   plugged in:    the port is opened again, waiting print jobs are retried
+//                  at once, clients get "device.connected"
+// On Linux we are told as soon as something appears in or disappears from
+// /dev; the ports are also listed every `hotplug_poll_secs`, which is all
+// other systems get.
+
+// Device kinds, as shown in the events
+pub(super) const PRINTER: &str = "printer";
+pub(super) const LABEL_PRINTER: &str = "label_printer";
+pub(super) const DISPLAY: &str = "display";
+
+// Lets the system finish setting up a new device (names, permissions)
+// before we look at it
+#[cfg(target_os = "linux")]
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+#[derive(Clone)]
+pub(super) struct WatchedPort {
+    pub(super) device_id: String,
+    pub(super) kind: &'static str,
+    address: PortAddress,
+    transport: SharedSerialTransport,
+    // None = not checked yet
+    pub(super) online: Option<bool>,
+}
+
+impl DeviceManager {
+    // Called while loading the config for every serial device
+    pub(super) fn watch_port(&self, device_id: &str, kind: &'static str, transport: SharedSerialTransport) {
+        // A bad address is reported when the device is used
+        let Ok(address) = PortAddress::parse(transport.address()) else { return };
+        self.serial_ports.lock().unwrap().push(WatchedPort { device_id: device_id.to_string(), kind, address, transport, online: None });
+    }
+
+    /// Starts watching the serial devices (`poll_every` = 0 turns it off).
+    pub fn spawn_hotplug_watcher(self: &Arc<Self>, poll_every: Duration) {
+        if self.serial_ports.lock().unwrap().is_empty() {
+            return;
+        }
+        if poll_every.is_zero() {
+            info!("Serial hotplug detection is off");
+            return;
+        }
+        let devices = self.clone();
+        tokio::spawn(async move {
+            let mut changes = PortChanges::new(poll_every);
+            loop {
+                devices.check_serial_ports().await;
+                changes.next().await;
+            }
+        });
+    }
+
+    async fn check_serial_ports(&self) {
+        // Listing the ports asks the system (udev...), which may block a moment
+        let ports = match tokio::task::spawn_blocking(serialport::available_ports).await {
+            Ok(Ok(ports)) => ports,
+            Ok(Err(e)) => {
+                debug!("Cannot list serial ports: {}", e);
+                return;
+            }
+            Err(_) => return,
+        };
+
+        // (device, plugged in now, first check)
+        let changes: Vec<(WatchedPort, bool, bool)> = {
+            let mut watched = self.serial_ports.lock().unwrap();
+            watched.iter_mut().filter_map(|port| {
+                let plugged_in = port.address.is_plugged_in(&ports);
+                let before = port.online.replace(plugged_in);
+                (before != Some(plugged_in)).then(|| (port.clone(), plugged_in, before.is_none()))
+            }).collect()
+        };
+
+        for (port, plugged_in, first) in changes {
+            if first {
+                // Nothing was open yet: just say what we found
+                if !plugged_in {
+                    warn!("Serial {} {} ({}) is not plugged in", port.kind, port.device_id, port.address);
+                }
+                continue;
+            }
+            let data = serde_json::json!({ "kind": port.kind, "connection": port.address.to_string() });
+            if plugged_in {
+                info!("Serial {} {} ({}) was plugged in", port.kind, port.device_id, port.address);
+                // If it fails (e.g. permissions not set up yet), the next job tries again
+                if let Err(e) = port.transport.reopen().await {
+                    warn!("Cannot open {} again yet: {}", port.device_id, e);
+                }
+                if port.kind == PRINTER {
+                    if let Some(queue) = self.get_print_queue(&port.device_id).await {
+                        queue.retry_now();
+                    }
+                }
+                self.publish(Event::new(events::DEVICE_CONNECTED, &port.device_id, data));
+            } else {
+                warn!("Serial {} {} ({}) was unplugged", port.kind, port.device_id, port.address);
+                port.transport.close().await;
+                self.publish(Event::new(events::DEVICE_DISCONNECTED, &port.device_id, data));
+            }
+        }
+    }
+}
+
+// Waits until the serial ports may have changed
+struct PortChanges {
+    poll_every: Duration,
+    #[cfg(target_os = "linux")]
+    dev: Option<inotify::EventStream<[u8; 1024]>>,
+}
+
+impl PortChanges {
+    fn new(poll_every: Duration) -> Self {
+        Self {
+            poll_every,
+            #[cfg(target_os = "linux")]
+            dev: match watch_dev() {
+                Ok(dev) => Some(dev),
+                Err(e) => {
+                    warn!("Cannot watch /dev ({}), checking serial ports every {}s instead", e, poll_every.as_secs());
+                    None
+                }
+            },
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn next(&mut self) {
+        let Some(dev) = &mut self.dev else {
+            tokio::time::sleep(self.poll_every).await;
+            return;
+        };
+        tokio::select! {
+            event = dev.next() => match event {
+                Some(Ok(_)) => {
+                    // One plug usually creates several files: wait for all of them
+                    tokio::time::sleep(SETTLE_TIME).await;
+                    while let Some(Some(Ok(_))) = dev.next().now_or_never() {}
+                }
+                _ => {
+                    warn!("Stopped watching /dev, checking serial ports every {}s instead", self.poll_every.as_secs());
+                    self.dev = None;
+                }
+            },
+            _ = tokio::time::sleep(self.poll_every) => {}
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    async fn next(&mut self) {
+        tokio::time::sleep(self.poll_every).await;
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn watch_dev() -> std::io::Result<inotify::EventStream<[u8; 1024]>> {
+    use inotify::{Inotify, WatchMask};
+    let inotify = Inotify::init()?;
+    inotify.watches().add("/dev", WatchMask::CREATE | WatchMask::DELETE)?;
+    inotify.into_event_stream([0u8; 1024])
+}
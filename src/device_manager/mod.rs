@@ -1,25 +1,65 @@
+mod hotplug;
+mod monitor;
+
 use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::hardware::traits::{Printer, Drawer, Display};
-use crate::hardware::printer::{MockPrinter, network::NetworkPrinter, serial::SerialPrinter, windows::WindowsPrinter};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, RwLock};
+use crate::hardware::traits::{Printer, LabelPrinter, Drawer, Display};
+use crate::hardware::printer::{MockPrinter, device_file::DeviceFilePrinter, network::NetworkPrinter, serial::SerialPrinter, windows::WindowsPrinter};
 use crate::hardware::drawer::{MockDrawer, printer_drawer::PrinterDrivenDrawer};
 use crate::hardware::display::{MockDisplay, serial::SerialDisplay};
+use crate::hardware::label::{self, MockLabelPrinter, transport_printer::TransportLabelPrinter};
+use crate::hardware::transport::{device_file::DeviceFileTransport, network::NetworkTransport, serial::SerialTransport};
 use crate::config::DevicesConfig;
+use crate::encoder::{CodePage, CommandSet};
+use crate::hardware::printer::profile::{self, PrinterProfile};
 use crate::errors::ServiceError;
+use crate::queue::PrintQueue;
+use crate::events::Event;
+use crate::queue::group::PrinterGroup;
+use crate::spool::Spool;
+use crate::routing::{OrderData, Router, Ticket};
 
 pub struct DeviceManager {
-    printers: RwLock<HashMap<String, Arc<dyn Printer>>>,
+    // Every printer sits behind its own job queue, so jobs never overlap.
+    // Printer groups live here too, so a group id works wherever a printer id does.
+    printers: RwLock<HashMap<String, Arc<PrintQueue>>>,
+    label_printers: RwLock<HashMap<String, Arc<dyn LabelPrinter>>>,
     drawers: RwLock<HashMap<String, Arc<dyn Drawer>>>,
     displays: RwLock<HashMap<String, Arc<dyn Display>>>,
+    // Print jobs waiting on disk for their printer
+    spool: Arc<Spool>,
+    // Event bus: job results, printer status, drawer state... for connected clients
+    events: broadcast::Sender<Event>,
+    // Splits "print_order" messages into per-station tickets
+    router: RwLock<Router>,
+    // Serial devices, watched for being unplugged and plugged back in
+    serial_ports: Mutex<Vec<hotplug::WatchedPort>>,
+}
+
+impl Default for DeviceManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DeviceManager {
+    // Without a spool directory, jobs are still retried but don't survive a restart
     pub fn new() -> Self {
+        Self::with_spool(Spool::disabled())
+    }
+
+    pub fn with_spool(spool: Spool) -> Self {
+        let (events, _) = broadcast::channel(100);
         Self {
             printers: RwLock::new(HashMap::new()),
+            label_printers: RwLock::new(HashMap::new()),
             drawers: RwLock::new(HashMap::new()),
             displays: RwLock::new(HashMap::new()),
+            spool: Arc::new(spool),
+            events,
+            router: RwLock::new(Router::new(Default::default())),
+            serial_ports: Mutex::new(Vec::new()),
         }
     }
 
@@ -28,32 +68,158 @@ impl DeviceManager {
         {
             let mut printers = self.printers.write().await;
             for p_conf in &config.printers {
+                // Code page used to convert text into printer bytes
+                let codepage = match p_conf.codepage.as_deref() {
+                    Some(name) => CodePage::from_name(name).unwrap_or_else(|| {
+                        tracing::warn!("Unknown codepage '{}' for printer {}, using CP437", name, p_conf.id);
+                        CodePage::default()
+                    }),
+                    None => CodePage::default(),
+                };
+
+                // Printer model capabilities, with per-printer overrides on top
+                let profile_name = p_conf.profile.as_deref().unwrap_or(profile::DEFAULT_PROFILE);
+                let mut profile = PrinterProfile::named(profile_name).unwrap_or_else(|| {
+                    tracing::warn!("Unknown profile '{}' for printer {}, using default. Known profiles: {}",
+                        profile_name, p_conf.id, PrinterProfile::NAMES.join(", "));
+                    PrinterProfile::default()
+                });
+                if let Some(overrides) = &p_conf.overrides {
+                    profile = profile.with_overrides(overrides);
+                }
+                if let Some(command_set) = p_conf.command_set {
+                    profile.command_set = command_set;
+                }
+                // Star printers can't switch to these pages: accented text would print as garbage
+                if profile.command_set == CommandSet::Star && codepage.star_table().is_none() {
+                    return Err(ServiceError::ConfigError(format!(
+                        "Printer {} uses Star commands, which have no code page {:?}: pick another codepage (e.g. cp858 or cp1252)", p_conf.id, codepage)));
+                }
+                if !profile.supports_codepage(codepage) {
+                    tracing::warn!("Printer {} (profile {}) may not support code page {:?}", p_conf.id, profile.name, codepage);
+                }
+
                 let printer: Arc<dyn Printer> = match p_conf.device_type.as_str() {
-                    "mock" => Arc::new(MockPrinter::new(p_conf.id.clone())),
-                    "network" | "esc_pos_network" => Arc::new(NetworkPrinter::new(p_conf.id.clone(), p_conf.connection.clone())),
+                    "mock" => Arc::new(MockPrinter::new(p_conf.id.clone(), profile)),
+                    "network" | "esc_pos_network" => Arc::new(NetworkPrinter::new(p_conf.id.clone(), p_conf.connection.clone(), profile, codepage, p_conf.network.clone())),
                     "serial" => {
                         // Support "COM1:9600" format or default to 9600
-                        let parts: Vec<&str> = p_conf.connection.split(':').collect();
-                        let (port, baud) = if parts.len() == 2 {
-                            let baud = parts[1].parse::<u32>().unwrap_or(9600);
-                            (parts[0], baud)
-                        } else {
-                            (p_conf.connection.as_str(), 9600)
-                        };
-                        
-                        Arc::new(SerialPrinter::new(p_conf.id.clone(), port.to_string(), baud))
+                        let (port, baud) = split_port_baud(&p_conf.connection);
+                        let printer = SerialPrinter::new(p_conf.id.clone(), port, baud, profile, codepage, p_conf.serial.clone());
+                        self.watch_port(&p_conf.id, hotplug::PRINTER, printer.transport());
+                        Arc::new(printer)
                     },
+                    // e.g. "/dev/usb/lp0" (Linux USB printers), or a file to capture the jobs
+                    "device_file" => Arc::new(DeviceFilePrinter::new(p_conf.id.clone(), p_conf.connection.clone(), profile, codepage)),
                     "windows" => {
                         // NEW: Support for direct Windows Spooler printing
                         tracing::info!("Loading Windows Printer: {}", p_conf.connection);
-                        Arc::new(WindowsPrinter::new(p_conf.connection.clone()))
+                        Arc::new(WindowsPrinter::new(p_conf.connection.clone(), profile, codepage))
                     },
                     _ => {
                         tracing::warn!("Unknown printer type '{}', using Mock", p_conf.device_type);
-                        Arc::new(MockPrinter::new(p_conf.id.clone()))
+                        Arc::new(MockPrinter::new(p_conf.id.clone(), profile))
+                    }
+                };
+                let queue = PrintQueue::new(p_conf.id.clone(), printer, self.spool.clone(), self.events.clone());
+                printers.insert(p_conf.id.clone(), queue);
+            }
+
+            // Printer groups (built from the printers above)
+            for g_conf in &config.printer_groups {
+                if printers.contains_key(&g_conf.id) {
+                    tracing::error!("Printer group {} has the same id as a printer, ignoring the group", g_conf.id);
+                    continue;
+                }
+
+                let mut members = Vec::new();
+                for member_id in &g_conf.members {
+                    match printers.get(member_id) {
+                        Some(member) if config.printers.iter().any(|p| &p.id == member_id) => members.push(member.clone()),
+                        _ => tracing::error!("Printer group {} references unknown printer {}", g_conf.id, member_id),
+                    }
+                }
+                // Receipts are laid out for the first member, so the others should match it
+                if let Some((first, others)) = members.split_first() {
+                    for member in others {
+                        let profile = member.profile();
+                        if profile.chars_per_line != first.profile().chars_per_line || profile.command_set != first.profile().command_set || member.codepage() != first.codepage() {
+                            tracing::warn!("Printer {} in group {} differs from {} (paper width, command set or code page); receipts may look wrong on it",
+                                member.printer_id(), g_conf.id, first.printer_id());
+                        }
+                    }
+                }
+
+                let group = match PrinterGroup::new(g_conf.id.clone(), g_conf.policy, members) {
+                    Ok(group) => group,
+                    Err(e) => {
+                        tracing::error!("{}, ignoring it", e);
+                        continue;
+                    }
+                };
+                tracing::info!("Loading printer group {} ({:?}): {}", g_conf.id, g_conf.policy, g_conf.members.join(", "));
+                let queue = PrintQueue::new(g_conf.id.clone(), Arc::new(group), self.spool.clone(), self.events.clone());
+                printers.insert(g_conf.id.clone(), queue);
+            }
+
+            // Put back the jobs that were still waiting when the service stopped
+            for saved in self.spool.load_all() {
+                match printers.get(&saved.printer_id) {
+                    Some(queue) => {
+                        let job_id = saved.job_id.clone();
+                        match queue.restore(saved) {
+                            Ok(_) => tracing::info!("Restored spooled job {}", job_id),
+                            Err(e) => {
+                                tracing::error!("{}", e);
+                                self.spool.remove(&job_id);
+                            }
+                        }
+                    }
+                    // Kept on disk in case the printer comes back in config.toml
+                    None => tracing::warn!("Spooled job {} is for unknown printer {}, keeping it", saved.job_id, saved.printer_id),
+                }
+            }
+        }
+
+        // Load Order Routing Rules
+        {
+            let router = Router::new(config.routing.clone());
+            let printers = self.printers.read().await;
+            for printer_id in router.printer_ids() {
+                if !printers.contains_key(printer_id) {
+                    tracing::error!("Routing rule points to unknown printer {}", printer_id);
+                }
+            }
+            *self.router.write().await = router;
+        }
+
+        // Load Label Printers
+        {
+            let mut label_printers = self.label_printers.write().await;
+            for l_conf in &config.label_printers {
+                let dpi = l_conf.dpi.unwrap_or(label::DEFAULT_DPI);
+                let printer: Arc<dyn LabelPrinter> = match l_conf.device_type.as_str() {
+                    "mock" => Arc::new(MockLabelPrinter::new(l_conf.id.clone(), l_conf.language, dpi)),
+                    "network" => {
+                        let transport = NetworkTransport::with_options(l_conf.connection.clone(), l_conf.network.clone());
+                        Arc::new(TransportLabelPrinter::new(l_conf.id.clone(), l_conf.language, dpi, Box::new(transport)))
+                    },
+                    "serial" => {
+                        let (port, baud) = split_port_baud(&l_conf.connection);
+                        let transport = Arc::new(SerialTransport::with_options(port, baud, l_conf.serial.clone()));
+                        self.watch_port(&l_conf.id, hotplug::LABEL_PRINTER, transport.clone());
+                        Arc::new(TransportLabelPrinter::new(l_conf.id.clone(), l_conf.language, dpi, Box::new(transport)))
+                    },
+                    "device_file" => {
+                        let transport = DeviceFileTransport::new(l_conf.connection.clone());
+                        Arc::new(TransportLabelPrinter::new(l_conf.id.clone(), l_conf.language, dpi, Box::new(transport)))
+                    },
+                    _ => {
+                        tracing::warn!("Unknown label printer type '{}', using Mock", l_conf.device_type);
+                        Arc::new(MockLabelPrinter::new(l_conf.id.clone(), l_conf.language, dpi))
                     }
                 };
-                printers.insert(p_conf.id.clone(), printer);
+                label_printers.insert(l_conf.id.clone(), printer);
             }
         }
 
@@ -70,7 +236,8 @@ impl DeviceManager {
                         // Find the printer
                         let target_printer_id = d_conf.connection.as_deref().unwrap_or("unknown");
                         if let Some(printer) = printers.get(target_printer_id) {
-                             Arc::new(PrinterDrivenDrawer::new(d_conf.id.clone(), printer.clone()))
+                             // Kicks go through the printer's queue, between two print jobs
+                             Arc::new(PrinterDrivenDrawer::new(d_conf.id.clone(), printer.clone(), d_conf.open_when_sensor_high))
                         } else {
                             tracing::error!("Drawer {} references unknown printer {}", d_conf.id, target_printer_id);
                             Arc::new(MockDrawer::new(d_conf.id.clone()))
@@ -90,14 +257,10 @@ impl DeviceManager {
                    "mock" => Arc::new(MockDisplay::new(d_conf.id.clone())),
                    "serial" => {
                         let conn_str = d_conf.connection.clone().unwrap_or_else(|| "COM2:9600".to_string());
-                        let parts: Vec<&str> = conn_str.split(':').collect();
-                        let (port, baud) = if parts.len() == 2 {
-                            let baud = parts[1].parse::<u32>().unwrap_or(9600);
-                            (parts[0], baud)
-                        } else {
-                            (conn_str.as_str(), 9600)
-                        };
-                        Arc::new(SerialDisplay::new(d_conf.id.clone(), port.to_string(), baud))
+                        let (port, baud) = split_port_baud(&conn_str);
+                        let display = SerialDisplay::new(d_conf.id.clone(), port, baud, d_conf.serial.clone());
+                        self.watch_port(&d_conf.id, hotplug::DISPLAY, display.transport());
+                        Arc::new(display)
                    },
                     _ => Arc::new(MockDisplay::new(d_conf.id.clone())),
                 };
@@ -109,10 +272,78 @@ impl DeviceManager {
     }
 
     pub async fn get_printer(&self, id: &str) -> Option<Arc<dyn Printer>> {
+        let printers = self.printers.read().await;
+        printers.get(id).map(|queue| queue.clone() as Arc<dyn Printer>)
+    }
+
+    pub async fn get_print_queue(&self, id: &str) -> Option<Arc<PrintQueue>> {
         let printers = self.printers.read().await;
         printers.get(id).cloned()
     }
 
+    /// Splits an order into station tickets and finds each ticket's printer.
+    /// Fails before anything is printed if an item or a printer can't be found.
+    pub async fn route_order(&self, order: &OrderData) -> Result<Vec<(Arc<PrintQueue>, Ticket)>, ServiceError> {
+        let tickets = self.router.read().await.split(order)?;
+        let printers = self.printers.read().await;
+        tickets.into_iter().map(|ticket| {
+            match printers.get(&ticket.printer_id) {
+                Some(queue) => Ok((queue.clone(), ticket)),
+                None => Err(ServiceError::DeviceNotFound(format!("Routing rule points to unknown printer {}", ticket.printer_id))),
+            }
+        }).collect()
+    }
+
+    // Finds the queue of the printer that owns this job
+    pub async fn find_job_queue(&self, job_id: &str) -> Option<Arc<PrintQueue>> {
+        let printers = self.printers.read().await;
+        printers.values().find(|queue| queue.job(job_id).is_some()).cloned()
+    }
+
+    pub fn subscribe_events(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    // Sends an event to every connected client that subscribed to its topic
+    pub fn publish(&self, event: Event) {
+        // Nobody listening is fine
+        let _ = self.events.send(event);
+    }
+
+    /// Every configured device, by kind, sorted by id.
+    pub async fn list_devices(&self) -> serde_json::Value {
+        let mut printers: Vec<_> = self.printers.read().await.iter()
+            .map(|(id, queue)| serde_json::json!({ "id": id, "supports_status": queue.supports_status() }))
+            .collect();
+        let mut label_printers: Vec<_> = self.label_printers.read().await.iter()
+            .map(|(id, printer)| serde_json::json!({ "id": id, "language": printer.language() }))
+            .collect();
+        let mut drawers: Vec<_> = self.drawers.read().await.iter()
+            .map(|(id, drawer)| serde_json::json!({ "id": id, "supports_state": drawer.supports_state() }))
+            .collect();
+        let mut displays: Vec<_> = self.displays.read().await.keys()
+            .map(|id| serde_json::json!({ "id": id }))
+            .collect();
+        for list in [&mut printers, &mut label_printers, &mut drawers, &mut displays] {
+            list.sort_by(|a, b| a["id"].as_str().cmp(&b["id"].as_str()));
+        }
+        // Serial devices: is the cable plugged in? (null until the hotplug watcher checked)
+        let ports = self.serial_ports.lock().unwrap();
+        for (kind, list) in [(hotplug::PRINTER, &mut printers), (hotplug::LABEL_PRINTER, &mut label_printers), (hotplug::DISPLAY, &mut displays)] {
+            for device in list.iter_mut() {
+                if let Some(port) = ports.iter().find(|port| port.kind == kind && device["id"] == port.device_id.as_str()) {
+                    device["connected"] = serde_json::json!(port.online);
+                }
+            }
+        }
+        serde_json::json!({ "printers": printers, "label_printers": label_printers, "drawers": drawers, "displays": displays })
+    }
+
+    pub async fn get_label_printer(&self, id: &str) -> Option<Arc<dyn LabelPrinter>> {
+        let label_printers = self.label_printers.read().await;
+        label_printers.get(id).cloned()
+    }
+
     pub async fn get_drawer(&self, id: &str) -> Option<Arc<dyn Drawer>> {
         let drawers = self.drawers.read().await;
         drawers.get(id).cloned()
@@ -123,3 +354,13 @@ impl DeviceManager {
         displays.get(id).cloned()
     }
 }
+
+// Splits a "COM1:9600" (or "usb:vid=0x0416,pid=0x5011:9600") connection string
+// into port and baud rate (default 9600)
+fn split_port_baud(connection: &str) -> (String, u32) {
+    // The baud rate is the number after the last ':' ("usb:vid=...,pid=..." has ':' too)
+    match connection.rsplit_once(':') {
+        Some((port, baud)) if baud.parse::<u32>().is_ok() => (port.to_string(), baud.parse().unwrap()),
+        _ => (connection.to_string(), 9600),
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use futures::future::join_all;
+use tokio::time::MissedTickBehavior;
+use crate::encoder::PrinterStatus;
+use crate::events::{self, Event};
+use crate::hardware::traits::Printer;
+use super::DeviceManager;
+use tracing::info;
+
+// =========================================================================
+// STATUS MONITOR
+// =========================================================================
+// Asks every printer (and printer-driven drawer) how it is doing every few
+// seconds, and publishes an event when something CHANGED: paper out, cover
+// opened, printer unreachable, drawer opened or closed...
+// Nothing is polled while no client is logged in (clients only listen to
+// events once they have logged in).
+
+impl DeviceManager {
+    /// Starts the background status polling (`every` = 0 turns it off).
+    pub fn spawn_status_monitor(self: &Arc<Self>, every: Duration) {
+        if every.is_zero() {
+            info!("Printer status polling is off");
+            return;
+        }
+        let devices = self.clone();
+        tokio::spawn(async move {
+            // Last known state; None = the printer didn't answer
+            let mut printers: HashMap<String, Option<PrinterStatus>> = HashMap::new();
+            let mut drawers: HashMap<String, bool> = HashMap::new();
+
+            let mut ticker = tokio::time::interval(every);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            loop {
+                ticker.tick().await;
+                // Only logged-in clients listen to the events
+                if devices.events.receiver_count() == 0 {
+                    // Forget everything, so the next client hears the current state
+                    printers.clear();
+                    drawers.clear();
+                    continue;
+                }
+                devices.poll_printers(&mut printers).await;
+                devices.poll_drawers(&mut drawers).await;
+            }
+        });
+    }
+
+    async fn poll_printers(&self, last: &mut HashMap<String, Option<PrinterStatus>>) {
+        // Copy the list first: a status query can take seconds
+        let queues: Vec<_> = self.printers.read().await.iter()
+            .filter(|(_, queue)| queue.supports_status())
+            .map(|(id, queue)| (id.clone(), queue.clone()))
+            .collect();
+
+        // All printers at once, so one offline printer doesn't delay the others
+        let results = join_all(queues.iter().map(|(id, queue)| async move {
+            (id, queue.status().await)
+        })).await;
+
+        for (id, result) in results {
+            let (status, data) = match result {
+                Ok(status) => (Some(status.clone()), serde_json::to_value(status).unwrap_or_default()),
+                Err(e) => (None, serde_json::json!({ "online": false, "error": e.to_string() })),
+            };
+            if last.get(id) != Some(&status) {
+                last.insert(id.clone(), status);
+                self.publish(Event::new(events::PRINTER_STATUS, id, data));
+            }
+        }
+    }
+
+    async fn poll_drawers(&self, last: &mut HashMap<String, bool>) {
+        let drawers: Vec<_> = self.drawers.read().await.iter()
+            .filter(|(_, drawer)| drawer.supports_state())
+            .map(|(id, drawer)| (id.clone(), drawer.clone()))
+            .collect();
+
+        let results = join_all(drawers.iter().map(|(id, drawer)| async move {
+            (id, drawer.is_open().await)
+        })).await;
+
+        for (id, result) in results {
+            // An unreachable printer is already reported as "printer.status"
+            let Ok(open) = result else { continue };
+            if last.get(id) != Some(&open) {
+                last.insert(id.clone(), open);
+                self.publish(Event::new(events::DRAWER_STATE, id, serde_json::json!({ "open": open })));
+            }
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use serde::Deserialize;
+use crate::encoder::raster::Bitmap;
+use crate::errors::ServiceError;
+
+// =========================================================================
+// BARCODES & QR CODES
+// =========================================================================
+// Options shared by every command set, plus validation: most printers
+// silently print nothing when the data doesn't fit the symbology, so we
+// reject bad data with a clear error instead.
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Symbology {
+    Ean13,
+    Ean8,
+    UpcA,
+    Code39,
+    Code128,
+    Itf,
+    Codabar,
+}
+
+// Where the Human Readable Interpretation (the digits) is printed
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HriPosition {
+    None,
+    Above,
+    #[default]
+    Below,
+    Both,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QrErrorCorrection {
+    L, // ~7% of the code can be damaged
+    #[default]
+    M, // ~15%
+    Q, // ~25%
+    H, // ~30%
+}
+
+/// Checks that `data` can be encoded with `symbology`.
+pub fn validate(symbology: Symbology, data: &str) -> Result<(), ServiceError> {
+    let digits_only = !data.is_empty() && data.bytes().all(|b| b.is_ascii_digit());
+    let valid = match symbology {
+        // The check digit is optional, the printer calculates it
+        Symbology::Ean13 => digits_only && (data.len() == 12 || data.len() == 13),
+        Symbology::Ean8 => digits_only && (data.len() == 7 || data.len() == 8),
+        Symbology::UpcA => digits_only && (data.len() == 11 || data.len() == 12),
+        // Interleaved 2 of 5 encodes digits in pairs
+        Symbology::Itf => digits_only && data.len().is_multiple_of(2),
+        Symbology::Code39 => !data.is_empty() && data.bytes().all(|b| {
+            b.is_ascii_digit() || b.is_ascii_uppercase() || b" $%*+-./".contains(&b)
+        }),
+        // Starts and ends with A, B, C or D (start/stop characters), e.g. "A123456B"
+        Symbology::Codabar => data.len() >= 3 && {
+            let bytes = data.as_bytes();
+            let is_start_stop = |b: &u8| b"ABCDabcd".contains(b);
+            is_start_stop(&bytes[0]) && is_start_stop(&bytes[bytes.len() - 1])
+                && bytes[1..bytes.len() - 1].iter().all(|b| b.is_ascii_digit() || b"$+-./:".contains(b))
+        },
+        Symbology::Code128 => !data.is_empty() && data.is_ascii(),
+    };
+
+    // GS k sends the length as a single byte (CODE128 adds a 2-byte prefix
+    // and sends "{" as "{{")
+    let encoded_len = match symbology {
+        Symbology::Code128 => data.len() + data.matches('{').count(),
+        _ => data.len(),
+    };
+    if valid && encoded_len <= 253 {
+        Ok(())
+    } else if symbology == Symbology::Codabar {
+        Err(ServiceError::InvalidCommand(format!(
+            "'{}' is not valid Codabar barcode data (it starts and ends with A, B, C or D, e.g. A123456B)", data)))
+    } else {
+        Err(ServiceError::InvalidCommand(format!("'{}' is not valid {:?} barcode data", data, symbology)))
+    }
+}
+
+/// Checks that `data` fits in a QR code (version 40, byte mode).
+pub fn validate_qr(data: &str, error_correction: QrErrorCorrection) -> Result<(), ServiceError> {
+    let capacity = match error_correction {
+        QrErrorCorrection::L => 2953,
+        QrErrorCorrection::M => 2331,
+        QrErrorCorrection::Q => 1663,
+        QrErrorCorrection::H => 1273,
+    };
+    if data.is_empty() {
+        return Err(ServiceError::InvalidCommand("QR code data can't be empty".into()));
+    }
+    if data.len() > capacity {
+        return Err(ServiceError::InvalidCommand(format!(
+            "QR code data is too long: {} bytes, at most {} with error correction {:?}", data.len(), capacity, error_correction)));
+    }
+    Ok(())
+}
+
+/// Renders a QR code as a bitmap, for printers that can't draw QR codes themselves.
+/// Each module becomes a `module_size` x `module_size` square, with the standard
+/// 4-module white border around the code. The modules are made smaller if
+/// the code would be wider than `max_width` dots.
+pub fn qr_bitmap(data: &str, error_correction: QrErrorCorrection, module_size: usize, max_width: usize) -> Result<Bitmap, ServiceError> {
+    let level = match error_correction {
+        QrErrorCorrection::L => qrcode::EcLevel::L,
+        QrErrorCorrection::M => qrcode::EcLevel::M,
+        QrErrorCorrection::Q => qrcode::EcLevel::Q,
+        QrErrorCorrection::H => qrcode::EcLevel::H,
+    };
+    let code = qrcode::QrCode::with_error_correction_level(data.as_bytes(), level)
+        .map_err(|e| ServiceError::InvalidCommand(format!("Cannot build QR code: {}", e)))?;
+
+    const QUIET_ZONE: usize = 4;
+    let modules = code.width();
+    let module_size = module_size.min(max_width / (modules + 2 * QUIET_ZONE));
+    if module_size == 0 {
+        return Err(ServiceError::InvalidCommand(format!(
+            "QR code is too big for the paper ({} modules, {} dots): use less data", modules + 2 * QUIET_ZONE, max_width)));
+    }
+    let size = (modules + 2 * QUIET_ZONE) * module_size;
+    let colors = code.to_colors();
+
+    let mut bitmap = Bitmap::new(size, size);
+    for y in 0..size {
+        for x in 0..size {
+            let mx = (x / module_size).checked_sub(QUIET_ZONE);
+            let my = (y / module_size).checked_sub(QUIET_ZONE);
+            if let (Some(mx), Some(my)) = (mx, my) {
+                if mx < modules && my < modules {
+                    bitmap.set(x, y, colors[my * modules + mx] == qrcode::Color::Dark);
+                }
+            }
+        }
+    }
+    Ok(bitmap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_valid_barcodes() {
+        let valid = [
+            (Symbology::Ean13, "400638133393"),
+            (Symbology::Ean13, "4006381333931"),
+            (Symbology::Ean8, "9638507"),
+            (Symbology::UpcA, "03600029145"),
+            (Symbology::Itf, "1234"),
+            (Symbology::Code39, "ABC-12 $%"),
+            (Symbology::Codabar, "A12$3.4:5B"),
+            (Symbology::Codabar, "c1d"),
+            (Symbology::Code128, "Order {42}"),
+        ];
+        for (symbology, data) in valid {
+            assert!(validate(symbology, data).is_ok(), "{:?} {}", symbology, data);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_barcodes() {
+        let invalid = [
+            (Symbology::Ean13, "40063813339"),
+            (Symbology::Ean13, "40063813339X"),
+            (Symbology::Ean8, "123"),
+            (Symbology::UpcA, ""),
+            (Symbology::Itf, "123"),
+            (Symbology::Code39, "abc"),
+            (Symbology::Codabar, "AB"),
+            (Symbology::Codabar, "123456"),
+            (Symbology::Codabar, "A12X4B"),
+            (Symbology::Code128, "Café"),
+            (Symbology::Code128, ""),
+        ];
+        for (symbology, data) in invalid {
+            assert!(validate(symbology, data).is_err(), "{:?} {}", symbology, data);
+        }
+    }
+
+    #[test]
+    fn code128_length_counts_escaped_braces() {
+        // GS k sends at most 255 bytes: "{B" + the data, with "{" sent as "{{"
+        assert!(validate(Symbology::Code128, &"a".repeat(253)).is_ok());
+        assert!(validate(Symbology::Code128, &"a".repeat(254)).is_err());
+        assert!(validate(Symbology::Code128, &"{".repeat(126)).is_ok());
+        assert!(validate(Symbology::Code128, &"{".repeat(127)).is_err());
+    }
+
+    #[test]
+    fn qr_capacity_depends_on_error_correction() {
+        assert!(validate_qr("", QrErrorCorrection::L).is_err());
+        assert!(validate_qr(&"x".repeat(2953), QrErrorCorrection::L).is_ok());
+        assert!(validate_qr(&"x".repeat(2954), QrErrorCorrection::L).is_err());
+        assert!(validate_qr(&"x".repeat(2331), QrErrorCorrection::M).is_ok());
+        assert!(validate_qr(&"x".repeat(2332), QrErrorCorrection::M).is_err());
+        assert!(validate_qr(&"x".repeat(1663), QrErrorCorrection::Q).is_ok());
+        assert!(validate_qr(&"x".repeat(1273), QrErrorCorrection::H).is_ok());
+        assert!(validate_qr(&"x".repeat(1274), QrErrorCorrection::H).is_err());
+    }
+
+    #[test]
+    fn qr_bitmap_fits_the_paper() {
+        // "hi" is a version 1 code: 21 modules + 2 x 4 for the white border
+        let bitmap = qr_bitmap("hi", QrErrorCorrection::M, 4, 384).unwrap();
+        assert_eq!((bitmap.width, bitmap.height), (116, 116));
+        // Border white, finder pattern in the top-left corner black
+        assert!(!bitmap.get(15, 15));
+        assert!(bitmap.get(16, 16));
+
+        // Smaller modules on narrow paper
+        assert_eq!(qr_bitmap("hi", QrErrorCorrection::M, 4, 100).unwrap().width, 87);
+        assert!(qr_bitmap("hi", QrErrorCorrection::M, 4, 28).is_err());
+    }
+}
@@ -0,0 +1,661 @@
+use serde::Deserialize;
+
+// =========================================================================
+// CODE PAGES
+// =========================================================================
+// Thermal printers don't understand UTF-8. They print single bytes using a
+// "code page": bytes 0x00-0x7F are plain ASCII, and bytes 0x80-0xFF map to a
+// table of accented letters, symbols or box-drawing characters that depends
+// on which page is selected (ESC t n).
+//
+// The encoder converts our UTF-8 strings into bytes of the printer's code page.
+// Characters the page doesn't have are transliterated ("Ł" -> "L", "€" -> "EUR")
+// instead of being printed as garbage.
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CodePage {
+    #[default]
+    Cp437,      // USA, Standard Europe (printer power-on default)
+    Cp850,      // Multilingual Latin 1
+    Cp852,      // Latin 2 (Central Europe)
+    Cp858,      // CP850 + Euro sign
+    Cp860,      // Portuguese
+    Cp863,      // Canadian-French
+    Cp865,      // Nordic
+    Cp866,      // Cyrillic #2
+    Cp1250,     // Windows Central Europe
+    Cp1251,     // Windows Cyrillic
+    Cp1252,     // Windows Latin 1
+    Iso8859_1,  // Latin 1
+    Iso8859_2,  // Latin 2
+    Iso8859_7,  // Greek
+    Iso8859_15, // Latin 9 (Latin 1 + Euro sign)
+}
+
+impl CodePage {
+    /// Parses a code page name from config.toml.
+    /// Accepts the common spellings: "cp858", "PC858", "858", "windows-1252", "iso-8859-15"...
+    pub fn from_name(name: &str) -> Option<Self> {
+        let normalized: String = name
+            .to_ascii_lowercase()
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect();
+        let number = normalized
+            .trim_start_matches("windows")
+            .trim_start_matches("wpc")
+            .trim_start_matches("cp")
+            .trim_start_matches("pc");
+
+        let page = match number {
+            "437" => CodePage::Cp437,
+            "850" => CodePage::Cp850,
+            "852" => CodePage::Cp852,
+            "858" => CodePage::Cp858,
+            "860" => CodePage::Cp860,
+            "863" => CodePage::Cp863,
+            "865" => CodePage::Cp865,
+            "866" => CodePage::Cp866,
+            "1250" => CodePage::Cp1250,
+            "1251" => CodePage::Cp1251,
+            "1252" => CodePage::Cp1252,
+            "iso88591" | "latin1" => CodePage::Iso8859_1,
+            "iso88592" | "latin2" => CodePage::Iso8859_2,
+            "iso88597" | "greek" => CodePage::Iso8859_7,
+            "iso885915" | "latin9" => CodePage::Iso8859_15,
+            _ => return None,
+        };
+        Some(page)
+    }
+
+    /// The `n` of the Epson `ESC t n` "Select character code table" command.
+    pub fn escpos_table(&self) -> u8 {
+        match self {
+            CodePage::Cp437 => 0,
+            CodePage::Cp850 => 2,
+            CodePage::Cp860 => 3,
+            CodePage::Cp863 => 4,
+            CodePage::Cp865 => 5,
+            // Epson has no ISO-8859-1 table, but its printable characters
+            // (0xA0-0xFF) are the same bytes in Windows-1252
+            CodePage::Cp1252 | CodePage::Iso8859_1 => 16,
+            CodePage::Cp866 => 17,
+            CodePage::Cp852 => 18,
+            CodePage::Cp858 => 19,
+            CodePage::Iso8859_7 => 15,
+            CodePage::Iso8859_2 => 39,
+            CodePage::Iso8859_15 => 40,
+            CodePage::Cp1250 => 45,
+            CodePage::Cp1251 => 46,
+        }
+    }
+
+    /// The `n` of the Star `ESC GS t n` command, if Star printers have this page.
+    pub fn star_table(&self) -> Option<u8> {
+        match self {
+            CodePage::Cp437 => Some(1),
+            // Star only has CP858, which is CP850 with "€" instead of "ı"
+            CodePage::Cp850 | CodePage::Cp858 => Some(4),
+            CodePage::Cp852 => Some(5),
+            CodePage::Cp860 => Some(6),
+            CodePage::Cp863 => Some(8),
+            CodePage::Cp865 => Some(9),
+            CodePage::Cp866 => Some(10),
+            CodePage::Cp1252 | CodePage::Iso8859_1 => Some(32),
+            CodePage::Cp1250 => Some(33),
+            CodePage::Cp1251 => Some(34),
+            CodePage::Iso8859_2 | CodePage::Iso8859_7 | CodePage::Iso8859_15 => None,
+        }
+    }
+
+    /// Converts UTF-8 text into bytes of this code page.
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(text.len());
+        for c in text.chars() {
+            self.encode_char(c, &mut bytes);
+        }
+        bytes
+    }
+
+    /// Replaces the characters this code page doesn't have, the same way
+    /// `encode` does ("€" -> "EUR" on CP437). The result has one character
+    /// per printed byte, so it is what line widths must be counted on.
+    pub fn transliterate(&self, text: &str) -> String {
+        self.encode(text)
+            .into_iter()
+            .map(|b| if b < 0x80 { b as char } else { self.upper_half()[(b - 0x80) as usize] })
+            .collect()
+    }
+
+    fn encode_char(&self, c: char, bytes: &mut Vec<u8>) {
+        // 1. Plain ASCII is the same in every code page
+        if c.is_ascii() {
+            bytes.push(c as u8);
+            return;
+        }
+
+        // 2. Look the character up in the upper half of the table
+        if let Some(index) = self.upper_half().iter().position(|&t| t == c && t != UNMAPPED) {
+            bytes.push(0x80 + index as u8);
+            return;
+        }
+
+        // 3. Not available: fall back to a close ASCII look-alike
+        match transliterate(c) {
+            Some(replacement) => {
+                for r in replacement.chars() {
+                    self.encode_char(r, bytes);
+                }
+            }
+            None => bytes.push(b'?'),
+        }
+    }
+
+    fn upper_half(&self) -> &'static [char; 128] {
+        match self {
+            CodePage::Cp437 => &CP437,
+            CodePage::Cp850 => &CP850,
+            CodePage::Cp852 => &CP852,
+            CodePage::Cp858 => &CP858,
+            CodePage::Cp860 => &CP860,
+            CodePage::Cp863 => &CP863,
+            CodePage::Cp865 => &CP865,
+            CodePage::Cp866 => &CP866,
+            CodePage::Cp1250 => &CP1250,
+            CodePage::Cp1251 => &CP1251,
+            CodePage::Cp1252 => &CP1252,
+            CodePage::Iso8859_1 => &ISO_8859_1,
+            CodePage::Iso8859_2 => &ISO_8859_2,
+            CodePage::Iso8859_7 => &ISO_8859_7,
+            CodePage::Iso8859_15 => &ISO_8859_15,
+        }
+    }
+}
+
+// Returns an ASCII replacement for characters that are missing from a code page.
+// Replacements only contain ASCII, so they can always be printed.
+fn transliterate(c: char) -> Option<&'static str> {
+    let replacement = match c {
+        // Accented Latin letters -> base letter
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' | 'Ā' | 'Ă' | 'Ą' => "A",
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+        'Ç' | 'Ć' | 'Ĉ' | 'Ċ' | 'Č' => "C",
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+        'Ď' => "D",
+        'ď' => "d",
+        'È' | 'É' | 'Ê' | 'Ë' | 'Ē' | 'Ĕ' | 'Ė' | 'Ę' | 'Ě' => "E",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+        'Ĝ' | 'Ğ' | 'Ġ' | 'Ģ' => "G",
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+        'Ĥ' => "H",
+        'ĥ' => "h",
+        'Ì' | 'Í' | 'Î' | 'Ï' | 'Ĩ' | 'Ī' | 'Ĭ' | 'Į' | 'İ' => "I",
+        'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' => "i",
+        'Ĵ' => "J",
+        'ĵ' => "j",
+        'Ķ' => "K",
+        'ķ' => "k",
+        'Ĺ' | 'Ļ' | 'Ľ' => "L",
+        'ĺ' | 'ļ' | 'ľ' => "l",
+        'Ñ' | 'Ń' | 'Ņ' | 'Ň' => "N",
+        'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ō' | 'Ŏ' | 'Ő' => "O",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ō' | 'ŏ' | 'ő' => "o",
+        'Ŕ' | 'Ŗ' | 'Ř' => "R",
+        'ŕ' | 'ŗ' | 'ř' => "r",
+        'Ś' | 'Ŝ' | 'Ş' | 'Š' | 'Ș' => "S",
+        'ś' | 'ŝ' | 'ş' | 'š' | 'ș' => "s",
+        'Ţ' | 'Ť' | 'Ț' => "T",
+        'ţ' | 'ť' | 'ț' => "t",
+        'Ù' | 'Ú' | 'Û' | 'Ü' | 'Ũ' | 'Ū' | 'Ŭ' | 'Ů' | 'Ű' | 'Ų' => "U",
+        'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+        'Ŵ' => "W",
+        'ŵ' => "w",
+        'Ý' | 'Ŷ' | 'Ÿ' => "Y",
+        'ý' | 'ÿ' | 'ŷ' => "y",
+        'Ź' | 'Ż' | 'Ž' => "Z",
+        'ź' | 'ż' | 'ž' => "z",
+
+        // Letters without a decomposition
+        'Đ' | 'Ð' => "D",
+        'đ' | 'ð' => "d",
+        'Ł' => "L",
+        'ł' => "l",
+        'Ø' => "O",
+        'ø' => "o",
+        'Þ' => "Th",
+        'þ' => "th",
+        'ß' => "ss",
+        'Æ' => "AE",
+        'æ' => "ae",
+        'Œ' => "OE",
+        'œ' => "oe",
+        'ı' => "i",
+
+        // Currency and symbols
+        '€' => "EUR",
+        '£' => "GBP",
+        '¥' => "JPY",
+        '¢' => "c",
+        '©' => "(C)",
+        '®' => "(R)",
+        '™' => "TM",
+        '°' => "o",
+        '±' => "+/-",
+        '×' => "x",
+        '÷' => "/",
+        '½' => "1/2",
+        '¼' => "1/4",
+        '¾' => "3/4",
+        '•' | '·' | '∙' => "*",
+        '…' => "...",
+
+        // Typographic punctuation
+        '\u{a0}' | '\u{2007}' | '\u{2009}' | '\u{202f}' => " ",
+        '‘' | '’' | '‚' | '‛' | '′' => "'",
+        '“' | '”' | '„' | '‟' | '″' | '«' | '»' => "\"",
+        '‹' => "<",
+        '›' => ">",
+        '‐' | '‑' | '‒' | '–' | '—' | '―' | '\u{ad}' => "-",
+
+        // Box drawing -> ASCII art
+        '─' | '━' | '┄' | '┅' | '┈' | '┉' => "-",
+        '═' => "=",
+        '│' | '┃' | '┆' | '┇' | '┊' | '┋' | '║' => "|",
+        '┌' | '┐' | '└' | '┘' | '├' | '┤' | '┬' | '┴' | '┼' |
+        '╔' | '╗' | '╚' | '╝' | '╠' | '╣' | '╦' | '╩' | '╬' => "+",
+        '░' | '▒' | '▓' | '█' | '■' => "#",
+
+        // Cyrillic -> Latin (for printers without a Cyrillic code page)
+        'А' => "A", 'Б' => "B", 'В' => "V", 'Г' => "G", 'Д' => "D", 'Е' => "E", 'Ё' => "E",
+        'Ж' => "Zh", 'З' => "Z", 'И' => "I", 'Й' => "Y", 'К' => "K", 'Л' => "L", 'М' => "M",
+        'Н' => "N", 'О' => "O", 'П' => "P", 'Р' => "R", 'С' => "S", 'Т' => "T", 'У' => "U",
+        'Ф' => "F", 'Х' => "Kh", 'Ц' => "Ts", 'Ч' => "Ch", 'Ш' => "Sh", 'Щ' => "Shch", 'Ъ' => "",
+        'Ы' => "Y", 'Ь' => "", 'Э' => "E", 'Ю' => "Yu", 'Я' => "Ya",
+        'а' => "a", 'б' => "b", 'в' => "v", 'г' => "g", 'д' => "d", 'е' => "e", 'ё' => "e",
+        'ж' => "zh", 'з' => "z", 'и' => "i", 'й' => "y", 'к' => "k", 'л' => "l", 'м' => "m",
+        'н' => "n", 'о' => "o", 'п' => "p", 'р' => "r", 'с' => "s", 'т' => "t", 'у' => "u",
+        'ф' => "f", 'х' => "kh", 'ц' => "ts", 'ч' => "ch", 'ш' => "sh", 'щ' => "shch", 'ъ' => "",
+        'ы' => "y", 'ь' => "", 'э' => "e", 'ю' => "yu", 'я' => "ya",
+
+        _ => return None,
+    };
+    Some(replacement)
+}
+
+// =========================================================================
+// CODE PAGE TABLES
+// =========================================================================
+// Characters for bytes 0x80-0xFF of each code page.
+// Bytes that are undefined (or C1 control codes) are marked UNMAPPED.
+
+const UNMAPPED: char = '\u{fffd}';
+
+const CP437: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç',
+    'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù',
+    'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º',
+    '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖',
+    '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟',
+    '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫',
+    '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ',
+    'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈',
+    '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+const CP850: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç',
+    'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù',
+    'ÿ', 'Ö', 'Ü', 'ø', '£', 'Ø', '×', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º',
+    '¿', '®', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', 'Á', 'Â', 'À',
+    '©', '╣', '║', '╗', '╝', '¢', '¥', '┐',
+    '└', '┴', '┬', '├', '─', '┼', 'ã', 'Ã',
+    '╚', '╔', '╩', '╦', '╠', '═', '╬', '¤',
+    'ð', 'Ð', 'Ê', 'Ë', 'È', 'ı', 'Í', 'Î',
+    'Ï', '┘', '┌', '█', '▄', '¦', 'Ì', '▀',
+    'Ó', 'ß', 'Ô', 'Ò', 'õ', 'Õ', 'µ', 'þ',
+    'Þ', 'Ú', 'Û', 'Ù', 'ý', 'Ý', '¯', '´',
+    '\u{ad}', '±', '‗', '¾', '¶', '§', '÷', '¸',
+    '°', '¨', '·', '¹', '³', '²', '■', '\u{a0}',
+];
+
+const CP852: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'ů', 'ć', 'ç',
+    'ł', 'ë', 'Ő', 'ő', 'î', 'Ź', 'Ä', 'Ć',
+    'É', 'Ĺ', 'ĺ', 'ô', 'ö', 'Ľ', 'ľ', 'Ś',
+    'ś', 'Ö', 'Ü', 'Ť', 'ť', 'Ł', '×', 'č',
+    'á', 'í', 'ó', 'ú', 'Ą', 'ą', 'Ž', 'ž',
+    'Ę', 'ę', '¬', 'ź', 'Č', 'ş', '«', '»',
+    '░', '▒', '▓', '│', '┤', 'Á', 'Â', 'Ě',
+    'Ş', '╣', '║', '╗', '╝', 'Ż', 'ż', '┐',
+    '└', '┴', '┬', '├', '─', '┼', 'Ă', 'ă',
+    '╚', '╔', '╩', '╦', '╠', '═', '╬', '¤',
+    'đ', 'Đ', 'Ď', 'Ë', 'ď', 'Ň', 'Í', 'Î',
+    'ě', '┘', '┌', '█', '▄', 'Ţ', 'Ů', '▀',
+    'Ó', 'ß', 'Ô', 'Ń', 'ń', 'ň', 'Š', 'š',
+    'Ŕ', 'Ú', 'ŕ', 'Ű', 'ý', 'Ý', 'ţ', '´',
+    '\u{ad}', '˝', '˛', 'ˇ', '˘', '§', '÷', '¸',
+    '°', '¨', '˙', 'ű', 'Ř', 'ř', '■', '\u{a0}',
+];
+
+const CP858: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç',
+    'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù',
+    'ÿ', 'Ö', 'Ü', 'ø', '£', 'Ø', '×', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º',
+    '¿', '®', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', 'Á', 'Â', 'À',
+    '©', '╣', '║', '╗', '╝', '¢', '¥', '┐',
+    '└', '┴', '┬', '├', '─', '┼', 'ã', 'Ã',
+    '╚', '╔', '╩', '╦', '╠', '═', '╬', '¤',
+    'ð', 'Ð', 'Ê', 'Ë', 'È', '€', 'Í', 'Î',
+    'Ï', '┘', '┌', '█', '▄', '¦', 'Ì', '▀',
+    'Ó', 'ß', 'Ô', 'Ò', 'õ', 'Õ', 'µ', 'þ',
+    'Þ', 'Ú', 'Û', 'Ù', 'ý', 'Ý', '¯', '´',
+    '\u{ad}', '±', '‗', '¾', '¶', '§', '÷', '¸',
+    '°', '¨', '·', '¹', '³', '²', '■', '\u{a0}',
+];
+
+const CP860: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ã', 'à', 'Á', 'ç',
+    'ê', 'Ê', 'è', 'Í', 'Ô', 'ì', 'Ã', 'Â',
+    'É', 'À', 'È', 'ô', 'õ', 'ò', 'Ú', 'ù',
+    'Ì', 'Õ', 'Ü', '¢', '£', 'Ù', '₧', 'Ó',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º',
+    '¿', 'Ò', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖',
+    '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟',
+    '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫',
+    '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ',
+    'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈',
+    '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+const CP863: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'Â', 'à', '¶', 'ç',
+    'ê', 'ë', 'è', 'ï', 'î', '‗', 'À', '§',
+    'É', 'È', 'Ê', 'ô', 'Ë', 'Ï', 'û', 'ù',
+    '¤', 'Ô', 'Ü', '¢', '£', 'Ù', 'Û', 'ƒ',
+    '¦', '´', 'ó', 'ú', '¨', '¸', '³', '¯',
+    'Î', '⌐', '¬', '½', '¼', '¾', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖',
+    '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟',
+    '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫',
+    '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ',
+    'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈',
+    '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+const CP865: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç',
+    'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù',
+    'ÿ', 'Ö', 'Ü', 'ø', '£', 'Ø', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º',
+    '¿', '⌐', '¬', '½', '¼', '¡', '«', '¤',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖',
+    '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟',
+    '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫',
+    '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ',
+    'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈',
+    '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+const CP866: [char; 128] = [
+    'А', 'Б', 'В', 'Г', 'Д', 'Е', 'Ж', 'З',
+    'И', 'Й', 'К', 'Л', 'М', 'Н', 'О', 'П',
+    'Р', 'С', 'Т', 'У', 'Ф', 'Х', 'Ц', 'Ч',
+    'Ш', 'Щ', 'Ъ', 'Ы', 'Ь', 'Э', 'Ю', 'Я',
+    'а', 'б', 'в', 'г', 'д', 'е', 'ж', 'з',
+    'и', 'й', 'к', 'л', 'м', 'н', 'о', 'п',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖',
+    '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟',
+    '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫',
+    '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'р', 'с', 'т', 'у', 'ф', 'х', 'ц', 'ч',
+    'ш', 'щ', 'ъ', 'ы', 'ь', 'э', 'ю', 'я',
+    'Ё', 'ё', 'Є', 'є', 'Ї', 'ї', 'Ў', 'ў',
+    '°', '∙', '·', '√', '№', '¤', '■', '\u{a0}',
+];
+
+const CP1250: [char; 128] = [
+    '€', UNMAPPED, '‚', UNMAPPED, '„', '…', '†', '‡',
+    UNMAPPED, '‰', 'Š', '‹', 'Ś', 'Ť', 'Ž', 'Ź',
+    UNMAPPED, '‘', '’', '“', '”', '•', '–', '—',
+    UNMAPPED, '™', 'š', '›', 'ś', 'ť', 'ž', 'ź',
+    '\u{a0}', 'ˇ', '˘', 'Ł', '¤', 'Ą', '¦', '§',
+    '¨', '©', 'Ş', '«', '¬', '\u{ad}', '®', 'Ż',
+    '°', '±', '˛', 'ł', '´', 'µ', '¶', '·',
+    '¸', 'ą', 'ş', '»', 'Ľ', '˝', 'ľ', 'ż',
+    'Ŕ', 'Á', 'Â', 'Ă', 'Ä', 'Ĺ', 'Ć', 'Ç',
+    'Č', 'É', 'Ę', 'Ë', 'Ě', 'Í', 'Î', 'Ď',
+    'Đ', 'Ń', 'Ň', 'Ó', 'Ô', 'Ő', 'Ö', '×',
+    'Ř', 'Ů', 'Ú', 'Ű', 'Ü', 'Ý', 'Ţ', 'ß',
+    'ŕ', 'á', 'â', 'ă', 'ä', 'ĺ', 'ć', 'ç',
+    'č', 'é', 'ę', 'ë', 'ě', 'í', 'î', 'ď',
+    'đ', 'ń', 'ň', 'ó', 'ô', 'ő', 'ö', '÷',
+    'ř', 'ů', 'ú', 'ű', 'ü', 'ý', 'ţ', '˙',
+];
+
+const CP1251: [char; 128] = [
+    'Ђ', 'Ѓ', '‚', 'ѓ', '„', '…', '†', '‡',
+    '€', '‰', 'Љ', '‹', 'Њ', 'Ќ', 'Ћ', 'Џ',
+    'ђ', '‘', '’', '“', '”', '•', '–', '—',
+    UNMAPPED, '™', 'љ', '›', 'њ', 'ќ', 'ћ', 'џ',
+    '\u{a0}', 'Ў', 'ў', 'Ј', '¤', 'Ґ', '¦', '§',
+    'Ё', '©', 'Є', '«', '¬', '\u{ad}', '®', 'Ї',
+    '°', '±', 'І', 'і', 'ґ', 'µ', '¶', '·',
+    'ё', '№', 'є', '»', 'ј', 'Ѕ', 'ѕ', 'ї',
+    'А', 'Б', 'В', 'Г', 'Д', 'Е', 'Ж', 'З',
+    'И', 'Й', 'К', 'Л', 'М', 'Н', 'О', 'П',
+    'Р', 'С', 'Т', 'У', 'Ф', 'Х', 'Ц', 'Ч',
+    'Ш', 'Щ', 'Ъ', 'Ы', 'Ь', 'Э', 'Ю', 'Я',
+    'а', 'б', 'в', 'г', 'д', 'е', 'ж', 'з',
+    'и', 'й', 'к', 'л', 'м', 'н', 'о', 'п',
+    'р', 'с', 'т', 'у', 'ф', 'х', 'ц', 'ч',
+    'ш', 'щ', 'ъ', 'ы', 'ь', 'э', 'ю', 'я',
+];
+
+const CP1252: [char; 128] = [
+    '€', UNMAPPED, '‚', 'ƒ', '„', '…', '†', '‡',
+    'ˆ', '‰', 'Š', '‹', 'Œ', UNMAPPED, 'Ž', UNMAPPED,
+    UNMAPPED, '‘', '’', '“', '”', '•', '–', '—',
+    '˜', '™', 'š', '›', 'œ', UNMAPPED, 'ž', 'Ÿ',
+    '\u{a0}', '¡', '¢', '£', '¤', '¥', '¦', '§',
+    '¨', '©', 'ª', '«', '¬', '\u{ad}', '®', '¯',
+    '°', '±', '²', '³', '´', 'µ', '¶', '·',
+    '¸', '¹', 'º', '»', '¼', '½', '¾', '¿',
+    'À', 'Á', 'Â', 'Ã', 'Ä', 'Å', 'Æ', 'Ç',
+    'È', 'É', 'Ê', 'Ë', 'Ì', 'Í', 'Î', 'Ï',
+    'Ð', 'Ñ', 'Ò', 'Ó', 'Ô', 'Õ', 'Ö', '×',
+    'Ø', 'Ù', 'Ú', 'Û', 'Ü', 'Ý', 'Þ', 'ß',
+    'à', 'á', 'â', 'ã', 'ä', 'å', 'æ', 'ç',
+    'è', 'é', 'ê', 'ë', 'ì', 'í', 'î', 'ï',
+    'ð', 'ñ', 'ò', 'ó', 'ô', 'õ', 'ö', '÷',
+    'ø', 'ù', 'ú', 'û', 'ü', 'ý', 'þ', 'ÿ',
+];
+
+const ISO_8859_1: [char; 128] = [
+    UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED,
+    UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED,
+    UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED,
+    UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED,
+    '\u{a0}', '¡', '¢', '£', '¤', '¥', '¦', '§',
+    '¨', '©', 'ª', '«', '¬', '\u{ad}', '®', '¯',
+    '°', '±', '²', '³', '´', 'µ', '¶', '·',
+    '¸', '¹', 'º', '»', '¼', '½', '¾', '¿',
+    'À', 'Á', 'Â', 'Ã', 'Ä', 'Å', 'Æ', 'Ç',
+    'È', 'É', 'Ê', 'Ë', 'Ì', 'Í', 'Î', 'Ï',
+    'Ð', 'Ñ', 'Ò', 'Ó', 'Ô', 'Õ', 'Ö', '×',
+    'Ø', 'Ù', 'Ú', 'Û', 'Ü', 'Ý', 'Þ', 'ß',
+    'à', 'á', 'â', 'ã', 'ä', 'å', 'æ', 'ç',
+    'è', 'é', 'ê', 'ë', 'ì', 'í', 'î', 'ï',
+    'ð', 'ñ', 'ò', 'ó', 'ô', 'õ', 'ö', '÷',
+    'ø', 'ù', 'ú', 'û', 'ü', 'ý', 'þ', 'ÿ',
+];
+
+const ISO_8859_2: [char; 128] = [
+    UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED,
+    UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED,
+    UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED,
+    UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED,
+    '\u{a0}', 'Ą', '˘', 'Ł', '¤', 'Ľ', 'Ś', '§',
+    '¨', 'Š', 'Ş', 'Ť', 'Ź', '\u{ad}', 'Ž', 'Ż',
+    '°', 'ą', '˛', 'ł', '´', 'ľ', 'ś', 'ˇ',
+    '¸', 'š', 'ş', 'ť', 'ź', '˝', 'ž', 'ż',
+    'Ŕ', 'Á', 'Â', 'Ă', 'Ä', 'Ĺ', 'Ć', 'Ç',
+    'Č', 'É', 'Ę', 'Ë', 'Ě', 'Í', 'Î', 'Ď',
+    'Đ', 'Ń', 'Ň', 'Ó', 'Ô', 'Ő', 'Ö', '×',
+    'Ř', 'Ů', 'Ú', 'Ű', 'Ü', 'Ý', 'Ţ', 'ß',
+    'ŕ', 'á', 'â', 'ă', 'ä', 'ĺ', 'ć', 'ç',
+    'č', 'é', 'ę', 'ë', 'ě', 'í', 'î', 'ď',
+    'đ', 'ń', 'ň', 'ó', 'ô', 'ő', 'ö', '÷',
+    'ř', 'ů', 'ú', 'ű', 'ü', 'ý', 'ţ', '˙',
+];
+
+const ISO_8859_7: [char; 128] = [
+    UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED,
+    UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED,
+    UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED,
+    UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED,
+    '\u{a0}', '‘', '’', '£', '€', '₯', '¦', '§',
+    '¨', '©', 'ͺ', '«', '¬', '\u{ad}', UNMAPPED, '―',
+    '°', '±', '²', '³', '΄', '΅', 'Ά', '·',
+    'Έ', 'Ή', 'Ί', '»', 'Ό', '½', 'Ύ', 'Ώ',
+    'ΐ', 'Α', 'Β', 'Γ', 'Δ', 'Ε', 'Ζ', 'Η',
+    'Θ', 'Ι', 'Κ', 'Λ', 'Μ', 'Ν', 'Ξ', 'Ο',
+    'Π', 'Ρ', UNMAPPED, 'Σ', 'Τ', 'Υ', 'Φ', 'Χ',
+    'Ψ', 'Ω', 'Ϊ', 'Ϋ', 'ά', 'έ', 'ή', 'ί',
+    'ΰ', 'α', 'β', 'γ', 'δ', 'ε', 'ζ', 'η',
+    'θ', 'ι', 'κ', 'λ', 'μ', 'ν', 'ξ', 'ο',
+    'π', 'ρ', 'ς', 'σ', 'τ', 'υ', 'φ', 'χ',
+    'ψ', 'ω', 'ϊ', 'ϋ', 'ό', 'ύ', 'ώ', UNMAPPED,
+];
+
+const ISO_8859_15: [char; 128] = [
+    UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED,
+    UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED,
+    UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED,
+    UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED, UNMAPPED,
+    '\u{a0}', '¡', '¢', '£', '€', '¥', 'Š', '§',
+    'š', '©', 'ª', '«', '¬', '\u{ad}', '®', '¯',
+    '°', '±', '²', '³', 'Ž', 'µ', '¶', '·',
+    'ž', '¹', 'º', '»', 'Œ', 'œ', 'Ÿ', '¿',
+    'À', 'Á', 'Â', 'Ã', 'Ä', 'Å', 'Æ', 'Ç',
+    'È', 'É', 'Ê', 'Ë', 'Ì', 'Í', 'Î', 'Ï',
+    'Ð', 'Ñ', 'Ò', 'Ó', 'Ô', 'Õ', 'Ö', '×',
+    'Ø', 'Ù', 'Ú', 'Û', 'Ü', 'Ý', 'Þ', 'ß',
+    'à', 'á', 'â', 'ã', 'ä', 'å', 'æ', 'ç',
+    'è', 'é', 'ê', 'ë', 'ì', 'í', 'î', 'ï',
+    'ð', 'ñ', 'ò', 'ó', 'ô', 'õ', 'ö', '÷',
+    'ø', 'ù', 'ú', 'û', 'ü', 'ý', 'þ', 'ÿ',
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [CodePage; 15] = [
+        CodePage::Cp437, CodePage::Cp850, CodePage::Cp852, CodePage::Cp858, CodePage::Cp860,
+        CodePage::Cp863, CodePage::Cp865, CodePage::Cp866, CodePage::Cp1250, CodePage::Cp1251,
+        CodePage::Cp1252, CodePage::Iso8859_1, CodePage::Iso8859_2, CodePage::Iso8859_7, CodePage::Iso8859_15,
+    ];
+
+    #[test]
+    fn parses_common_names() {
+        assert_eq!(CodePage::from_name("cp858"), Some(CodePage::Cp858));
+        assert_eq!(CodePage::from_name("PC858"), Some(CodePage::Cp858));
+        assert_eq!(CodePage::from_name("858"), Some(CodePage::Cp858));
+        assert_eq!(CodePage::from_name("windows-1252"), Some(CodePage::Cp1252));
+        assert_eq!(CodePage::from_name("WPC1250"), Some(CodePage::Cp1250));
+        assert_eq!(CodePage::from_name("ISO-8859-1"), Some(CodePage::Iso8859_1));
+        assert_eq!(CodePage::from_name("latin1"), Some(CodePage::Iso8859_1));
+        assert_eq!(CodePage::from_name("iso_8859_15"), Some(CodePage::Iso8859_15));
+        assert_eq!(CodePage::from_name("utf-8"), None);
+        assert_eq!(CodePage::from_name(""), None);
+    }
+
+    #[test]
+    fn ascii_is_unchanged() {
+        for page in ALL {
+            assert_eq!(page.encode("Total: 12.50 $\n"), b"Total: 12.50 $\n");
+        }
+    }
+
+    #[test]
+    fn uses_the_table_of_the_page() {
+        assert_eq!(CodePage::Cp437.encode("é"), [0x82]);
+        assert_eq!(CodePage::Cp858.encode("€"), [0xD5]);
+        assert_eq!(CodePage::Cp1252.encode("€é"), [0x80, 0xE9]);
+        assert_eq!(CodePage::Iso8859_1.encode("éÿ"), [0xE9, 0xFF]);
+        assert_eq!(CodePage::Iso8859_15.encode("€"), [0xA4]);
+        assert_eq!(CodePage::Iso8859_2.encode("Łódź"), [0xA3, 0xF3, 0x64, 0xBC]);
+        assert_eq!(CodePage::Cp866.encode("Дом"), [0x84, 0xAE, 0xAC]);
+        assert_eq!(CodePage::Iso8859_7.encode("Ωμέγα"), [0xD9, 0xEC, 0xDD, 0xE3, 0xE1]);
+    }
+
+    #[test]
+    fn transliterates_missing_characters() {
+        assert_eq!(CodePage::Cp437.encode("5 €"), b"5 EUR");
+        assert_eq!(CodePage::Cp437.encode("Łódź"), [b'L', 0xA2, b'd', b'z']);
+        assert_eq!(CodePage::Cp1252.encode("Дом"), b"Dom");
+        assert_eq!(CodePage::Iso8859_1.encode("“Œuvre” – 3…"), b"\"OEuvre\" - 3...");
+        // Nothing close in ASCII
+        assert_eq!(CodePage::Cp437.encode("中"), b"?");
+    }
+
+    #[test]
+    fn never_prints_undefined_bytes() {
+        // U+FFFD marks the holes of the tables, it must not match them
+        assert_eq!(CodePage::Cp1252.encode("\u{fffd}"), b"?");
+        assert_eq!(CodePage::Iso8859_1.encode("\u{85}"), b"?");
+    }
+
+    #[test]
+    fn transliterate_matches_encode() {
+        assert_eq!(CodePage::Cp437.transliterate("Café 5 €"), "Café 5 EUR");
+        assert_eq!(CodePage::Cp858.transliterate("Café 5 €"), "Café 5 €");
+        for page in ALL {
+            let text = "Crème brûlée – 5 € × 2 Łódź Дом Ωμέγα 中";
+            let printed = page.transliterate(text);
+            assert_eq!(printed.chars().count(), page.encode(text).len(), "{:?}", page);
+            assert_eq!(page.encode(&printed), page.encode(text), "{:?}", page);
+        }
+    }
+
+    #[test]
+    fn tables_have_no_duplicates() {
+        for page in ALL {
+            let table = page.upper_half();
+            for (i, c) in table.iter().enumerate().filter(|(_, c)| **c != UNMAPPED) {
+                assert!(!c.is_ascii(), "{:?} has ASCII {:?} at 0x{:02X}", page, c, 0x80 + i);
+                assert_eq!(table.iter().position(|t| t == c), Some(i), "{:?} has {:?} twice", page, c);
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use serde::Deserialize;
+use crate::encoder::barcode::{HriPosition, QrErrorCorrection, Symbology};
+use crate::encoder::codepage::CodePage;
+use crate::encoder::document::{Align, CutMode, DrawerPin, TextStyle};
+use crate::encoder::escpos::{EscPos, GraphicsMode};
+use crate::encoder::raster::Bitmap;
+use crate::encoder::star::StarLine;
+
+// =========================================================================
+// COMMAND SETS
+// =========================================================================
+// Printer brands speak different "languages". Epson and most clones use
+// ESC/POS, Star printers in Line Mode use their own commands for the same
+// things (cut, drawer, styles...). The receipt encoder only decides WHAT to
+// print; a PrinterCommands implementation decides which bytes mean it.
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CommandSet {
+    #[default]
+    EscPos,
+    Star,
+}
+
+impl CommandSet {
+    pub fn commands(&self) -> &'static dyn PrinterCommands {
+        match self {
+            CommandSet::EscPos => &EscPos,
+            CommandSet::Star => &StarLine,
+        }
+    }
+}
+
+pub trait PrinterCommands: Send + Sync {
+    /// Resets the printer and selects the code page for bytes 0x80-0xFF.
+    fn initialize(&self, codepage: CodePage) -> Vec<u8>;
+
+    /// Selects the code page for bytes 0x80-0xFF, without resetting the printer.
+    fn select_codepage(&self, codepage: CodePage) -> Vec<u8>;
+
+    fn justify(&self, align: Align) -> Vec<u8>;
+
+    /// Switches on the text style (font, bold, underline, reverse, size).
+    fn set_style(&self, style: &TextStyle) -> Vec<u8>;
+
+    /// Back to plain, left-aligned text.
+    fn reset_style(&self) -> Vec<u8>;
+
+    fn feed(&self, lines: u8) -> Vec<u8>;
+
+    /// Feeds past the cutter and cuts. `None` means the printer has no cutter.
+    fn cut(&self, mode: Option<CutMode>, feed_lines: u8) -> Vec<u8>;
+
+    fn drawer_kick(&self, pin: DrawerPin) -> Vec<u8>;
+
+    fn image(&self, bitmap: &Bitmap, graphics: GraphicsMode) -> Vec<u8>;
+
+    fn barcode(&self, symbology: Symbology, data: &str, height: u8, width: u8, hri: HriPosition) -> Vec<u8>;
+
+    fn qrcode(&self, data: &str, module_size: u8, error_correction: QrErrorCorrection) -> Vec<u8>;
+}
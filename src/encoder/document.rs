@@ -0,0 +1,193 @@
+use serde::Deserialize;
+use crate::encoder::raster::Dither;
+use crate::encoder::barcode::{HriPosition, QrErrorCorrection, Symbology};
+
+// =========================================================================
+// RECEIPT DOCUMENT MODEL
+// =========================================================================
+// A "document" is a list of typed blocks that describe WHAT should be printed,
+// not HOW. The encoder turns it into printer bytes, so the POS frontend no
+// longer has to pad columns or embed escape codes in strings.
+//
+// e.g. "document": [
+//   { "type": "text", "text": "MY SHOP", "align": "center", "bold": true, "double_height": true },
+//   { "type": "rule" },
+//   { "type": "row", "left": "2x Coffee", "right": "7.00" },
+//   { "type": "image", "data": "iVBORw0KGgo...", "align": "center", "dither": "atkinson" },
+//   { "type": "barcode", "symbology": "ean13", "data": "400638133393" },
+//   { "type": "qrcode", "data": "https://shop.example/r/12345", "module_size": 6 },
+//   { "type": "feed", "lines": 2 },
+//   { "type": "cut" },
+//   { "type": "drawer" }
+// ]
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Block {
+    // A paragraph of text. Long lines are word-wrapped to the paper width.
+    Text {
+        text: String,
+        #[serde(flatten)]
+        style: TextStyle,
+    },
+
+    // A horizontal line across the full paper width (e.g. "------").
+    Rule {
+        #[serde(default = "default_rule_char")]
+        character: char,
+    },
+
+    // Two columns: left text and right-aligned text (e.g. item name + price).
+    Row {
+        left: String,
+        right: String,
+        #[serde(flatten)]
+        style: TextStyle,
+    },
+
+    // A PNG or JPEG picture (logo, signature...), sent as base64.
+    // It is scaled down to the paper width and dithered to black & white.
+    Image {
+        data: String,
+        // Maximum width in printer dots (defaults to the full paper width)
+        width: Option<usize>,
+        #[serde(default)]
+        dither: Dither,
+        // Grey level (0-255) below which a pixel is printed black
+        #[serde(default = "default_threshold")]
+        threshold: u8,
+        #[serde(default)]
+        align: Align,
+    },
+
+    // A 1D barcode (product codes, receipt numbers...).
+    Barcode {
+        symbology: Symbology,
+        data: String,
+        // Bar height in dots
+        #[serde(default = "default_barcode_height")]
+        height: u8,
+        // Width of the narrowest bar in dots (2-6)
+        #[serde(default = "default_barcode_width")]
+        width: u8,
+        #[serde(default)]
+        hri: HriPosition,
+        #[serde(default = "default_center")]
+        align: Align,
+    },
+
+    // A QR code (model 2), e.g. for returns or e-receipts.
+    Qrcode {
+        data: String,
+        // Size of one QR "square" in dots (1-16)
+        #[serde(default = "default_module_size")]
+        module_size: u8,
+        #[serde(default)]
+        error_correction: QrErrorCorrection,
+        #[serde(default = "default_center")]
+        align: Align,
+    },
+
+    // Feed blank lines.
+    Feed {
+        #[serde(default = "default_feed_lines")]
+        lines: u8,
+    },
+
+    // Feed past the cutter blade and cut the paper.
+    Cut {
+        #[serde(default)]
+        mode: CutMode,
+    },
+
+    // Pulse the cash drawer plugged into the printer.
+    Drawer {
+        #[serde(default)]
+        pin: DrawerPin,
+    },
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct TextStyle {
+    #[serde(default)]
+    pub align: Align,
+    #[serde(default)]
+    pub font: Font,
+    #[serde(default)]
+    pub bold: bool,
+    #[serde(default)]
+    pub underline: bool,
+    #[serde(default)]
+    pub double_width: bool,
+    #[serde(default)]
+    pub double_height: bool,
+    #[serde(default)]
+    pub invert: bool, // White text on black background
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Font {
+    // Normal font (e.g. 42 or 48 characters on 80mm paper)
+    #[default]
+    A,
+    // Condensed font, fits more characters per line
+    B,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CutMode {
+    // Leaves a small tab of paper so the receipt doesn't fall on the floor.
+    // This is what the service has always sent for "auto_cut".
+    #[default]
+    Partial,
+    Full,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DrawerPin {
+    // Pin 2 of the RJ11 drawer port (most drawers)
+    #[default]
+    Pin2,
+    // Pin 5, used when a second drawer is daisy-chained
+    Pin5,
+}
+
+fn default_rule_char() -> char {
+    '-'
+}
+
+fn default_feed_lines() -> u8 {
+    1
+}
+
+fn default_threshold() -> u8 {
+    128
+}
+
+fn default_barcode_height() -> u8 {
+    80
+}
+
+fn default_barcode_width() -> u8 {
+    3
+}
+
+fn default_module_size() -> u8 {
+    6
+}
+
+fn default_center() -> Align {
+    Align::Center
+}
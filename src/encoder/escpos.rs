@@ -0,0 +1,269 @@
+use crate::encoder::codepage::CodePage;
+use crate::encoder::commands::PrinterCommands;
+use crate::encoder::document::{Align, CutMode, DrawerPin, Font, TextStyle};
+use crate::encoder::raster::Bitmap;
+use crate::encoder::barcode::{HriPosition, QrErrorCorrection, Symbology};
+use serde::Deserialize;
+
+// =========================================================================
+// ESC/POS COMMANDS
+// =========================================================================
+// The standard Epson command set spoken by most receipt printers.
+
+// ESC @ - Initialize printer (clears Bold/DoubleWidth/etc. left over from a previous job)
+pub const INIT: &[u8] = &[0x1B, 0x40];
+
+// Tallest band sent in one GS v 0 command. Some printers have small
+// receive buffers and drop data if a whole logo arrives in one command.
+const RASTER_BAND_HEIGHT: usize = 256;
+
+/// How a printer wants to receive pictures.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GraphicsMode {
+    // GS v 0 - Raster bit image (modern printers)
+    #[default]
+    Raster,
+    // ESC * - 24-dot column bit image (older printers and many clones)
+    Column,
+    // The printer can't print pictures at all
+    None,
+}
+
+/// ESC t n - Select the character code table used for bytes 0x80-0xFF.
+/// Must be sent after ESC @, which resets it to the printer's default.
+pub fn select_codepage(codepage: CodePage) -> [u8; 3] {
+    [0x1B, 0x74, codepage.escpos_table()]
+}
+
+/// Returns the drawer kick pulse: ESC p m t1 t2
+/// t1=25 (50ms on), t2=250 (500ms off)
+pub fn drawer_kick(pin: DrawerPin) -> Vec<u8> {
+    let m = match pin {
+        DrawerPin::Pin2 => 0x00,
+        DrawerPin::Pin5 => 0x01,
+    };
+    vec![0x1B, 0x70, m, 0x19, 0xFA]
+}
+
+/// GS v 0 m xL xH yL yH d1...dk - Print a raster bit image, in bands.
+pub fn raster_image(bitmap: &Bitmap) -> Vec<u8> {
+    let bytes_per_row = bitmap.width.div_ceil(8);
+    let mut data = Vec::new();
+
+    for band_start in (0..bitmap.height).step_by(RASTER_BAND_HEIGHT) {
+        let band_height = RASTER_BAND_HEIGHT.min(bitmap.height - band_start);
+        data.extend_from_slice(&[
+            0x1D, 0x76, 0x30, 0x00,
+            (bytes_per_row & 0xFF) as u8, (bytes_per_row >> 8) as u8,
+            (band_height & 0xFF) as u8, (band_height >> 8) as u8,
+        ]);
+        for y in band_start..band_start + band_height {
+            data.extend_from_slice(&bitmap.row_bytes(y));
+        }
+    }
+    data
+}
+
+/// ESC * 33 nL nH d1...dk - Print the image as stripes of 24-dot columns.
+pub fn column_image(bitmap: &Bitmap) -> Vec<u8> {
+    let mut data = Vec::new();
+    // ESC 3 24 - Line spacing = 24 dots, so the stripes touch each other
+    data.extend_from_slice(&[0x1B, 0x33, 24]);
+
+    for stripe_start in (0..bitmap.height).step_by(24) {
+        data.extend_from_slice(&[0x1B, 0x2A, 33, (bitmap.width & 0xFF) as u8, (bitmap.width >> 8) as u8]);
+        for x in 0..bitmap.width {
+            data.extend_from_slice(&bitmap.column_bytes(x, stripe_start));
+        }
+        data.push(b'\n');
+    }
+
+    // ESC 2 - Back to the default line spacing
+    data.extend_from_slice(&[0x1B, 0x32]);
+    data
+}
+
+/// GS k m n d1...dn - Print a 1D barcode (preceded by its size/HRI settings).
+pub fn barcode(symbology: Symbology, data: &str, height: u8, width: u8, hri: HriPosition) -> Vec<u8> {
+    // "Function B" symbology numbers
+    let m = match symbology {
+        Symbology::UpcA => 65,
+        Symbology::Ean13 => 67,
+        Symbology::Ean8 => 68,
+        Symbology::Code39 => 69,
+        Symbology::Itf => 70,
+        Symbology::Codabar => 71,
+        Symbology::Code128 => 73,
+    };
+    let hri = match hri {
+        HriPosition::None => 0,
+        HriPosition::Above => 1,
+        HriPosition::Below => 2,
+        HriPosition::Both => 3,
+    };
+
+    let mut payload = data.as_bytes().to_vec();
+    // CODE128 needs a code set selector; "{B" = letters, digits and punctuation.
+    // A "{" in the data would start another selector, so it is sent as "{{".
+    if symbology == Symbology::Code128 {
+        payload = b"{B".to_vec();
+        payload.extend_from_slice(data.replace('{', "{{").as_bytes());
+    }
+
+    let mut out = vec![
+        0x1D, 0x48, hri,                 // GS H n - HRI position
+        0x1D, 0x66, 0x00,                // GS f 0 - HRI font A
+        0x1D, 0x68, height.max(1),       // GS h n - Bar height
+        0x1D, 0x77, width.clamp(2, 6),   // GS w n - Bar width
+        0x1D, 0x6B, m, payload.len() as u8,
+    ];
+    out.extend_from_slice(&payload);
+    out
+}
+
+/// GS ( k - Store and print a QR code (model 2).
+pub fn qrcode(data: &str, module_size: u8, error_correction: QrErrorCorrection) -> Vec<u8> {
+    let level = match error_correction {
+        QrErrorCorrection::L => 48,
+        QrErrorCorrection::M => 49,
+        QrErrorCorrection::Q => 50,
+        QrErrorCorrection::H => 51,
+    };
+    // The stored length includes the 3 bytes "cn fn m" (the data was checked
+    // by `barcode::validate_qr`, so it fits in the two length bytes)
+    let store_len = data.len() + 3;
+
+    let mut out = vec![
+        0x1D, 0x28, 0x6B, 0x04, 0x00, 0x31, 0x41, 0x32, 0x00,              // Model 2
+        0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x43, module_size.clamp(1, 16), // Module size
+        0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x45, level,                   // Error correction
+        0x1D, 0x28, 0x6B, (store_len & 0xFF) as u8, (store_len >> 8) as u8, 0x31, 0x50, 0x30,
+    ];
+    out.extend_from_slice(data.as_bytes());
+    out.extend_from_slice(&[0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x51, 0x30]); // Print
+    out
+}
+
+// =========================================================================
+// ESC/POS COMMAND SET
+// =========================================================================
+
+pub struct EscPos;
+
+impl PrinterCommands for EscPos {
+    fn initialize(&self, codepage: CodePage) -> Vec<u8> {
+        let mut data = INIT.to_vec();
+        data.extend_from_slice(&select_codepage(codepage));
+        data
+    }
+
+    fn select_codepage(&self, codepage: CodePage) -> Vec<u8> {
+        select_codepage(codepage).to_vec()
+    }
+
+    // ESC a n - Justification (0 = left, 1 = center, 2 = right)
+    fn justify(&self, align: Align) -> Vec<u8> {
+        let n = match align {
+            Align::Left => 0,
+            Align::Center => 1,
+            Align::Right => 2,
+        };
+        vec![0x1B, 0x61, n]
+    }
+
+    fn set_style(&self, style: &TextStyle) -> Vec<u8> {
+        let mut data = self.justify(style.align);
+        // ESC M n - Character font (0 = A, 1 = B)
+        data.extend_from_slice(&[0x1B, 0x4D, (style.font == Font::B) as u8]);
+        // ESC E n - Emphasized (bold)
+        data.extend_from_slice(&[0x1B, 0x45, style.bold as u8]);
+        // ESC - n - Underline
+        data.extend_from_slice(&[0x1B, 0x2D, style.underline as u8]);
+        // GS B n - White/black reverse
+        data.extend_from_slice(&[0x1D, 0x42, style.invert as u8]);
+        // GS ! n - Character size (bits 4-7 = width, bits 0-3 = height)
+        let size = ((style.double_width as u8) << 4) | style.double_height as u8;
+        data.extend_from_slice(&[0x1D, 0x21, size]);
+        data
+    }
+
+    fn reset_style(&self) -> Vec<u8> {
+        vec![
+            0x1B, 0x61, 0x00, // Left align
+            0x1B, 0x4D, 0x00, // Font A
+            0x1B, 0x45, 0x00, // Bold off
+            0x1B, 0x2D, 0x00, // Underline off
+            0x1D, 0x42, 0x00, // Reverse off
+            0x1D, 0x21, 0x00, // Normal size
+        ]
+    }
+
+    // ESC d n - Print and feed n lines
+    fn feed(&self, lines: u8) -> Vec<u8> {
+        vec![0x1B, 0x64, lines]
+    }
+
+    fn cut(&self, mode: Option<CutMode>, feed_lines: u8) -> Vec<u8> {
+        // Feed so the last printed line clears the cutter blade
+        let mut data = self.feed(feed_lines);
+        match mode {
+            // GS V 66 0 - Feed and partial cut
+            Some(CutMode::Partial) => data.extend_from_slice(&[0x1D, 0x56, 0x42, 0x00]),
+            // GS V 65 0 - Feed and full cut
+            Some(CutMode::Full) => data.extend_from_slice(&[0x1D, 0x56, 0x41, 0x00]),
+            None => {}
+        }
+        data
+    }
+
+    fn drawer_kick(&self, pin: DrawerPin) -> Vec<u8> {
+        drawer_kick(pin)
+    }
+
+    fn image(&self, bitmap: &Bitmap, graphics: GraphicsMode) -> Vec<u8> {
+        match graphics {
+            GraphicsMode::Column => column_image(bitmap),
+            _ => raster_image(bitmap),
+        }
+    }
+
+    fn barcode(&self, symbology: Symbology, data: &str, height: u8, width: u8, hri: HriPosition) -> Vec<u8> {
+        barcode(symbology, data, height, width, hri)
+    }
+
+    fn qrcode(&self, data: &str, module_size: u8, error_correction: QrErrorCorrection) -> Vec<u8> {
+        qrcode(data, module_size, error_correction)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn barcode_bytes() {
+        let mut expected = vec![0x1D, 0x48, 2, 0x1D, 0x66, 0, 0x1D, 0x68, 80, 0x1D, 0x77, 3, 0x1D, 0x6B, 67, 13];
+        expected.extend_from_slice(b"4006381333931");
+        assert_eq!(barcode(Symbology::Ean13, "4006381333931", 80, 3, HriPosition::Below), expected);
+    }
+
+    #[test]
+    fn code128_selects_code_set_b_and_escapes_braces() {
+        let mut expected = vec![0x1D, 0x48, 0, 0x1D, 0x66, 0, 0x1D, 0x68, 1, 0x1D, 0x77, 2, 0x1D, 0x6B, 73, 7];
+        expected.extend_from_slice(b"{Ba{{1}");
+        assert_eq!(barcode(Symbology::Code128, "a{1}", 0, 1, HriPosition::None), expected);
+    }
+
+    #[test]
+    fn qrcode_bytes() {
+        assert_eq!(qrcode("hi", 6, QrErrorCorrection::H), [
+            0x1D, 0x28, 0x6B, 0x04, 0x00, 0x31, 0x41, 0x32, 0x00,
+            0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x43, 6,
+            0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x45, 51,
+            0x1D, 0x28, 0x6B, 0x05, 0x00, 0x31, 0x50, 0x30, b'h', b'i',
+            0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x51, 0x30,
+        ]);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::encoder::barcode::{QrErrorCorrection, Symbology};
+use crate::encoder::codepage::CodePage;
+use crate::encoder::label::{Label, LabelField};
+
+// =========================================================================
+// ELTRON / ZEBRA EPL2
+// =========================================================================
+// One command per line: N clears the image buffer, q/Q set the label size,
+// every field is drawn, and P prints. EPL has no UTF-8, so text is sent in
+// code page 850 (selected with I8,1).
+
+// Heights (in dots) of the built-in fonts 1 to 5 at 203 dpi
+const FONT_HEIGHTS: [u32; 5] = [12, 16, 20, 24, 48];
+
+pub fn encode(label: &Label, dpi: u32) -> Vec<u8> {
+    let codepage = CodePage::Cp850;
+    let mut data: Vec<u8> = Vec::new();
+
+    // A leading newline ends any half-received command from before
+    line(&mut data, b"");
+    line(&mut data, b"N");
+    // q - Label width, Q - label length and gap (0 = continuous paper), in dots
+    line(&mut data, format!("q{}", Label::dots(label.width_mm, dpi)).as_bytes());
+    line(&mut data, format!("Q{},{}", Label::dots(label.height_mm, dpi), Label::dots(label.gap_mm, dpi)).as_bytes());
+    // I8,1,001 - 8-bit characters, DOS 850, USA
+    line(&mut data, b"I8,1,001");
+
+    for field in &label.fields {
+        match field {
+            LabelField::Text { x, y, text, size } => {
+                let (font, multiplier) = pick_font(*size);
+                // A x,y,rotation,font,h-mult,v-mult,N (normal, not reversed),"text"
+                let mut command = format!("A{},{},0,{},{},{},N,", x, y, font, multiplier.min(6), multiplier).into_bytes();
+                command.extend_from_slice(&quote(&codepage, text));
+                line(&mut data, &command);
+            }
+            LabelField::Barcode { x, y, symbology, data: code, height, width, hri } => {
+                let kind = match symbology {
+                    Symbology::Ean13 => "E30",
+                    Symbology::Ean8 => "E80",
+                    Symbology::UpcA => "UA0",
+                    Symbology::Code39 => "3",
+                    // "1" = Code 128 with automatic subset switching
+                    Symbology::Code128 => "1",
+                    Symbology::Itf => "2",
+                    Symbology::Codabar => "K",
+                };
+                let hri = if *hri { 'B' } else { 'N' };
+                // B x,y,rotation,type,narrow,wide,height,B (with digits) / N,"data"
+                let mut command = format!("B{},{},0,{},{},{},{},{},", x, y, kind, width, width * 3, height, hri).into_bytes();
+                command.extend_from_slice(&quote(&codepage, code));
+                line(&mut data, &command);
+            }
+            LabelField::Qrcode { x, y, data: code, module_size, error_correction } => {
+                let level = match error_correction {
+                    QrErrorCorrection::L => 'L',
+                    QrErrorCorrection::M => 'M',
+                    QrErrorCorrection::Q => 'Q',
+                    QrErrorCorrection::H => 'H',
+                };
+                // b x,y,Q,m2 (model 2),s (cell size),e (error correction),iA (auto mode),"data"
+                let mut command = format!("b{},{},Q,m2,s{},e{},iA,", x, y, (*module_size).clamp(1, 99), level).into_bytes();
+                command.extend_from_slice(&quote(&codepage, code));
+                line(&mut data, &command);
+            }
+            LabelField::Box { x, y, width, height, thickness } => {
+                // X x_start,y_start,line thickness,x_end,y_end
+                line(&mut data, format!("X{},{},{},{},{}", x, y, thickness, x + width, y + height).as_bytes());
+            }
+        }
+    }
+
+    // P - Print this many copies
+    line(&mut data, format!("P{}", label.copies).as_bytes());
+    data
+}
+
+fn line(data: &mut Vec<u8>, command: &[u8]) {
+    data.extend_from_slice(command);
+    data.push(b'\n');
+}
+
+// EPL only has 5 fixed fonts. We take the biggest one that is not taller than
+// the requested size, and enlarge it for sizes above the biggest font.
+fn pick_font(size: u32) -> (usize, u32) {
+    let index = FONT_HEIGHTS.iter().rposition(|&h| h <= size).unwrap_or(0);
+    let multiplier = (size / FONT_HEIGHTS[index]).clamp(1, 9);
+    (index + 1, multiplier)
+}
+
+// EPL strings are in double quotes; quotes and backslashes are escaped with a backslash
+fn quote(codepage: &CodePage, text: &str) -> Vec<u8> {
+    let escaped = text.replace('\\', "\\\\").replace('"', "\\\"");
+    let mut bytes = vec![b'"'];
+    bytes.extend_from_slice(&codepage.encode(&escaped));
+    bytes.push(b'"');
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::label::tests::sample;
+
+    #[test]
+    fn encodes_a_label() {
+        let expected: &[&[u8]] = &[
+            b"",
+            b"N",
+            b"q400",
+            b"Q200,16",
+            b"I8,1,001",
+            // Font 4 (24 dots) is the biggest one up to 40 dots; "é" is 0x82 in code page 850
+            b"A10,20,0,4,1,1,N,\"Caf\x82 \\\"Bio\\\" ^_~\\\\\"",
+            b"B10,80,0,E30,2,6,50,B,\"4006381333931\"",
+            b"b250,20,Q,m2,s4,eH,iA,\"https://shop.example/p/1\"",
+            b"X5,5,2,395,195",
+            b"P2",
+            b"",
+        ];
+        assert_eq!(encode(&sample(), 203), expected.join(&b'\n'));
+    }
+
+    #[test]
+    fn picks_the_biggest_font_that_fits() {
+        assert_eq!(pick_font(5), (1, 1));
+        assert_eq!(pick_font(20), (3, 1));
+        assert_eq!(pick_font(48), (5, 1));
+        assert_eq!(pick_font(100), (5, 2));
+    }
+}
@@ -0,0 +1,245 @@
+pub mod zpl;
+pub mod tspl;
+pub mod epl;
+
+use serde::{Deserialize, Serialize};
+use crate::encoder::barcode::{self, QrErrorCorrection, Symbology};
+use crate::errors::ServiceError;
+
+// =========================================================================
+// LABEL LAYOUT MODEL
+// =========================================================================
+// Label printers don't print line by line like receipt printers: every field
+// is placed at an (x, y) position on the label, in printer dots (measured
+// from the top-left corner). The label size is given in millimetres and
+// converted with the printer's resolution (203 dpi = 8 dots per mm).
+//
+// e.g. "data": {
+//   "width_mm": 58, "height_mm": 40, "gap_mm": 3, "copies": 2,
+//   "fields": [
+//     { "type": "text", "x": 20, "y": 20, "text": "Smoked Ham", "size": 40 },
+//     { "type": "barcode", "x": 20, "y": 80, "symbology": "ean13", "data": "2001234500000" },
+//     { "type": "qrcode", "x": 320, "y": 20, "data": "https://shop.example/p/123" },
+//     { "type": "box", "x": 10, "y": 10, "width": 440, "height": 300 }
+//   ]
+// }
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelLanguage {
+    // Zebra ZPL II
+    Zpl,
+    // TSC TSPL/TSPL2 (also used by most cheap "thermal label" printers)
+    Tspl,
+    // Eltron/Zebra EPL2 (older Zebra LP/TLP desktop printers)
+    Epl,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Label {
+    pub width_mm: f32,
+    pub height_mm: f32,
+    // Space between two labels on the roll. 0 = continuous paper.
+    #[serde(default = "default_gap_mm")]
+    pub gap_mm: f32,
+    #[serde(default = "default_copies")]
+    pub copies: u32,
+    pub fields: Vec<LabelField>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LabelField {
+    Text {
+        x: u32,
+        y: u32,
+        text: String,
+        // Character height in dots
+        #[serde(default = "default_text_size")]
+        size: u32,
+    },
+
+    Barcode {
+        x: u32,
+        y: u32,
+        symbology: Symbology,
+        data: String,
+        // Bar height in dots
+        #[serde(default = "default_barcode_height")]
+        height: u32,
+        // Width of the narrowest bar in dots
+        #[serde(default = "default_barcode_width")]
+        width: u32,
+        // Print the digits under the bars
+        #[serde(default = "default_true")]
+        hri: bool,
+    },
+
+    Qrcode {
+        x: u32,
+        y: u32,
+        data: String,
+        // Size of one QR "square" in dots
+        #[serde(default = "default_module_size")]
+        module_size: u32,
+        #[serde(default)]
+        error_correction: QrErrorCorrection,
+    },
+
+    // A rectangle outline (use a small width or height for a line)
+    Box {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        #[serde(default = "default_thickness")]
+        thickness: u32,
+    },
+}
+
+impl Label {
+    /// Millimetres to printer dots at the given resolution.
+    pub fn dots(mm: f32, dpi: u32) -> u32 {
+        (mm * dpi as f32 / 25.4).round().max(0.0) as u32
+    }
+}
+
+/// Checks the label and renders it in the printer's language.
+pub fn encode(language: LabelLanguage, label: &Label, dpi: u32) -> Result<Vec<u8>, ServiceError> {
+    validate(label)?;
+    let data = match language {
+        LabelLanguage::Zpl => zpl::encode(label, dpi),
+        LabelLanguage::Tspl => tspl::encode(label, dpi),
+        LabelLanguage::Epl => epl::encode(label, dpi),
+    };
+    Ok(data)
+}
+
+// Label printers usually print nothing (or garbage) on bad data,
+// so we reject it here with a clear message instead.
+fn validate(label: &Label) -> Result<(), ServiceError> {
+    if label.width_mm <= 0.0 || label.height_mm <= 0.0 {
+        return Err(ServiceError::InvalidCommand("Label width_mm and height_mm must be greater than 0".into()));
+    }
+    if label.gap_mm < 0.0 {
+        return Err(ServiceError::InvalidCommand("Label gap_mm can't be negative".into()));
+    }
+    if label.copies == 0 {
+        return Err(ServiceError::InvalidCommand("Label copies must be at least 1".into()));
+    }
+
+    for field in &label.fields {
+        match field {
+            LabelField::Text { text, .. } => no_control_chars("Label text", text)?,
+            LabelField::Barcode { symbology, data, .. } => {
+                no_control_chars("Barcode data", data)?;
+                barcode::validate(*symbology, data)?;
+            }
+            LabelField::Qrcode { data, error_correction, .. } => {
+                no_control_chars("QR code data", data)?;
+                barcode::validate_qr(data, *error_correction)?;
+            }
+            LabelField::Box { .. } => {}
+        }
+    }
+    Ok(())
+}
+
+// TSPL and EPL are one command per line: a line break in a field would end
+// the field's command, and whatever follows ("PRINT 1,999") would run as the
+// next one. Other control characters have no place on a label either.
+fn no_control_chars(what: &str, text: &str) -> Result<(), ServiceError> {
+    match text.chars().find(|c| c.is_ascii_control()) {
+        Some(c) => Err(ServiceError::InvalidCommand(format!(
+            "{} can't contain control characters (found U+{:04X})", what, c as u32))),
+        None => Ok(()),
+    }
+}
+
+fn default_gap_mm() -> f32 {
+    3.0
+}
+
+fn default_copies() -> u32 {
+    1
+}
+
+fn default_text_size() -> u32 {
+    30
+}
+
+fn default_barcode_height() -> u32 {
+    80
+}
+
+fn default_barcode_width() -> u32 {
+    2
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_module_size() -> u32 {
+    4
+}
+
+fn default_thickness() -> u32 {
+    2
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // One field of each kind, with characters each language has to escape
+    pub(crate) fn sample() -> Label {
+        serde_json::from_value(serde_json::json!({
+            "width_mm": 50, "height_mm": 25, "gap_mm": 2, "copies": 2,
+            "fields": [
+                { "type": "text", "x": 10, "y": 20, "text": "Café \"Bio\" ^_~\\", "size": 40 },
+                { "type": "barcode", "x": 10, "y": 80, "symbology": "ean13", "data": "4006381333931", "height": 50 },
+                { "type": "qrcode", "x": 250, "y": 20, "data": "https://shop.example/p/1", "error_correction": "h" },
+                { "type": "box", "x": 5, "y": 5, "width": 390, "height": 190 }
+            ]
+        })).unwrap()
+    }
+
+    fn with_field(field: serde_json::Value) -> Label {
+        serde_json::from_value(serde_json::json!({ "width_mm": 50, "height_mm": 25, "fields": [field] })).unwrap()
+    }
+
+    #[test]
+    fn converts_millimetres_to_dots() {
+        assert_eq!(Label::dots(25.4, 203), 203);
+        assert_eq!(Label::dots(50.0, 203), 400);
+        assert_eq!(Label::dots(50.0, 300), 591);
+    }
+
+    #[test]
+    fn rejects_line_breaks_in_fields() {
+        let injected = [
+            serde_json::json!({ "type": "text", "x": 0, "y": 0, "text": "Ham\r\nPRINT 1,999" }),
+            serde_json::json!({ "type": "qrcode", "x": 0, "y": 0, "data": "x\"\r\nPRINT 1,999\r\n" }),
+            serde_json::json!({ "type": "barcode", "x": 0, "y": 0, "symbology": "code128", "data": "1\nP999" }),
+            serde_json::json!({ "type": "text", "x": 0, "y": 0, "text": "tab\there" }),
+        ];
+        for field in injected {
+            for language in [LabelLanguage::Zpl, LabelLanguage::Tspl, LabelLanguage::Epl] {
+                let error = encode(language, &with_field(field.clone()), 203).unwrap_err();
+                assert!(error.to_string().contains("control characters"), "{}", error);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_bad_label_sizes() {
+        let mut label = sample();
+        label.copies = 0;
+        assert!(encode(LabelLanguage::Zpl, &label, 203).is_err());
+        let mut label = sample();
+        label.width_mm = 0.0;
+        assert!(encode(LabelLanguage::Tspl, &label, 203).is_err());
+        assert!(encode(LabelLanguage::Epl, &sample(), 203).is_ok());
+    }
+}
@@ -0,0 +1,99 @@
+use crate::encoder::barcode::{QrErrorCorrection, Symbology};
+use crate::encoder::label::{Label, LabelField};
+
+// =========================================================================
+// TSC TSPL / TSPL2
+// =========================================================================
+// One command per line (ended by CR LF): set up the label size, CLS clears
+// the image buffer, then every field is drawn and PRINT feeds the labels out.
+
+pub fn encode(label: &Label, dpi: u32) -> Vec<u8> {
+    let mut lines: Vec<String> = Vec::new();
+
+    lines.push(format!("SIZE {} mm,{} mm", label.width_mm, label.height_mm));
+    // GAP 0 mm,0 mm = continuous paper
+    lines.push(format!("GAP {} mm,0 mm", label.gap_mm));
+    lines.push("CODEPAGE UTF-8".into());
+    lines.push("CLS".into());
+
+    for field in &label.fields {
+        match field {
+            LabelField::Text { x, y, text, size } => {
+                // Font "0" is the scalable font; its size is given in points (1/72 inch)
+                let points = (*size * 72 / dpi.max(1)).max(1);
+                lines.push(format!("TEXT {},{},\"0\",0,{},{},{}", x, y, points, points, quote(text)));
+            }
+            LabelField::Barcode { x, y, symbology, data, height, width, hri } => {
+                let kind = match symbology {
+                    Symbology::Ean13 => "EAN13",
+                    Symbology::Ean8 => "EAN8",
+                    Symbology::UpcA => "UPCA",
+                    Symbology::Code39 => "39",
+                    // "128" = the printer switches subsets automatically
+                    Symbology::Code128 => "128",
+                    Symbology::Itf => "25",
+                    Symbology::Codabar => "CODA",
+                };
+                // BARCODE x,y,"type",height,human readable,rotation,narrow,wide,"data"
+                lines.push(format!(
+                    "BARCODE {},{},\"{}\",{},{},0,{},{},{}",
+                    x, y, kind, height, *hri as u8, width, width * 3, quote(data)
+                ));
+            }
+            LabelField::Qrcode { x, y, data, module_size, error_correction } => {
+                let level = match error_correction {
+                    QrErrorCorrection::L => 'L',
+                    QrErrorCorrection::M => 'M',
+                    QrErrorCorrection::Q => 'Q',
+                    QrErrorCorrection::H => 'H',
+                };
+                // QRCODE x,y,ECC level,cell width,A (auto mode),rotation,"data"
+                lines.push(format!(
+                    "QRCODE {},{},{},{},A,0,{}",
+                    x, y, level, (*module_size).clamp(1, 10), quote(data)
+                ));
+            }
+            LabelField::Box { x, y, width, height, thickness } => {
+                // BOX x_start,y_start,x_end,y_end,line thickness
+                lines.push(format!("BOX {},{},{},{},{}", x, y, x + width, y + height, thickness));
+            }
+        }
+    }
+
+    // PRINT sets,copies
+    lines.push(format!("PRINT 1,{}", label.copies));
+
+    let mut tspl = lines.join("\r\n");
+    tspl.push_str("\r\n");
+    tspl.into_bytes()
+}
+
+// TSPL strings are in double quotes; a quote inside the text is written as \["]
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\\[\"]"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::label::tests::sample;
+
+    #[test]
+    fn encodes_a_label() {
+        let tspl = String::from_utf8(encode(&sample(), 203)).unwrap();
+        let expected = [
+            "SIZE 50 mm,25 mm",
+            "GAP 2 mm,0 mm",
+            "CODEPAGE UTF-8",
+            "CLS",
+            // 40 dots at 203 dpi = 14 points
+            r#"TEXT 10,20,"0",0,14,14,"Café \["]Bio\["] ^_~\""#,
+            r#"BARCODE 10,80,"EAN13",50,1,0,2,6,"4006381333931""#,
+            r#"QRCODE 250,20,H,4,A,0,"https://shop.example/p/1""#,
+            "BOX 5,5,395,195,2",
+            "PRINT 1,2",
+            "",
+        ];
+        assert_eq!(tspl, expected.join("\r\n"));
+    }
+}
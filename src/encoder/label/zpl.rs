@@ -0,0 +1,119 @@
+use crate::encoder::barcode::{QrErrorCorrection, Symbology};
+use crate::encoder::label::{Label, LabelField};
+
+// =========================================================================
+// ZEBRA ZPL II
+// =========================================================================
+// A label starts with ^XA and ends with ^XZ. Each field is
+// "^FO x,y" (field origin) + what to draw + "^FD data" + "^FS" (field separator).
+
+pub fn encode(label: &Label, dpi: u32) -> Vec<u8> {
+    let mut zpl = String::new();
+
+    zpl.push_str("^XA\n");
+    // ^CI28 - Field data is UTF-8
+    zpl.push_str("^CI28\n");
+    // ^PW / ^LL - Label width and length in dots, ^LH - origin at the top-left corner
+    zpl.push_str(&format!("^PW{}\n", Label::dots(label.width_mm, dpi)));
+    zpl.push_str(&format!("^LL{}\n", Label::dots(label.height_mm, dpi)));
+    zpl.push_str("^LH0,0\n");
+    // ^MN - Media tracking: Y = labels with gaps, N = continuous paper
+    zpl.push_str(if label.gap_mm > 0.0 { "^MNY\n" } else { "^MNN\n" });
+
+    for field in &label.fields {
+        match field {
+            LabelField::Text { x, y, text, size } => {
+                // ^A0N,h,w - Scalable font 0, normal orientation
+                zpl.push_str(&format!("^FO{},{}^A0N,{},{}^FH^FD{}^FS\n", x, y, size, size, escape(text)));
+            }
+            LabelField::Barcode { x, y, symbology, data, height, width, hri } => {
+                let hri = if *hri { 'Y' } else { 'N' };
+                let command = match symbology {
+                    Symbology::Ean13 => format!("^BEN,{},{},N", height, hri),
+                    Symbology::Ean8 => format!("^B8N,{},{},N", height, hri),
+                    Symbology::UpcA => format!("^BUN,{},{},N,Y", height, hri),
+                    Symbology::Code39 => format!("^B3N,N,{},{},N", height, hri),
+                    // Mode A = the printer picks the best Code 128 subsets itself
+                    Symbology::Code128 => format!("^BCN,{},{},N,N,A", height, hri),
+                    Symbology::Itf => format!("^B2N,{},{},N,N", height, hri),
+                    // The start and stop characters are parameters, not data
+                    Symbology::Codabar => format!("^BKN,N,{},{},N,{},{}", height, hri,
+                        data[..1].to_ascii_uppercase(), data[data.len() - 1..].to_ascii_uppercase()),
+                };
+                let data = match symbology {
+                    Symbology::Codabar => &data[1..data.len() - 1],
+                    _ => data.as_str(),
+                };
+                // ^BY - Narrow bar width in dots
+                zpl.push_str(&format!("^FO{},{}^BY{}{}^FH^FD{}^FS\n", x, y, width, command, escape(data)));
+            }
+            LabelField::Qrcode { x, y, data, module_size, error_correction } => {
+                let level = match error_correction {
+                    QrErrorCorrection::L => 'L',
+                    QrErrorCorrection::M => 'M',
+                    QrErrorCorrection::Q => 'Q',
+                    QrErrorCorrection::H => 'H',
+                };
+                // ^BQN,2,m - QR model 2, magnification m (1-10).
+                // The data starts with the error correction level and "A" (automatic input mode).
+                zpl.push_str(&format!(
+                    "^FO{},{}^BQN,2,{}^FH^FD{}A,{}^FS\n",
+                    x, y, (*module_size).clamp(1, 10), level, escape(data)
+                ));
+            }
+            LabelField::Box { x, y, width, height, thickness } => {
+                // ^GB w,h,t - Graphic box
+                zpl.push_str(&format!("^FO{},{}^GB{},{},{}^FS\n", x, y, width, height, thickness));
+            }
+        }
+    }
+
+    // ^PQ - Number of copies
+    zpl.push_str(&format!("^PQ{}\n", label.copies));
+    zpl.push_str("^XZ\n");
+    zpl.into_bytes()
+}
+
+// With ^FH, "_" followed by two hex digits is a raw byte. We use it to send
+// characters that would otherwise start a new ZPL command.
+fn escape(text: &str) -> String {
+    text.replace('_', "_5F").replace('^', "_5E").replace('~', "_7E")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::label::tests::sample;
+
+    #[test]
+    fn encodes_a_label() {
+        let zpl = String::from_utf8(encode(&sample(), 203)).unwrap();
+        let expected = [
+            "^XA",
+            "^CI28",
+            "^PW400",
+            "^LL200",
+            "^LH0,0",
+            "^MNY",
+            // ^ _ and ~ would start commands: sent as hex with ^FH
+            r#"^FO10,20^A0N,40,40^FH^FDCafé "Bio" _5E_5F_7E\^FS"#,
+            "^FO10,80^BY2^BEN,50,Y,N^FH^FD4006381333931^FS",
+            "^FO250,20^BQN,2,4^FH^FDHA,https://shop.example/p/1^FS",
+            "^FO5,5^GB390,190,2^FS",
+            "^PQ2",
+            "^XZ",
+            "",
+        ];
+        assert_eq!(zpl, expected.join("\n"));
+    }
+
+    #[test]
+    fn codabar_start_and_stop_are_parameters() {
+        let mut label = sample();
+        label.fields = vec![LabelField::Barcode {
+            x: 0, y: 0, symbology: Symbology::Codabar, data: "a1234b".into(), height: 40, width: 2, hri: false,
+        }];
+        let zpl = String::from_utf8(encode(&label, 203)).unwrap();
+        assert!(zpl.contains("^FO0,0^BY2^BKN,N,40,N,N,A,B^FH^FD1234^FS\n"), "{}", zpl);
+    }
+}
@@ -0,0 +1,90 @@
+// =========================================================================
+// TEXT LAYOUT HELPERS
+// =========================================================================
+// Thermal printers print a fixed number of characters per line (e.g. 32 on
+// 58mm paper, 42/48 on 80mm). These helpers do the word-wrapping and column
+// padding that the POS frontend used to do in JavaScript.
+// Widths are counted in characters, not bytes, so "Café" is 4 wide. The
+// text must already be transliterated for the printer's code page
+// (`CodePage::transliterate`): "€" prints as "EUR" on CP437, 3 wide.
+
+/// Removes control characters (ESC, GS, DLE...) except line breaks and tabs.
+/// Client text must never reach the printer as commands: "\u{1b}p\u{0}..."
+/// in an item name would open the cash drawer.
+pub fn printable(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_ascii_control() || *c == '\n' || *c == '\t')
+        .collect()
+}
+
+/// Word-wraps `text` so that no line is longer than `width` characters.
+/// Existing line breaks are kept; words longer than a line are split.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+
+    for paragraph in text.split('\n') {
+        let mut current = String::new();
+        let mut current_len = 0;
+
+        for word in paragraph.split(' ') {
+            let mut word: Vec<char> = word.chars().collect();
+
+            // Put a space between words (if there's room) or start a new line
+            if current_len > 0 {
+                if current_len + 1 + word.len() <= width {
+                    current.push(' ');
+                    current_len += 1;
+                } else {
+                    lines.push(std::mem::take(&mut current));
+                    current_len = 0;
+                }
+            }
+
+            // A single word wider than the paper gets hard-split
+            while word.len() > width - current_len {
+                let rest = word.split_off(width - current_len);
+                current.extend(word);
+                lines.push(std::mem::take(&mut current));
+                current_len = 0;
+                word = rest;
+            }
+
+            current_len += word.len();
+            current.extend(word);
+        }
+
+        lines.push(current);
+    }
+
+    lines
+}
+
+/// Lays out a two-column row: `left` text and `right` text pushed to the
+/// right edge. If both don't fit on one line, the left text is wrapped and
+/// the right text goes right-aligned on the last line (or its own line).
+pub fn two_columns(left: &str, right: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let right_len = right.chars().count();
+
+    // Keep at least one space between the columns
+    let left_width = width.saturating_sub(right_len + 1).max(1);
+    let mut lines = wrap(left, left_width);
+
+    let last = lines.pop().unwrap_or_default();
+    let last_len = last.chars().count();
+
+    if last_len + 1 + right_len <= width {
+        let padding = width - last_len - right_len;
+        lines.push(format!("{}{}{}", last, " ".repeat(padding), right));
+    } else {
+        // The right column is wider than the paper (or nearly): give it its own line(s)
+        lines.push(last);
+        for line in wrap(right, width) {
+            let padding = width.saturating_sub(line.chars().count());
+            lines.push(format!("{}{}", " ".repeat(padding), line));
+        }
+    }
+
+    lines
+}
@@ -0,0 +1,26 @@
+// =========================================================================
+// ENCODER
+// =========================================================================
+// Turns high-level print requests (plain text, structured documents) into the
+// raw bytes that a receipt printer understands, and label layouts into label
+// printer languages (ZPL, TSPL, EPL). Drivers in `hardware` only move bytes;
+// everything about the command languages lives here.
+
+pub mod document;
+pub mod layout;
+pub mod codepage;
+pub mod raster;
+pub mod barcode;
+pub mod commands;
+pub mod escpos;
+pub mod star;
+pub mod receipt;
+pub mod status;
+pub mod label;
+
+pub use document::Block;
+pub use codepage::CodePage;
+pub use commands::CommandSet;
+pub use receipt::ReceiptEncoder;
+pub use label::{Label, LabelLanguage};
+pub use status::PrinterStatus;
@@ -0,0 +1,163 @@
+use base64::Engine;
+use serde::Deserialize;
+use crate::errors::ServiceError;
+
+// =========================================================================
+// RASTER IMAGES
+// =========================================================================
+// Thermal printers can only print black or white dots. To print a logo or a
+// signature we:
+//   1. decode the PNG/JPEG the POS sent us (as base64),
+//   2. scale it down to fit the paper (e.g. 512 dots on 80mm paper),
+//   3. "dither" the grey levels into a pattern of black and white dots.
+// The resulting Bitmap is then wrapped in printer commands by the encoder.
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Dither {
+    // Every pixel darker than the threshold becomes black. Best for logos and line art.
+    Threshold,
+    // Spreads the rounding error to the neighbours. Best for photos.
+    #[default]
+    FloydSteinberg,
+    // Like Floyd-Steinberg but lighter and with more contrast (classic Mac look).
+    Atkinson,
+}
+
+/// A 1-bit image: `true` means "print a black dot".
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<bool>,
+}
+
+impl Bitmap {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, pixels: vec![false; width * height] }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, black: bool) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = black;
+        }
+    }
+
+    /// Returns a copy placed at `offset` dots from the left inside a bitmap of
+    /// `total_width` dots. Used to center/right-align images on the paper.
+    pub fn with_left_margin(&self, offset: usize, total_width: usize) -> Bitmap {
+        let mut padded = Bitmap::new(total_width.max(self.width + offset), self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                padded.set(x + offset, y, self.get(x, y));
+            }
+        }
+        padded
+    }
+
+    /// Packs 24 dots of column `x`, starting at row `top`, into 3 bytes
+    /// (top dot = most significant bit). Used by column graphics commands.
+    pub fn column_bytes(&self, x: usize, top: usize) -> [u8; 3] {
+        let mut bytes = [0u8; 3];
+        for (byte, value) in bytes.iter_mut().enumerate() {
+            for bit in 0..8 {
+                if self.get(x, top + byte * 8 + bit) {
+                    *value |= 0x80 >> bit;
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Packs one row into bytes, 8 dots per byte, most significant bit first.
+    pub fn row_bytes(&self, y: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; self.width.div_ceil(8)];
+        for x in 0..self.width {
+            if self.get(x, y) {
+                bytes[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        bytes
+    }
+}
+
+/// Decodes a base64 PNG/JPEG, scales it to at most `max_width` dots and dithers it.
+/// A "data:image/png;base64," prefix (as produced by browsers) is accepted.
+pub fn decode_image(data: &str, max_width: usize, dither: Dither, threshold: u8) -> Result<Bitmap, ServiceError> {
+    let encoded = match data.find("base64,") {
+        Some(index) => &data[index + "base64,".len()..],
+        None => data,
+    };
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| ServiceError::InvalidCommand(format!("Image is not valid base64: {}", e)))?;
+
+    let decoded = image::load_from_memory(&bytes)
+        .map_err(|e| ServiceError::InvalidCommand(format!("Unsupported image: {}", e)))?;
+
+    // Only ever scale down: small logos should stay sharp
+    let decoded = if decoded.width() as usize > max_width {
+        let height = (decoded.height() as u64 * max_width as u64 / decoded.width() as u64).max(1);
+        decoded.resize_exact(max_width as u32, height as u32, image::imageops::FilterType::Triangle)
+    } else {
+        decoded
+    };
+
+    // Convert to grey levels, treating transparent pixels as white paper
+    let rgba = decoded.to_rgba8();
+    let (width, height) = (rgba.width() as usize, rgba.height() as usize);
+    let mut luma: Vec<f32> = rgba
+        .pixels()
+        .map(|p| {
+            let [r, g, b, a] = p.0;
+            let grey = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+            let alpha = a as f32 / 255.0;
+            grey * alpha + 255.0 * (1.0 - alpha)
+        })
+        .collect();
+
+    Ok(dither_luma(&mut luma, width, height, dither, threshold))
+}
+
+// Turns grey levels (0 = black, 255 = white) into black/white dots.
+fn dither_luma(luma: &mut [f32], width: usize, height: usize, dither: Dither, threshold: u8) -> Bitmap {
+    // (dx, dy, weight) of the neighbours that receive part of the error
+    let diffusion: &[(isize, usize, f32)] = match dither {
+        Dither::Threshold => &[],
+        Dither::FloydSteinberg => &[
+            (1, 0, 7.0 / 16.0),
+            (-1, 1, 3.0 / 16.0), (0, 1, 5.0 / 16.0), (1, 1, 1.0 / 16.0),
+        ],
+        // Atkinson only spreads 6/8 of the error, which keeps highlights clean
+        Dither::Atkinson => &[
+            (1, 0, 1.0 / 8.0), (2, 0, 1.0 / 8.0),
+            (-1, 1, 1.0 / 8.0), (0, 1, 1.0 / 8.0), (1, 1, 1.0 / 8.0),
+            (0, 2, 1.0 / 8.0),
+        ],
+    };
+
+    let threshold = threshold as f32;
+    let mut bitmap = Bitmap::new(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let old = luma[y * width + x];
+            let black = old < threshold;
+            bitmap.set(x, y, black);
+
+            let error = old - if black { 0.0 } else { 255.0 };
+            for &(dx, dy, weight) in diffusion {
+                let nx = x as isize + dx;
+                let ny = y + dy;
+                if nx >= 0 && (nx as usize) < width && ny < height {
+                    luma[ny * width + nx as usize] += error * weight;
+                }
+            }
+        }
+    }
+
+    bitmap
+}
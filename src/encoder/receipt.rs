@@ -0,0 +1,253 @@
+use crate::encoder::barcode;
+use crate::encoder::codepage::CodePage;
+use crate::encoder::commands::PrinterCommands;
+use crate::encoder::document::{Align, Block, CutMode, DrawerPin, Font, TextStyle};
+use crate::encoder::escpos::GraphicsMode;
+use crate::encoder::layout;
+use crate::encoder::raster::{self, Bitmap};
+use crate::errors::ServiceError;
+use crate::hardware::printer::profile::PrinterProfile;
+use crate::hardware::traits::Printer;
+
+// =========================================================================
+// RECEIPT ENCODER
+// =========================================================================
+// Compiles documents into printer bytes. Paper width, fonts and supported
+// features come from the printer's profile; the actual bytes come from the
+// profile's command set (ESC/POS or Star).
+
+pub struct ReceiptEncoder<'a> {
+    profile: &'a PrinterProfile,
+    codepage: CodePage,
+    commands: &'static dyn PrinterCommands,
+}
+
+/// Builds a complete plain-text job: printer reset, code page selection, the
+/// text converted to the printer's code page, and a final newline.
+pub fn plain_text_job(profile: &PrinterProfile, codepage: CodePage, text: &str) -> Vec<u8> {
+    let encoder = ReceiptEncoder::new(profile, codepage);
+    let mut data = Vec::new();
+    encoder.initialize(&mut data);
+    encoder.encode_text(&mut data, text);
+    data
+}
+
+/// The text converted to the printer's code page, after selecting that code
+/// page. Unlike `plain_text_job` the printer is not reset and no newline is
+/// added: the text is printed as-is, in the middle of whatever came before.
+pub fn raw_text_job(profile: &PrinterProfile, codepage: CodePage, text: &str) -> Vec<u8> {
+    let mut data = profile.command_set.commands().select_codepage(codepage);
+    data.extend_from_slice(&codepage.encode(&layout::printable(text)));
+    data
+}
+
+impl<'a> ReceiptEncoder<'a> {
+    pub fn new(profile: &'a PrinterProfile, codepage: CodePage) -> Self {
+        Self { profile, codepage, commands: profile.command_set.commands() }
+    }
+
+    /// An encoder set up for the given printer's profile and code page.
+    pub fn for_printer(printer: &'a dyn Printer) -> Self {
+        Self::new(printer.profile(), printer.codepage())
+    }
+
+    /// Resets the printer and selects our code page.
+    pub fn initialize(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.commands.initialize(self.codepage));
+    }
+
+    /// Appends plain text in the printer's code page, making sure it ends
+    /// with a newline so that the printer actually prints the last line.
+    /// Control characters are dropped (see `layout::printable`).
+    pub fn encode_text(&self, buffer: &mut Vec<u8>, text: &str) {
+        buffer.extend_from_slice(&self.codepage.encode(&layout::printable(text)));
+        if !text.ends_with('\n') {
+            buffer.push(b'\n');
+        }
+    }
+
+    /// Returns the "feed & cut" sequence for the given cut mode.
+    /// If the cutter can't do the requested cut, the other one is used;
+    /// printers without a cutter only feed the paper up to the tear bar.
+    pub fn cut(&self, mode: CutMode) -> Vec<u8> {
+        let mode = match mode {
+            CutMode::Full if self.profile.full_cut => Some(CutMode::Full),
+            CutMode::Partial if self.profile.partial_cut => Some(CutMode::Partial),
+            _ if self.profile.partial_cut => Some(CutMode::Partial),
+            _ if self.profile.full_cut => Some(CutMode::Full),
+            _ => None,
+        };
+        self.commands.cut(mode, self.profile.cut_feed_lines)
+    }
+
+    /// Returns the pulse that opens a cash drawer plugged into the printer.
+    pub fn drawer_kick(&self, pin: DrawerPin) -> Vec<u8> {
+        self.commands.drawer_kick(pin)
+    }
+
+    /// Compiles a list of document blocks into printer bytes.
+    /// Fails if a block contains invalid data (e.g. an image that can't be decoded).
+    pub fn encode_document(&self, buffer: &mut Vec<u8>, blocks: &[Block]) -> Result<(), ServiceError> {
+        for block in blocks {
+            match block {
+                Block::Text { text, style } => {
+                    let lines = layout::wrap(&self.printed_text(text), self.line_width(style));
+                    self.encode_styled_lines(buffer, &lines, style);
+                }
+                Block::Rule { character } => {
+                    let line = character.to_string().repeat(self.profile.chars_per_line);
+                    self.encode_text(buffer, &line);
+                }
+                Block::Row { left, right, style } => {
+                    let lines = layout::two_columns(&self.printed_text(left), &self.printed_text(right), self.line_width(style));
+                    // Rows always span the full width, alignment would break the columns
+                    let style = TextStyle { align: Align::Left, ..style.clone() };
+                    self.encode_styled_lines(buffer, &lines, &style);
+                }
+                Block::Image { data, width, dither, threshold, align } => {
+                    let dots_per_line = self.profile.dots_per_line;
+                    if *width == Some(0) {
+                        return Err(ServiceError::InvalidCommand("Image width must be at least 1 dot".into()));
+                    }
+                    // Never wider than the paper
+                    let max_width = width.unwrap_or(dots_per_line).min(dots_per_line);
+                    let bitmap = raster::decode_image(data, max_width, *dither, *threshold)?;
+                    self.encode_bitmap(buffer, &bitmap, *align)?;
+                }
+                Block::Barcode { symbology, data, height, width, hri, align } => {
+                    barcode::validate(*symbology, data)?;
+                    buffer.extend_from_slice(&self.commands.justify(*align));
+                    buffer.extend_from_slice(&self.commands.barcode(*symbology, data, *height, *width, *hri));
+                    buffer.push(b'\n');
+                    buffer.extend_from_slice(&self.commands.justify(Align::Left));
+                }
+                Block::Qrcode { data, module_size, error_correction, align } => {
+                    barcode::validate_qr(data, *error_correction)?;
+                    if self.profile.native_qr {
+                        buffer.extend_from_slice(&self.commands.justify(*align));
+                        buffer.extend_from_slice(&self.commands.qrcode(data, *module_size, *error_correction));
+                        buffer.push(b'\n');
+                        buffer.extend_from_slice(&self.commands.justify(Align::Left));
+                    } else {
+                        // No native QR support: draw the QR code ourselves and print it as a picture
+                        let bitmap = barcode::qr_bitmap(data, *error_correction, (*module_size).max(1) as usize, self.profile.dots_per_line)?;
+                        self.encode_bitmap(buffer, &bitmap, *align)?;
+                    }
+                }
+                Block::Feed { lines } => {
+                    buffer.extend_from_slice(&self.commands.feed(*lines));
+                }
+                Block::Cut { mode } => {
+                    buffer.extend_from_slice(&self.cut(*mode));
+                }
+                Block::Drawer { pin } => {
+                    buffer.extend_from_slice(&self.drawer_kick(*pin));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints a 1-bit image using the printer's graphics command.
+    pub fn encode_bitmap(&self, buffer: &mut Vec<u8>, bitmap: &Bitmap, align: Align) -> Result<(), ServiceError> {
+        if self.profile.graphics == GraphicsMode::None {
+            return Err(ServiceError::InvalidCommand(format!(
+                "Printer profile '{}' cannot print images", self.profile.name
+            )));
+        }
+
+        // Alignment is done by adding white space on the left, because
+        // not every printer applies its alignment command to images.
+        let free = self.profile.dots_per_line.saturating_sub(bitmap.width);
+        let offset = match align {
+            Align::Left => 0,
+            Align::Center => free / 2,
+            Align::Right => free,
+        };
+        let placed = bitmap.with_left_margin(offset, bitmap.width + offset);
+
+        buffer.extend_from_slice(&self.commands.image(&placed, self.profile.graphics));
+        Ok(())
+    }
+
+    // Client text as it will be printed: without control characters and with
+    // the characters our code page lacks already replaced, so that the
+    // layout counts "€" as the 3 columns of "EUR" on CP437.
+    fn printed_text(&self, text: &str) -> String {
+        self.codepage.transliterate(&layout::printable(text))
+    }
+
+    // Font B is narrower, and double-width characters take two columns.
+    fn line_width(&self, style: &TextStyle) -> usize {
+        let chars_per_line = match style.font {
+            Font::A => self.profile.chars_per_line,
+            Font::B => self.profile.chars_per_line_font_b,
+        };
+        if style.double_width {
+            (chars_per_line / 2).max(1)
+        } else {
+            chars_per_line
+        }
+    }
+
+    fn encode_styled_lines(&self, buffer: &mut Vec<u8>, lines: &[String], style: &TextStyle) {
+        // 1. Switch the styles on
+        buffer.extend_from_slice(&self.commands.set_style(style));
+
+        // 2. The text itself
+        for line in lines {
+            self.encode_text(buffer, line);
+        }
+
+        // 3. Reset everything so the next block starts from a clean state
+        buffer.extend_from_slice(&self.commands.reset_style());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The printed line that starts with `start`
+    fn line_starting_with<'b>(data: &'b [u8], start: &[u8]) -> &'b [u8] {
+        let from = data.windows(start.len()).position(|w| w == start).expect("line not printed");
+        let len = data[from..].iter().position(|b| *b == b'\n').unwrap();
+        &data[from..from + len]
+    }
+
+    fn encode(codepage: CodePage, document: serde_json::Value) -> (PrinterProfile, Vec<u8>) {
+        let profile = PrinterProfile::default();
+        let blocks: Vec<Block> = serde_json::from_value(document).unwrap();
+        let mut data = Vec::new();
+        ReceiptEncoder::new(&profile, codepage).encode_document(&mut data, &blocks).unwrap();
+        (profile, data)
+    }
+
+    #[test]
+    fn rows_are_measured_as_printed() {
+        let document = serde_json::json!([{ "type": "row", "left": "Menu", "right": "12.50 €" }]);
+
+        // "€" becomes "EUR": 3 columns
+        let (profile, data) = encode(CodePage::Cp437, document.clone());
+        let line = line_starting_with(&data, b"Menu");
+        assert_eq!(line.len(), profile.chars_per_line);
+        assert!(line.ends_with(b" 12.50 EUR"));
+
+        // "€" is one byte of CP858
+        let (profile, data) = encode(CodePage::Cp858, document);
+        let line = line_starting_with(&data, b"Menu");
+        assert_eq!(line.len(), profile.chars_per_line);
+        assert!(line.ends_with(&[b' ', b'1', b'2', b'.', b'5', b'0', b' ', 0xD5]));
+    }
+
+    #[test]
+    fn wraps_text_as_printed() {
+        // 40 characters, 80 once printed
+        let text = "1 € ".repeat(10);
+        let (profile, data) = encode(CodePage::Cp437, serde_json::json!([{ "type": "text", "text": text }]));
+        let first = line_starting_with(&data, b"1 EUR");
+        assert!(first.len() <= profile.chars_per_line);
+        assert!(first.len() > profile.chars_per_line - 6);
+        assert_eq!(data.windows(5).filter(|w| *w == b"1 EUR").count(), 10);
+    }
+}
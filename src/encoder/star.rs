@@ -0,0 +1,247 @@
+use crate::encoder::codepage::CodePage;
+use crate::encoder::commands::PrinterCommands;
+use crate::encoder::document::{Align, CutMode, DrawerPin, Font, TextStyle};
+use crate::encoder::escpos::GraphicsMode;
+use crate::encoder::raster::Bitmap;
+use crate::encoder::barcode::{HriPosition, QrErrorCorrection, Symbology};
+
+// =========================================================================
+// STAR LINE MODE COMMAND SET
+// =========================================================================
+// Star TSP100/TSP650 (and most Star receipt printers) in "Star Line Mode".
+// Many commands look like ESC/POS but mean something else: e.g. `ESC d n`
+// is a CUT on Star but a FEED on Epson, so sending Epson bytes to a Star
+// printer gives blank paper and no cut.
+
+pub struct StarLine;
+
+impl PrinterCommands for StarLine {
+    fn initialize(&self, codepage: CodePage) -> Vec<u8> {
+        // ESC @ - Initialize
+        let mut data = vec![0x1B, 0x40];
+        data.extend_from_slice(&self.select_codepage(codepage));
+        data
+    }
+
+    // ESC GS t n - Select code page (only if Star has this one)
+    fn select_codepage(&self, codepage: CodePage) -> Vec<u8> {
+        match codepage.star_table() {
+            Some(n) => vec![0x1B, 0x1D, 0x74, n],
+            None => Vec::new(),
+        }
+    }
+
+    // ESC GS a n - Alignment (0 = left, 1 = center, 2 = right)
+    fn justify(&self, align: Align) -> Vec<u8> {
+        let n = match align {
+            Align::Left => 0,
+            Align::Center => 1,
+            Align::Right => 2,
+        };
+        vec![0x1B, 0x1D, 0x61, n]
+    }
+
+    fn set_style(&self, style: &TextStyle) -> Vec<u8> {
+        let mut data = self.justify(style.align);
+        // ESC RS F n - Font (0 = Font A 12x24, 1 = Font B 9x24)
+        data.extend_from_slice(&[0x1B, 0x1E, 0x46, (style.font == Font::B) as u8]);
+        // ESC E / ESC F - Emphasized on / off
+        data.extend_from_slice(if style.bold { &[0x1B, 0x45] } else { &[0x1B, 0x46] });
+        // ESC - n - Underline
+        data.extend_from_slice(&[0x1B, 0x2D, style.underline as u8]);
+        // ESC 4 / ESC 5 - White/black reverse on / off
+        data.extend_from_slice(if style.invert { &[0x1B, 0x34] } else { &[0x1B, 0x35] });
+        // ESC i n1 n2 - Character expansion (n1 = height - 1, n2 = width - 1)
+        data.extend_from_slice(&[0x1B, 0x69, style.double_height as u8, style.double_width as u8]);
+        data
+    }
+
+    fn reset_style(&self) -> Vec<u8> {
+        vec![
+            0x1B, 0x1D, 0x61, 0x00,       // Left align
+            0x1B, 0x1E, 0x46, 0x00,       // Font A
+            0x1B, 0x46,                   // Bold off
+            0x1B, 0x2D, 0x00,             // Underline off
+            0x1B, 0x35,                   // Reverse off
+            0x1B, 0x69, 0x00, 0x00,       // Normal size
+        ]
+    }
+
+    // ESC a n - Feed n lines
+    fn feed(&self, lines: u8) -> Vec<u8> {
+        vec![0x1B, 0x61, lines]
+    }
+
+    // ESC d n - Cut (2 = feed to cutter & full cut, 3 = feed to cutter & partial cut).
+    // Star feeds to the cutter position by itself, so `feed_lines` is only
+    // used for printers without a cutter (to reach the tear bar).
+    fn cut(&self, mode: Option<CutMode>, feed_lines: u8) -> Vec<u8> {
+        match mode {
+            Some(CutMode::Full) => vec![0x1B, 0x64, 0x02],
+            Some(CutMode::Partial) => vec![0x1B, 0x64, 0x03],
+            None => self.feed(feed_lines),
+        }
+    }
+
+    // ESC BEL n1 n2 - Set the pulse timing (n1 x 10ms on, n2 x 10ms delay,
+    // 200ms each like the Star default), then BEL fires drawer 1 and SUB drawer 2.
+    fn drawer_kick(&self, pin: DrawerPin) -> Vec<u8> {
+        let fire = match pin {
+            DrawerPin::Pin2 => 0x07,
+            DrawerPin::Pin5 => 0x1A,
+        };
+        vec![0x1B, 0x07, 0x14, 0x14, fire]
+    }
+
+    // Line Mode only has column graphics: ESC k n1 n2 prints a 24-dot
+    // high stripe (3 bytes per column, like ESC * 33 on Epson).
+    fn image(&self, bitmap: &Bitmap, _graphics: GraphicsMode) -> Vec<u8> {
+        // ESC 0 - 3mm (24 dot) line spacing, so the stripes touch each other
+        let mut data = vec![0x1B, 0x30];
+
+        for stripe_start in (0..bitmap.height).step_by(24) {
+            data.extend_from_slice(&[0x1B, 0x6B, (bitmap.width & 0xFF) as u8, (bitmap.width >> 8) as u8]);
+            for x in 0..bitmap.width {
+                data.extend_from_slice(&bitmap.column_bytes(x, stripe_start));
+            }
+            data.push(b'\n');
+        }
+
+        // ESC z 1 - Back to the default 4mm line spacing
+        data.extend_from_slice(&[0x1B, 0x7A, 0x01]);
+        data
+    }
+
+    // ESC b n1 n2 n3 n4 d1...dk RS - Print a barcode
+    fn barcode(&self, symbology: Symbology, data: &str, height: u8, width: u8, hri: HriPosition) -> Vec<u8> {
+        let n1 = match symbology {
+            Symbology::UpcA => 0x31,
+            Symbology::Ean8 => 0x32,
+            Symbology::Ean13 => 0x33,
+            Symbology::Code39 => 0x34,
+            Symbology::Itf => 0x35,
+            Symbology::Code128 => 0x36,
+            Symbology::Codabar => 0x38,
+        };
+        // Star only prints the digits below the bars (2), or not at all (1)
+        let n2 = match hri {
+            HriPosition::None => 0x31,
+            _ => 0x32,
+        };
+        // Narrowest bar: 1 = 2 dots, 2 = 3 dots, 3 = 4 dots
+        let n3 = match width {
+            0..=2 => 0x31,
+            3 => 0x32,
+            _ => 0x33,
+        };
+
+        let mut out = vec![0x1B, 0x62, n1, n2, n3, height.max(1)];
+        out.extend_from_slice(data.as_bytes());
+        out.push(0x1E); // RS - end of data
+        out
+    }
+
+    // ESC GS y - QR code: set model, error correction and cell size,
+    // store the data, then print it.
+    fn qrcode(&self, data: &str, module_size: u8, error_correction: QrErrorCorrection) -> Vec<u8> {
+        let level = match error_correction {
+            QrErrorCorrection::L => 0,
+            QrErrorCorrection::M => 1,
+            QrErrorCorrection::Q => 2,
+            QrErrorCorrection::H => 3,
+        };
+        let len = data.len();
+
+        let mut out = vec![
+            0x1B, 0x1D, 0x79, 0x53, 0x30, 0x02,                   // Model 2
+            0x1B, 0x1D, 0x79, 0x53, 0x31, level,                  // Error correction
+            0x1B, 0x1D, 0x79, 0x53, 0x32, module_size.clamp(1, 8), // Cell size
+            0x1B, 0x1D, 0x79, 0x44, 0x31, 0x00, (len & 0xFF) as u8, (len >> 8) as u8,
+        ];
+        out.extend_from_slice(data.as_bytes());
+        out.extend_from_slice(&[0x1B, 0x1D, 0x79, 0x50]); // Print
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn initializes_with_the_star_code_page() {
+        assert_eq!(StarLine.initialize(CodePage::Cp858), [0x1B, 0x40, 0x1B, 0x1D, 0x74, 4]);
+        assert_eq!(StarLine.initialize(CodePage::Cp1252), [0x1B, 0x40, 0x1B, 0x1D, 0x74, 32]);
+        // Star has no ISO-8859-15: nothing is selected
+        assert!(StarLine.select_codepage(CodePage::Iso8859_15).is_empty());
+    }
+
+    #[test]
+    fn styles() {
+        let style = TextStyle {
+            align: Align::Right,
+            font: Font::B,
+            bold: true,
+            underline: true,
+            double_width: true,
+            double_height: false,
+            invert: true,
+        };
+        assert_eq!(StarLine.set_style(&style), [
+            0x1B, 0x1D, 0x61, 0x02,
+            0x1B, 0x1E, 0x46, 0x01,
+            0x1B, 0x45,
+            0x1B, 0x2D, 0x01,
+            0x1B, 0x34,
+            0x1B, 0x69, 0x00, 0x01,
+        ]);
+        // The default style is what reset_style switches back to
+        assert_eq!(StarLine.set_style(&TextStyle::default()), StarLine.reset_style());
+    }
+
+    #[test]
+    fn cuts_feeds_and_kicks_the_drawer() {
+        // ESC d is a cut on Star (a feed on Epson)
+        assert_eq!(StarLine.cut(Some(CutMode::Full), 4), [0x1B, 0x64, 0x02]);
+        assert_eq!(StarLine.cut(Some(CutMode::Partial), 4), [0x1B, 0x64, 0x03]);
+        assert_eq!(StarLine.cut(None, 4), [0x1B, 0x61, 0x04]);
+        assert_eq!(StarLine.drawer_kick(DrawerPin::Pin2), [0x1B, 0x07, 0x14, 0x14, 0x07]);
+        assert_eq!(StarLine.drawer_kick(DrawerPin::Pin5), [0x1B, 0x07, 0x14, 0x14, 0x1A]);
+    }
+
+    #[test]
+    fn prints_images_in_24_dot_stripes() {
+        let mut bitmap = Bitmap::new(2, 25);
+        bitmap.set(0, 0, true);
+        bitmap.set(1, 24, true);
+        assert_eq!(StarLine.image(&bitmap, GraphicsMode::Raster), [
+            0x1B, 0x30,
+            0x1B, 0x6B, 0x02, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, b'\n',
+            0x1B, 0x6B, 0x02, 0x00, 0x00, 0x00, 0x00, 0x80, 0x00, 0x00, b'\n',
+            0x1B, 0x7A, 0x01,
+        ]);
+    }
+
+    #[test]
+    fn barcodes() {
+        let mut expected = vec![0x1B, 0x62, 0x33, 0x32, 0x31, 80];
+        expected.extend_from_slice(b"4006381333931");
+        expected.push(0x1E);
+        assert_eq!(StarLine.barcode(Symbology::Ean13, "4006381333931", 80, 2, HriPosition::Below), expected);
+
+        // No digits, 4-dot bars, height at least 1
+        assert_eq!(StarLine.barcode(Symbology::Code128, "A1", 0, 5, HriPosition::None),
+            [0x1B, 0x62, 0x36, 0x31, 0x33, 1, b'A', b'1', 0x1E]);
+    }
+
+    #[test]
+    fn qr_codes() {
+        assert_eq!(StarLine.qrcode("hi", 12, QrErrorCorrection::Q), [
+            0x1B, 0x1D, 0x79, 0x53, 0x30, 0x02,
+            0x1B, 0x1D, 0x79, 0x53, 0x31, 0x02,
+            0x1B, 0x1D, 0x79, 0x53, 0x32, 0x08,
+            0x1B, 0x1D, 0x79, 0x44, 0x31, 0x00, 0x02, 0x00, b'h', b'i',
+            0x1B, 0x1D, 0x79, 0x50,
+        ]);
+    }
+}
@@ -0,0 +1,113 @@
+use serde::Serialize;
+use crate::errors::ServiceError;
+
+// =========================================================================
+// PRINTER STATUS (DLE EOT)
+// =========================================================================
+// ESC/POS printers answer "DLE EOT n" with one status byte, even while they
+// are busy or offline. We ask all four questions in one go:
+//   n = 1: printer status (online, drawer sensor)
+//   n = 2: why it is offline (cover open, paper end, error)
+//   n = 3: which error (cutter, unrecoverable, auto-recoverable)
+//   n = 4: paper roll sensor (near end, out)
+
+/// The four DLE EOT queries, sent together. The printer replies with 4 bytes.
+pub const DLE_EOT_QUERY: [u8; 12] = [
+    0x10, 0x04, 0x01,
+    0x10, 0x04, 0x02,
+    0x10, 0x04, 0x03,
+    0x10, 0x04, 0x04,
+];
+pub const DLE_EOT_REPLY_LEN: usize = 4;
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrinterStatus {
+    pub online: bool,
+    pub cover_open: bool,
+    pub paper_near_end: bool,
+    pub paper_out: bool,
+    pub cutter_error: bool,
+    // Level of pin 3 of the drawer port. Whether "high" means open or closed
+    // depends on the drawer model (most report high when closed).
+    pub drawer_sensor_high: bool,
+    // Errors that clear by themselves (e.g. print head too hot)
+    pub recoverable_error: bool,
+    // Errors that need the printer to be switched off and on
+    pub unrecoverable_error: bool,
+}
+
+impl PrinterStatus {
+    /// A printer that is ready to print.
+    pub fn ready() -> Self {
+        Self { online: true, drawer_sensor_high: true, ..Self::default() }
+    }
+
+    /// Decodes the 4 reply bytes of `DLE_EOT_QUERY`.
+    pub fn decode(reply: &[u8]) -> Result<Self, ServiceError> {
+        // Every status byte has bit 1 and bit 4 set and bits 0 and 7 cleared.
+        // Anything else means the printer didn't understand DLE EOT.
+        let valid = reply.len() == DLE_EOT_REPLY_LEN && reply.iter().all(|b| b & 0x93 == 0x12);
+        if !valid {
+            return Err(ServiceError::DeviceError(format!("Unexpected status reply from printer: {:02X?}", reply)));
+        }
+        let (printer, offline, error, paper) = (reply[0], reply[1], reply[2], reply[3]);
+
+        Ok(Self {
+            online: printer & 0x08 == 0,
+            drawer_sensor_high: printer & 0x04 != 0,
+            cover_open: offline & 0x04 != 0,
+            cutter_error: error & 0x08 != 0,
+            unrecoverable_error: error & 0x20 != 0,
+            recoverable_error: error & 0x40 != 0,
+            paper_near_end: paper & 0x0C != 0,
+            paper_out: paper & 0x60 != 0 || offline & 0x20 != 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_printer() {
+        let status = PrinterStatus::decode(&[0x12, 0x12, 0x12, 0x12]).unwrap();
+        assert_eq!(status, PrinterStatus { online: true, ..PrinterStatus::default() });
+        let status = PrinterStatus::decode(&[0x16, 0x12, 0x12, 0x12]).unwrap();
+        assert_eq!(status, PrinterStatus::ready());
+    }
+
+    #[test]
+    fn decodes_each_bit() {
+        let decode = |reply: [u8; 4]| PrinterStatus::decode(&reply).unwrap();
+        assert!(!decode([0x1A, 0x12, 0x12, 0x12]).online);
+        assert!(decode([0x12, 0x16, 0x12, 0x12]).cover_open);
+        // Offline because the paper ran out
+        assert!(decode([0x12, 0x32, 0x12, 0x12]).paper_out);
+        assert!(decode([0x12, 0x12, 0x1A, 0x12]).cutter_error);
+        assert!(decode([0x12, 0x12, 0x32, 0x12]).unrecoverable_error);
+        assert!(decode([0x12, 0x12, 0x52, 0x12]).recoverable_error);
+        // Near-end and paper-end sensors each have two bits
+        assert!(decode([0x12, 0x12, 0x12, 0x1E]).paper_near_end);
+        assert!(decode([0x12, 0x12, 0x12, 0x16]).paper_near_end);
+        assert!(decode([0x12, 0x12, 0x12, 0x72]).paper_out);
+        assert!(decode([0x12, 0x12, 0x12, 0x32]).paper_out);
+        assert!(!decode([0x12, 0x12, 0x12, 0x1E]).paper_out);
+    }
+
+    #[test]
+    fn rejects_replies_that_are_not_dle_eot() {
+        for reply in [
+            &[][..],
+            &[0x12, 0x12, 0x12][..],
+            &[0x12, 0x12, 0x12, 0x12, 0x12][..],
+            &[0x00, 0x12, 0x12, 0x12][..],
+            &[0x12, 0x12, 0x12, 0x13][..],
+            &[0x92, 0x12, 0x12, 0x12][..],
+            &[0x12, 0x02, 0x12, 0x12][..],
+            &[0x12, 0x12, 0x10, 0x12][..],
+        ] {
+            assert!(PrinterStatus::decode(reply).is_err(), "{:02X?} was accepted", reply);
+        }
+    }
+}
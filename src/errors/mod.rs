@@ -15,6 +15,11 @@ pub enum ServiceError {
     #[error("Device error: {0}")]
     DeviceError(String),
 
+    // The printer got only part of the data: the job must not be sent again,
+    // or the part that did print comes out twice
+    #[error("Partly sent: {0}")]
+    PartiallySent(String),
+
     #[error("Authentication failed: {0}")]
     AuthError(String),
 
@@ -25,6 +30,34 @@ pub enum ServiceError {
     InternalError(String),
 }
 
+/// The kind of a `ServiceError`, without its message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Config,
+    Io,
+    NotFound,
+    Device,
+    PartiallySent,
+    Auth,
+    InvalidCommand,
+    Internal,
+}
+
+impl ServiceError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ServiceError::ConfigError(_) => ErrorKind::Config,
+            ServiceError::IoError(_) => ErrorKind::Io,
+            ServiceError::DeviceNotFound(_) => ErrorKind::NotFound,
+            ServiceError::DeviceError(_) => ErrorKind::Device,
+            ServiceError::PartiallySent(_) => ErrorKind::PartiallySent,
+            ServiceError::AuthError(_) => ErrorKind::Auth,
+            ServiceError::InvalidCommand(_) => ErrorKind::InvalidCommand,
+            ServiceError::InternalError(_) => ErrorKind::Internal,
+        }
+    }
+}
+
 impl From<std::io::Error> for ServiceError {
     fn from(err: std::io::Error) -> Self {
         ServiceError::IoError(err.to_string())
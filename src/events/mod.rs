@@ -0,0 +1,99 @@
+use std::collections::BTreeSet;
+use serde::Serialize;
+
+// =========================================================================
+// DEVICE EVENTS
+// =========================================================================
+// Things that happen on their own, without the POS asking: a job finished,
+// the kitchen printer ran out of paper, the drawer was left open...
+// Every event has a topic. Clients "subscribe" to the topics they care about
+// and the service pushes matching events to them over the WebSocket.
+//
+// e.g. { "event": "printer_status", "topic": "printer.status", "device_id": "printer_kitchen",
+//        "data": { "online": true, "paper_out": true, ... } }
+
+pub const PRINTER_STATUS: &str = "printer.status";
+pub const DRAWER_STATE: &str = "drawer.state";
+pub const JOB_COMPLETED: &str = "job.completed";
+pub const JOB_FAILED: &str = "job.failed";
+// For barcode scanners; published through `DeviceManager::publish`
+pub const SCANNER_DATA: &str = "scanner.data";
+// A client sent a wrong token (admin keys only; device_id is empty)
+pub const AUTH_FAILED: &str = "security.auth_failed";
+// A serial/USB device was plugged back in or unplugged
+pub const DEVICE_CONNECTED: &str = "device.connected";
+pub const DEVICE_DISCONNECTED: &str = "device.disconnected";
+
+pub const TOPICS: [&str; 8] = [PRINTER_STATUS, DRAWER_STATE, JOB_COMPLETED, JOB_FAILED, SCANNER_DATA, AUTH_FAILED, DEVICE_CONNECTED, DEVICE_DISCONNECTED];
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Event {
+    // Topic with "_" instead of ".", e.g. "job_completed"
+    pub event: String,
+    pub topic: String,
+    pub device_id: String,
+    pub data: serde_json::Value,
+}
+
+impl Event {
+    pub fn new(topic: &str, device_id: &str, data: impl Serialize) -> Self {
+        Self {
+            event: topic.replace('.', "_"),
+            topic: topic.to_string(),
+            device_id: device_id.to_string(),
+            data: serde_json::to_value(data).unwrap_or_default(),
+        }
+    }
+}
+
+/// Does `pattern` select `topic`?
+/// "*" selects everything, "job.*" every job topic, anything else must be equal.
+pub fn topic_matches(pattern: &str, topic: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some("") => true,
+        Some(prefix) => prefix.ends_with('.') && topic.starts_with(prefix),
+        None => pattern == topic,
+    }
+}
+
+/// The topics one client wants to hear about.
+#[derive(Debug, Clone, Default)]
+pub struct Subscriptions {
+    patterns: BTreeSet<String>,
+}
+
+impl Subscriptions {
+    // New connections get job results, like before subscriptions existed
+    pub fn new() -> Self {
+        let mut subscriptions = Self::default();
+        subscriptions.patterns.insert("job.*".into());
+        subscriptions
+    }
+
+    /// Adds topics. Fails (and adds nothing) if a pattern matches no known topic.
+    pub fn subscribe(&mut self, patterns: &[String]) -> Result<(), String> {
+        let unknown: Vec<&str> = patterns.iter()
+            .filter(|pattern| !TOPICS.iter().any(|topic| topic_matches(pattern, topic)))
+            .map(String::as_str)
+            .collect();
+        if !unknown.is_empty() {
+            return Err(format!("Unknown topic(s): {}. Known topics: {}", unknown.join(", "), TOPICS.join(", ")));
+        }
+        self.patterns.extend(patterns.iter().cloned());
+        Ok(())
+    }
+
+    pub fn unsubscribe(&mut self, patterns: &[String]) {
+        for pattern in patterns {
+            self.patterns.remove(pattern);
+        }
+    }
+
+    pub fn wants(&self, topic: &str) -> bool {
+        self.patterns.iter().any(|pattern| topic_matches(pattern, topic))
+    }
+
+    pub fn topics(&self) -> Vec<&str> {
+        self.patterns.iter().map(String::as_str).collect()
+    }
+}
@@ -1,34 +1,30 @@
 use async_trait::async_trait;
 use crate::hardware::traits::Display;
 use crate::errors::ServiceError;
-use tokio_serial::SerialPortBuilderExt;
-use tokio::io::AsyncWriteExt;
+use std::sync::Arc;
+use crate::hardware::transport::{serial::{SerialOptions, SerialTransport, SharedSerialTransport}, Transport};
 use tracing::info;
 
-#[cfg(unix)]
-const DEFAULT_TTY: &str = "/dev/ttyUSB0";
-#[cfg(windows)]
-const DEFAULT_TTY: &str = "COM1";
-
 pub struct SerialDisplay {
     id: String,
     port_name: String,
-    baud_rate: u32,
+    // Same port handling as serial printers (kept open, line settings)
+    transport: SharedSerialTransport,
 }
 
 impl SerialDisplay {
-    pub fn new(id: String, port_name: String, baud_rate: u32) -> Self {
-        Self { id, port_name, baud_rate }
+    pub fn new(id: String, port_name: String, baud_rate: u32, options: SerialOptions) -> Self {
+        let transport = Arc::new(SerialTransport::with_options(port_name.clone(), baud_rate, options));
+        Self { id, port_name, transport }
     }
 
-    async fn send_command(&self, data: &[u8]) -> Result<(), ServiceError> {
-         let mut port = tokio_serial::new(&self.port_name, self.baud_rate)
-            .open_native_async()
-            .map_err(|e| ServiceError::IoError(format!("Failed to open display port {}: {}", self.port_name, e)))?;
+    // For the hotplug watcher
+    pub fn transport(&self) -> SharedSerialTransport {
+        self.transport.clone()
+    }
 
-        port.write_all(data).await
-            .map_err(|e| ServiceError::IoError(format!("Failed to write to display: {}", e)))?;
-        Ok(())
+    async fn send_command(&self, data: &[u8]) -> Result<(), ServiceError> {
+        self.transport.send(data).await
     }
 }
 
@@ -2,17 +2,22 @@ use async_trait::async_trait;
 use crate::hardware::traits::Drawer;
 use crate::hardware::traits::Printer;
 use crate::errors::ServiceError;
+use crate::encoder::ReceiptEncoder;
+use crate::encoder::document::DrawerPin;
 use std::sync::Arc;
 use tracing::info;
 
 pub struct PrinterDrivenDrawer {
     id: String,
     printer: Arc<dyn Printer>,
+    // The printer reads the drawer's sensor (pin 3). Most drawers report
+    // "high" when closed; set this for the ones that report "high" when open.
+    open_when_high: bool,
 }
 
 impl PrinterDrivenDrawer {
-    pub fn new(id: String, printer: Arc<dyn Printer>) -> Self {
-        Self { id, printer }
+    pub fn new(id: String, printer: Arc<dyn Printer>, open_when_high: bool) -> Self {
+        Self { id, printer, open_when_high }
     }
 }
 
@@ -24,7 +29,17 @@ impl Drawer for PrinterDrivenDrawer {
         // ESC p m t1 t2
         // m=0 (pin 2), t1=50ms, t2=50ms
         // Decimal: 27 112 0 25 250
-        let kick_command = b"\x1B\x70\x00\x19\xFA";
-        self.printer.print_raw(kick_command).await
+        // Star printers get ESC BEL n1 n2 + BEL instead (from the printer's command set).
+        let kick_command = ReceiptEncoder::for_printer(self.printer.as_ref()).drawer_kick(DrawerPin::Pin2);
+        self.printer.print_raw(&kick_command).await
+    }
+
+    fn supports_state(&self) -> bool {
+        self.printer.supports_status()
+    }
+
+    async fn is_open(&self) -> Result<bool, ServiceError> {
+        let status = self.printer.status().await?;
+        Ok(status.drawer_sensor_high == self.open_when_high)
     }
 }
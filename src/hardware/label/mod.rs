@@ -0,0 +1,41 @@
+pub mod transport_printer;
+
+use async_trait::async_trait;
+use crate::hardware::traits::LabelPrinter;
+use crate::encoder::{label, Label, LabelLanguage};
+use crate::errors::ServiceError;
+use tracing::info;
+
+// Resolution used when a label printer doesn't set `dpi` (most desktop label printers)
+pub const DEFAULT_DPI: u32 = 203;
+
+pub struct MockLabelPrinter {
+    id: String,
+    language: LabelLanguage,
+    dpi: u32,
+}
+
+impl MockLabelPrinter {
+    pub fn new(id: String, language: LabelLanguage, dpi: u32) -> Self {
+        Self { id, language, dpi }
+    }
+}
+
+#[async_trait]
+impl LabelPrinter for MockLabelPrinter {
+    fn language(&self) -> LabelLanguage {
+        self.language
+    }
+
+    async fn print_label(&self, label: &Label) -> Result<(), ServiceError> {
+        // Still render the label, so layout errors show up during testing
+        let data = label::encode(self.language, label, self.dpi)?;
+        info!("[LabelPrinter {}] Label ({} bytes of {:?}):\n{}", self.id, data.len(), self.language, String::from_utf8_lossy(&data));
+        Ok(())
+    }
+
+    async fn print_raw(&self, data: &[u8]) -> Result<(), ServiceError> {
+        info!("[LabelPrinter {}] Raw: {} bytes", self.id, data.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+use async_trait::async_trait;
+use crate::hardware::traits::LabelPrinter;
+use crate::hardware::transport::Transport;
+use crate::encoder::{label, Label, LabelLanguage};
+use crate::errors::ServiceError;
+use tracing::info;
+
+// A label printer reached through any transport (network, serial...).
+// The label is rendered the same way for every connection; only the way
+// the bytes travel differs.
+pub struct TransportLabelPrinter {
+    id: String,
+    language: LabelLanguage,
+    dpi: u32,
+    transport: Box<dyn Transport>,
+}
+
+impl TransportLabelPrinter {
+    pub fn new(id: String, language: LabelLanguage, dpi: u32, transport: Box<dyn Transport>) -> Self {
+        Self { id, language, dpi, transport }
+    }
+}
+
+#[async_trait]
+impl LabelPrinter for TransportLabelPrinter {
+    fn language(&self) -> LabelLanguage {
+        self.language
+    }
+
+    async fn print_label(&self, label: &Label) -> Result<(), ServiceError> {
+        info!("[LabelPrinter {}] Printing {} label(s) as {:?}", self.id, label.copies, self.language);
+        let data = label::encode(self.language, label, self.dpi)?;
+        self.transport.send(&data).await
+    }
+
+    async fn print_raw(&self, data: &[u8]) -> Result<(), ServiceError> {
+        info!("[LabelPrinter {}] Sending raw data", self.id);
+        self.transport.send(data).await
+    }
+}
@@ -2,3 +2,5 @@ pub mod traits;
 pub mod printer;
 pub mod drawer;
 pub mod display;
+pub mod label;
+pub mod transport;
@@ -0,0 +1,64 @@
+use async_trait::async_trait;
+use crate::hardware::traits::Printer;
+use crate::errors::ServiceError;
+use crate::encoder::{receipt, CodePage, CommandSet, ReceiptEncoder};
+use crate::encoder::document::CutMode;
+use crate::hardware::printer::profile::PrinterProfile;
+use crate::hardware::transport::{device_file::DeviceFileTransport, Transport};
+use tracing::{debug, info};
+
+// A printer written to through a file, e.g. "/dev/usb/lp0" on Linux
+pub struct DeviceFilePrinter {
+    id: String,
+    path: String,
+    transport: DeviceFileTransport,
+    profile: PrinterProfile,
+    codepage: CodePage,
+}
+
+impl DeviceFilePrinter {
+    pub fn new(id: String, path: String, profile: PrinterProfile, codepage: CodePage) -> Self {
+        let transport = DeviceFileTransport::new(path.clone());
+        Self { id, path, transport, profile, codepage }
+    }
+
+    async fn send_data(&self, data: &[u8]) -> Result<(), ServiceError> {
+        self.transport.send(data).await
+    }
+}
+
+#[async_trait]
+impl Printer for DeviceFilePrinter {
+    fn profile(&self) -> &PrinterProfile {
+        &self.profile
+    }
+
+    fn codepage(&self) -> CodePage {
+        self.codepage
+    }
+
+    async fn print_text(&self, text: &str) -> Result<(), ServiceError> {
+        info!("[DeviceFilePrinter {}] Printing text to {}", self.id, self.path);
+        let data = receipt::plain_text_job(&self.profile, self.codepage, text);
+        self.send_data(&data).await
+    }
+
+    async fn cut_paper(&self) -> Result<(), ServiceError> {
+        info!("[DeviceFilePrinter {}] Cutting paper", self.id);
+        let data = ReceiptEncoder::new(&self.profile, self.codepage).cut(CutMode::Partial);
+        self.send_data(&data).await
+    }
+
+    async fn print_raw(&self, data: &[u8]) -> Result<(), ServiceError> {
+        info!("[DeviceFilePrinter {}] Sending raw data", self.id);
+        self.send_data(data).await
+    }
+    async fn query_raw(&self, data: &[u8], reply_len: usize) -> Result<Vec<u8>, ServiceError> {
+        debug!("[DeviceFilePrinter {}] Querying printer", self.id);
+        self.transport.request(data, reply_len).await
+    }
+    // Plain files and FIFOs can't answer status queries
+    fn supports_status(&self) -> bool {
+        self.profile.command_set == CommandSet::EscPos && self.transport.can_answer()
+    }
+}
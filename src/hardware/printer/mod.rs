@@ -1,24 +1,33 @@
+pub mod device_file;
 pub mod network;
 pub mod serial;
 pub mod windows;
+pub mod profile;
 
 use async_trait::async_trait;
 use crate::hardware::traits::Printer;
 use crate::errors::ServiceError;
-use tracing::info;
+use crate::encoder::PrinterStatus;
+use profile::PrinterProfile;
+use tracing::{debug, info};
 
 pub struct MockPrinter {
     id: String,
+    profile: PrinterProfile,
 }
 
 impl MockPrinter {
-    pub fn new(id: String) -> Self {
-        Self { id }
+    pub fn new(id: String, profile: PrinterProfile) -> Self {
+        Self { id, profile }
     }
 }
 
 #[async_trait]
 impl Printer for MockPrinter {
+    fn profile(&self) -> &PrinterProfile {
+        &self.profile
+    }
+
     async fn print_text(&self, text: &str) -> Result<(), ServiceError> {
         info!("[Printer {}] Printing: {}", self.id, text);
         Ok(())
@@ -33,4 +42,13 @@ impl Printer for MockPrinter {
         info!("[Printer {}] Raw data: {:?}", self.id, data);
         Ok(())
     }
+
+    fn supports_status(&self) -> bool {
+        true
+    }
+
+    async fn status(&self) -> Result<PrinterStatus, ServiceError> {
+        debug!("[Printer {}] Status requested", self.id);
+        Ok(PrinterStatus::ready())
+    }
 }
@@ -1,48 +1,51 @@
 use async_trait::async_trait;
 use crate::hardware::traits::Printer;
 use crate::errors::ServiceError;
-use tokio::net::TcpStream;
-use tokio::io::AsyncWriteExt;
-use tracing::info;
+use crate::encoder::{receipt, CodePage, CommandSet, ReceiptEncoder};
+use crate::encoder::document::CutMode;
+use crate::hardware::printer::profile::PrinterProfile;
+use crate::hardware::transport::{network::{NetworkOptions, NetworkTransport}, Transport};
+use tracing::{debug, info};
 
 pub struct NetworkPrinter {
     id: String,
     address: String,
+    transport: NetworkTransport,
+    profile: PrinterProfile,
+    codepage: CodePage,
 }
 
 impl NetworkPrinter {
-    pub fn new(id: String, address: String) -> Self {
-        Self { id, address }
+    pub fn new(id: String, address: String, profile: PrinterProfile, codepage: CodePage, options: NetworkOptions) -> Self {
+        let transport = NetworkTransport::with_options(address.clone(), options);
+        Self { id, address, transport, profile, codepage }
     }
 
-    // This helper function opens a real network connection to the printer
-    // and sends the raw bytes (commands) to it.
+    // Sends the raw bytes (commands) over the (kept open) TCP connection to the printer.
     async fn send_data(&self, data: &[u8]) -> Result<(), ServiceError> {
-        // 1. Connect to the printer IP address (e.g., 192.168.1.200:9100)
-        let mut stream = TcpStream::connect(&self.address).await
-            .map_err(|e| ServiceError::IoError(format!("Failed to connect to printer at {}: {}", self.address, e)))?;
-        
-        // 2. Send the actual data bytes
-        stream.write_all(data).await
-            .map_err(|e| ServiceError::IoError(format!("Failed to write to printer: {}", e)))?;
-            
-        Ok(())
+        self.transport.send(data).await
     }
 }
 
 #[async_trait]
 impl Printer for NetworkPrinter {
+    fn profile(&self) -> &PrinterProfile {
+        &self.profile
+    }
+
+    fn codepage(&self) -> CodePage {
+        self.codepage
+    }
+
     // This function formats simple text and sends it to the printer.
     async fn print_text(&self, text: &str) -> Result<(), ServiceError> {
         info!("[NetworkPrinter {}] Printing text to {}", self.id, self.address);
         // Simple text print command usually involves initializing printer then sending text
         // ESC @ = Initialize Printer Command
-        // Text = The raw text content
+        // ESC t n = Select our code page (ESC GS t n on Star printers)
+        // Text = The text converted from UTF-8 to the printer's code page
         // LF = Line Feed (Input key essentially) to make it print
-        let mut data = Vec::new();
-        data.extend_from_slice(b"\x1B\x40"); // ESC @ (Init)
-        data.extend_from_slice(text.as_bytes());
-        data.extend_from_slice(b"\n");       // Newline
+        let data = receipt::plain_text_job(&self.profile, self.codepage, text);
         
         self.send_data(&data).await
     }
@@ -50,9 +53,9 @@ impl Printer for NetworkPrinter {
     // This sends the specific command code to cut the paper.
     async fn cut_paper(&self) -> Result<(), ServiceError> {
         info!("[NetworkPrinter {}] Cutting paper", self.id);
-        // GS V 66 0 is a standard ESC/POS command for "Cut Paper"
-        let data = b"\x1D\x56\x42\x00"; 
-        self.send_data(data).await
+        // Feed past the blade, then GS V 66 0 (or whatever cut the profile supports)
+        let data = ReceiptEncoder::new(&self.profile, self.codepage).cut(CutMode::Partial);
+        self.send_data(&data).await
     }
 
     // This allows the POS to send raw hexadecimal commands directly
@@ -61,4 +64,11 @@ impl Printer for NetworkPrinter {
         info!("[NetworkPrinter {}] Sending raw data", self.id);
         self.send_data(data).await
     }
+    async fn query_raw(&self, data: &[u8], reply_len: usize) -> Result<Vec<u8>, ServiceError> {
+        debug!("[NetworkPrinter {}] Querying printer", self.id);
+        self.transport.request(data, reply_len).await
+    }
+    fn supports_status(&self) -> bool {
+        self.profile.command_set == CommandSet::EscPos
+    }
 }
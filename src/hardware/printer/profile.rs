@@ -0,0 +1,218 @@
+use serde::Deserialize;
+use crate::encoder::{CodePage, CommandSet};
+use crate::encoder::escpos::GraphicsMode;
+
+// =========================================================================
+// PRINTER PROFILES
+// =========================================================================
+// A profile describes what a printer model can do: how wide the paper is,
+// how many characters fit on a line, which cut and picture commands it
+// understands, and which code pages it has. The encoder reads it to wrap
+// text and pick commands, so a 58mm and an 80mm printer get different bytes.
+//
+// Choose one per printer in config.toml with `profile = "generic_58mm"` and
+// adjust single values with `overrides = { chars_per_line = 30 }`.
+
+// Used when a printer doesn't name a profile
+pub const DEFAULT_PROFILE: &str = "epson_tm_t88";
+
+#[derive(Debug, Clone)]
+pub struct PrinterProfile {
+    pub name: String,
+    // The printer's command language (ESC/POS or Star Line Mode)
+    pub command_set: CommandSet,
+    pub paper_width_mm: u32,
+    // Printable width in dots (for pictures)
+    pub dots_per_line: usize,
+    // Characters per line with Font A (normal) and Font B (condensed)
+    pub chars_per_line: usize,
+    pub chars_per_line_font_b: usize,
+    // Which cuts the cutter can do. Printers without a cutter have neither.
+    pub full_cut: bool,
+    pub partial_cut: bool,
+    // Lines to feed so the last printed line clears the cutter blade
+    pub cut_feed_lines: u8,
+    pub graphics: GraphicsMode,
+    // Whether the printer understands GS ( k (QR codes drawn by the printer)
+    pub native_qr: bool,
+    pub code_pages: Vec<CodePage>,
+}
+
+// Per-printer adjustments on top of a named profile
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ProfileOverrides {
+    pub command_set: Option<CommandSet>,
+    pub paper_width_mm: Option<u32>,
+    pub dots_per_line: Option<usize>,
+    pub chars_per_line: Option<usize>,
+    pub chars_per_line_font_b: Option<usize>,
+    pub full_cut: Option<bool>,
+    pub partial_cut: Option<bool>,
+    pub cut_feed_lines: Option<u8>,
+    pub graphics: Option<GraphicsMode>,
+    pub native_qr: Option<bool>,
+}
+
+impl Default for PrinterProfile {
+    fn default() -> Self {
+        Self::named(DEFAULT_PROFILE).expect("default profile exists")
+    }
+}
+
+impl PrinterProfile {
+    /// Names of the built-in profiles.
+    pub const NAMES: &'static [&'static str] = &[
+        "epson_tm_t88",
+        "epson_tm_t20",
+        "generic_80mm",
+        "generic_58mm",
+        "xprinter_80",
+        "xprinter_58",
+        "star_tsp100",
+        "star_tsp650",
+    ];
+
+    /// Looks up a built-in profile by name.
+    pub fn named(name: &str) -> Option<Self> {
+        use CodePage::*;
+        let common_pages = vec![Cp437, Cp850, Cp852, Cp858, Cp860, Cp863, Cp865, Cp866, Cp1252];
+        let all_pages = vec![
+            Cp437, Cp850, Cp852, Cp858, Cp860, Cp863, Cp865, Cp866,
+            Cp1250, Cp1251, Cp1252, Iso8859_2, Iso8859_7, Iso8859_15,
+        ];
+        let mut clone_pages = common_pages.clone();
+        clone_pages.extend([Cp1250, Cp1251]);
+        let star_pages = vec![Cp437, Cp850, Cp852, Cp858, Cp860, Cp863, Cp865, Cp866, Cp1250, Cp1251, Cp1252];
+
+        let profile = match name {
+            // Epson TM-T88 (IV/V/VI): 80mm paper at 180 dpi
+            "epson_tm_t88" => Self {
+                name: name.into(),
+                command_set: CommandSet::EscPos,
+                paper_width_mm: 80,
+                dots_per_line: 512,
+                chars_per_line: 42,
+                chars_per_line_font_b: 56,
+                full_cut: true,
+                partial_cut: true,
+                cut_feed_lines: 3,
+                graphics: GraphicsMode::Raster,
+                native_qr: true,
+                code_pages: all_pages,
+            },
+            // Epson TM-T20 / TM-m30: 80mm paper at 203 dpi
+            "epson_tm_t20" => Self {
+                name: name.into(),
+                command_set: CommandSet::EscPos,
+                paper_width_mm: 80,
+                dots_per_line: 576,
+                chars_per_line: 48,
+                chars_per_line_font_b: 64,
+                full_cut: true,
+                partial_cut: true,
+                cut_feed_lines: 3,
+                graphics: GraphicsMode::Raster,
+                native_qr: true,
+                code_pages: all_pages,
+            },
+            "generic_80mm" => Self {
+                name: name.into(),
+                command_set: CommandSet::EscPos,
+                paper_width_mm: 80,
+                dots_per_line: 576,
+                chars_per_line: 48,
+                chars_per_line_font_b: 64,
+                full_cut: false,
+                partial_cut: true,
+                cut_feed_lines: 4,
+                graphics: GraphicsMode::Raster,
+                native_qr: true,
+                code_pages: common_pages,
+            },
+            // Cheap 58mm printers usually have a tear bar instead of a cutter
+            // and no QR code support
+            "generic_58mm" => Self {
+                name: name.into(),
+                command_set: CommandSet::EscPos,
+                paper_width_mm: 58,
+                dots_per_line: 384,
+                chars_per_line: 32,
+                chars_per_line_font_b: 42,
+                full_cut: false,
+                partial_cut: false,
+                cut_feed_lines: 4,
+                graphics: GraphicsMode::Raster,
+                native_qr: false,
+                code_pages: common_pages,
+            },
+            "xprinter_80" => Self {
+                name: name.into(),
+                command_set: CommandSet::EscPos,
+                paper_width_mm: 80,
+                dots_per_line: 576,
+                chars_per_line: 48,
+                chars_per_line_font_b: 64,
+                full_cut: true,
+                partial_cut: true,
+                cut_feed_lines: 4,
+                graphics: GraphicsMode::Raster,
+                native_qr: true,
+                code_pages: clone_pages,
+            },
+            "xprinter_58" => Self {
+                name: name.into(),
+                command_set: CommandSet::EscPos,
+                paper_width_mm: 58,
+                dots_per_line: 384,
+                chars_per_line: 32,
+                chars_per_line_font_b: 42,
+                full_cut: false,
+                partial_cut: false,
+                cut_feed_lines: 4,
+                graphics: GraphicsMode::Column,
+                native_qr: false,
+                code_pages: clone_pages,
+            },
+            // Star TSP100 (futurePRNT) and TSP650 in Star Line Mode
+            "star_tsp100" | "star_tsp650" => Self {
+                name: name.into(),
+                command_set: CommandSet::Star,
+                paper_width_mm: 80,
+                dots_per_line: 576,
+                chars_per_line: 48,
+                chars_per_line_font_b: 64,
+                full_cut: true,
+                partial_cut: true,
+                cut_feed_lines: 3,
+                graphics: GraphicsMode::Column,
+                native_qr: true,
+                code_pages: star_pages,
+            },
+            _ => return None,
+        };
+        Some(profile)
+    }
+
+    /// Applies the values set in config.toml on top of this profile.
+    pub fn with_overrides(mut self, overrides: &ProfileOverrides) -> Self {
+        if let Some(v) = overrides.command_set { self.command_set = v; }
+        if let Some(v) = overrides.paper_width_mm { self.paper_width_mm = v; }
+        if let Some(v) = overrides.dots_per_line { self.dots_per_line = v; }
+        if let Some(v) = overrides.chars_per_line { self.chars_per_line = v; }
+        if let Some(v) = overrides.chars_per_line_font_b { self.chars_per_line_font_b = v; }
+        if let Some(v) = overrides.full_cut { self.full_cut = v; }
+        if let Some(v) = overrides.partial_cut { self.partial_cut = v; }
+        if let Some(v) = overrides.cut_feed_lines { self.cut_feed_lines = v; }
+        if let Some(v) = overrides.graphics { self.graphics = v; }
+        if let Some(v) = overrides.native_qr { self.native_qr = v; }
+        self
+    }
+
+    pub fn supports_codepage(&self, codepage: CodePage) -> bool {
+        let has_command = match self.command_set {
+            CommandSet::EscPos => true,
+            CommandSet::Star => codepage.star_table().is_some(),
+        };
+        has_command && self.code_pages.contains(&codepage)
+    }
+}
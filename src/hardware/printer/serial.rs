@@ -1,63 +1,71 @@
 use async_trait::async_trait;
 use crate::hardware::traits::Printer;
 use crate::errors::ServiceError;
-use tokio_serial::SerialPortBuilderExt;
-use tokio::io::AsyncWriteExt;
-use tracing::info;
-
-#[cfg(unix)]
-const DEFAULT_TTY: &str = "/dev/ttyUSB0";
-#[cfg(windows)]
-const DEFAULT_TTY: &str = "COM1";
+use crate::encoder::{receipt, CodePage, CommandSet, ReceiptEncoder};
+use crate::encoder::document::CutMode;
+use crate::hardware::printer::profile::PrinterProfile;
+use std::sync::Arc;
+use crate::hardware::transport::{serial::{SerialOptions, SerialTransport, SharedSerialTransport}, Transport};
+use tracing::{debug, info};
 
 pub struct SerialPrinter {
     id: String,
     port_name: String,
-    baud_rate: u32,
+    transport: SharedSerialTransport,
+    profile: PrinterProfile,
+    codepage: CodePage,
 }
 
 impl SerialPrinter {
-    pub fn new(id: String, port_name: String, baud_rate: u32) -> Self {
-        Self { id, port_name, baud_rate }
+    pub fn new(id: String, port_name: String, baud_rate: u32, profile: PrinterProfile, codepage: CodePage, options: SerialOptions) -> Self {
+        let transport = Arc::new(SerialTransport::with_options(port_name.clone(), baud_rate, options));
+        Self { id, port_name, transport, profile, codepage }
     }
 
-    async fn send_data(&self, data: &[u8]) -> Result<(), ServiceError> {
-        let mut port = tokio_serial::new(&self.port_name, self.baud_rate)
-            .open_native_async()
-            .map_err(|e| ServiceError::IoError(format!("Failed to open serial port {}: {}", self.port_name, e)))?;
-
-        // On Windows specifically, and some serial devices, setting DTR/RTS is sometimes needed, 
-        // effectively resetting the line or asserting ready.
-        // For basic ESC/POS, defaults are often fine, but we'll stick to defaults unless issues arise.
-        
-        port.write_all(data).await
-            .map_err(|e| ServiceError::IoError(format!("Failed to write to serial printer: {}", e)))?;
+    // For the hotplug watcher
+    pub fn transport(&self) -> SharedSerialTransport {
+        self.transport.clone()
+    }
 
-        Ok(())
+    async fn send_data(&self, data: &[u8]) -> Result<(), ServiceError> {
+        self.transport.send(data).await
     }
 }
 
 #[async_trait]
 impl Printer for SerialPrinter {
+    fn profile(&self) -> &PrinterProfile {
+        &self.profile
+    }
+
+    fn codepage(&self) -> CodePage {
+        self.codepage
+    }
+
     async fn print_text(&self, text: &str) -> Result<(), ServiceError> {
         info!("[SerialPrinter {}] Printing text to {}", self.id, self.port_name);
         
-        let mut data = Vec::new();
-        data.extend_from_slice(b"\x1B\x40"); // ESC @ (Init)
-        data.extend_from_slice(text.as_bytes());
-        data.extend_from_slice(b"\n");
+        // ESC @ (Init) + ESC t n (Code page) + encoded text + newline
+        let data = receipt::plain_text_job(&self.profile, self.codepage, text);
         
         self.send_data(&data).await
     }
 
     async fn cut_paper(&self) -> Result<(), ServiceError> {
         info!("[SerialPrinter {}] Cutting paper", self.id);
-        let data = b"\x1D\x56\x42\x00"; 
-        self.send_data(data).await
+        let data = ReceiptEncoder::new(&self.profile, self.codepage).cut(CutMode::Partial);
+        self.send_data(&data).await
     }
 
     async fn print_raw(&self, data: &[u8]) -> Result<(), ServiceError> {
         info!("[SerialPrinter {}] Sending raw data", self.id);
         self.send_data(data).await
     }
+    async fn query_raw(&self, data: &[u8], reply_len: usize) -> Result<Vec<u8>, ServiceError> {
+        debug!("[SerialPrinter {}] Querying printer", self.id);
+        self.transport.request(data, reply_len).await
+    }
+    fn supports_status(&self) -> bool {
+        self.profile.command_set == CommandSet::EscPos
+    }
 }
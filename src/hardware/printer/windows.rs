@@ -1,7 +1,12 @@
 use async_trait::async_trait;
 use crate::errors::ServiceError;
 use crate::hardware::traits::Printer;
+use crate::encoder::{receipt, CodePage, ReceiptEncoder};
+use crate::encoder::document::CutMode;
+use crate::hardware::printer::profile::PrinterProfile;
+#[cfg(windows)]
 use std::ffi::c_void;
+#[cfg(windows)]
 use tracing::{info, error};
 
 #[cfg(windows)]
@@ -16,11 +21,13 @@ use windows::core::{PCWSTR, PWSTR};
 
 pub struct WindowsPrinter {
     printer_name: String,
+    profile: PrinterProfile,
+    codepage: CodePage,
 }
 
 impl WindowsPrinter {
-    pub fn new(printer_name: String) -> Self {
-        Self { printer_name }
+    pub fn new(printer_name: String, profile: PrinterProfile, codepage: CodePage) -> Self {
+        Self { printer_name, profile, codepage }
     }
 
     #[cfg(windows)]
@@ -111,21 +118,27 @@ impl Drop for PrinterHandleGuard {
 
 #[async_trait]
 impl Printer for WindowsPrinter {
+    fn profile(&self) -> &PrinterProfile {
+        &self.profile
+    }
+
+    fn codepage(&self) -> CodePage {
+        self.codepage
+    }
+
     async fn print_text(&self, text: &str) -> Result<(), ServiceError> {
-        self.print_raw(text.as_bytes()).await
+        // The spooler is in RAW mode, so the text must already be in the printer's code page
+        let data = receipt::raw_text_job(&self.profile, self.codepage, text);
+        self.print_raw(&data).await
     }
 
     async fn cut_paper(&self) -> Result<(), ServiceError> {
          // Some printers need a paper feed before cutting so the text clears the cutter blade.
          // We send:
-         // 1. ESC d 3 (Feed 3 lines) -> [0x1B, 0x64, 0x03]
-         // 2. GS V 66 0 (Feed & Cut) -> [0x1D, 0x56, 0x42, 0x00]
-         // 3. Fallback GS V 0 (Simple Cut) -> [0x1D, 0x56, 0x00]
-         
-         let cut_sequence = [
-             0x1B, 0x64, 0x03,       // Feed 3 lines
-             0x1D, 0x56, 0x42, 0x00, // Primary Cut Command
-         ];
+         // 1. ESC d n (Feed n lines, from the profile)
+         // 2. GS V 66 0 (Feed & Cut), or the cut the profile supports
+         // (Star printers get their own ESC d n cut instead)
+         let cut_sequence = ReceiptEncoder::new(&self.profile, self.codepage).cut(CutMode::Partial);
          
          self.print_raw(&cut_sequence).await
     }
@@ -135,9 +148,11 @@ impl Printer for WindowsPrinter {
         // to prevent stopping the whole server while printing.
         let data = data.to_vec();
         let name = self.printer_name.clone();
+        let profile = self.profile.clone();
+        let codepage = self.codepage;
 
         let res = tokio::task::spawn_blocking(move || {
-            let printer = WindowsPrinter::new(name);
+            let printer = WindowsPrinter::new(name, profile, codepage);
             printer.send_raw_to_printer(&data)
         }).await;
         
@@ -1,17 +1,68 @@
 use async_trait::async_trait;
 use crate::errors::ServiceError;
+use crate::encoder::{CodePage, CommandSet, Label, LabelLanguage};
+use crate::encoder::status::{self, PrinterStatus};
+use crate::hardware::printer::profile::PrinterProfile;
 
 #[async_trait]
 pub trait Printer: Send + Sync {
+    // What this printer model can do (paper width, cutter, pictures...).
+    // Encoders use it to lay out text and pick commands.
+    fn profile(&self) -> &PrinterProfile;
+
+    // The character code page configured for this printer.
+    // Text must be encoded with it before being sent through print_raw.
+    fn codepage(&self) -> CodePage {
+        CodePage::default()
+    }
     async fn print_text(&self, text: &str) -> Result<(), ServiceError>;
     async fn cut_paper(&self) -> Result<(), ServiceError>;
     // Add raw for bytes ESC/POS
     async fn print_raw(&self, data: &[u8]) -> Result<(), ServiceError>;
+
+    // Sends raw bytes and reads `reply_len` bytes of answer.
+    // Only bidirectional connections (network, serial) can do this.
+    async fn query_raw(&self, _data: &[u8], _reply_len: usize) -> Result<Vec<u8>, ServiceError> {
+        Err(ServiceError::DeviceError("This printer connection can't read replies from the printer".into()))
+    }
+
+    // Can `status()` work on this printer? (needs a connection that reads replies)
+    fn supports_status(&self) -> bool {
+        false
+    }
+
+    // Asks the printer for its real-time status (DLE EOT 1-4).
+    async fn status(&self) -> Result<PrinterStatus, ServiceError> {
+        if self.profile().command_set != CommandSet::EscPos {
+            return Err(ServiceError::InvalidCommand("Status queries are only available for ESC/POS printers".into()));
+        }
+        let reply = self.query_raw(&status::DLE_EOT_QUERY, status::DLE_EOT_REPLY_LEN).await?;
+        PrinterStatus::decode(&reply)
+    }
+}
+
+#[async_trait]
+pub trait LabelPrinter: Send + Sync {
+    // The printer's command language (ZPL, TSPL or EPL)
+    fn language(&self) -> LabelLanguage;
+    // Renders the label layout in the printer's language and prints it.
+    async fn print_label(&self, label: &Label) -> Result<(), ServiceError>;
+    // Sends ready-made ZPL/TSPL/EPL (e.g. a template from the label designer)
+    async fn print_raw(&self, data: &[u8]) -> Result<(), ServiceError>;
 }
 
 #[async_trait]
 pub trait Drawer: Send + Sync {
     async fn open(&self) -> Result<(), ServiceError>;
+
+    // Can the drawer tell whether it is open? (needs a sensor the service can read)
+    fn supports_state(&self) -> bool {
+        false
+    }
+
+    async fn is_open(&self) -> Result<bool, ServiceError> {
+        Err(ServiceError::DeviceError("This drawer has no readable sensor".into()))
+    }
 }
 
 #[async_trait]
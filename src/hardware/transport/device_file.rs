@@ -0,0 +1,234 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use crate::errors::ServiceError;
+use crate::hardware::transport::{partly_sent, Transport, REPLY_TIMEOUT};
+use tokio::sync::Mutex;
+use tracing::debug;
+
+// Writes straight to a file, e.g. "/dev/usb/lp0": on Linux, USB printers
+// handled by the "usblp" driver show up as /dev/usb/lp0, /dev/usb/lp1...
+// Also works with a plain file (jobs are added at the end, handy to see what
+// would have been printed) or a FIFO (a program reading it gets every job).
+//
+// The file is opened for every job and locked meanwhile (flock), so two
+// programs never mix their jobs on one printer. Status queries read the
+// answer from the same file; only devices can answer (usblp does, if the
+// printer has a bidirectional USB interface), not plain files or FIFOs.
+// A job that stops halfway (printer busy for too long, unplugged...) fails
+// with `PartiallySent`, so the queue doesn't print the first part twice.
+
+// How long we wait for another program to release the file
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+// How long one job may take (the printer may still be busy with the last one)
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+// Pause before trying again while the device is busy
+const RETRY_PAUSE: Duration = Duration::from_millis(20);
+
+pub struct DeviceFileTransport {
+    path: PathBuf,
+    // One job at a time from this service
+    busy: Mutex<()>,
+}
+
+impl DeviceFileTransport {
+    pub fn new(path: String) -> Self {
+        Self { path: path.into(), busy: Mutex::new(()) }
+    }
+
+    /// Can the file answer status queries? (only devices can)
+    pub fn can_answer(&self) -> bool {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+            std::fs::metadata(&self.path).is_ok_and(|meta| meta.file_type().is_char_device())
+        }
+        #[cfg(not(unix))]
+        false
+    }
+
+    // File access blocks, so it runs on its own thread
+    async fn run<T: Send + 'static>(&self, job: impl FnOnce(&Path) -> Result<T, ServiceError> + Send + 'static) -> Result<T, ServiceError> {
+        let _busy = self.busy.lock().await;
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || job(&path)).await
+            .map_err(|e| ServiceError::IoError(format!("Failed to use {}: {}", self.path.display(), e)))?
+    }
+}
+
+// Opens and locks the file
+fn open(path: &Path, read: bool) -> Result<File, ServiceError> {
+    let mut options = OpenOptions::new();
+    // Jobs go to the end of a plain file instead of overwriting it
+    options.read(read).append(true);
+    // open() and write() must never hang (a FIFO nobody reads, a busy printer): we wait ourselves
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_NONBLOCK | libc::O_NOCTTY);
+    }
+    let file = options.open(path).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => ServiceError::DeviceError(format!("{} does not exist (printer unplugged or switched off?)", path.display())),
+        #[cfg(unix)]
+        _ if e.raw_os_error() == Some(libc::ENXIO) => ServiceError::DeviceError(format!("Nothing is reading from {}", path.display())),
+        _ => ServiceError::IoError(format!("Failed to open {}: {}", path.display(), e)),
+    })?;
+
+    let deadline = Instant::now() + LOCK_TIMEOUT;
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(file),
+            Err(TryLockError::WouldBlock) if Instant::now() < deadline => std::thread::sleep(RETRY_PAUSE),
+            Err(TryLockError::WouldBlock) => {
+                return Err(ServiceError::DeviceError(format!("{} is used by another program", path.display())));
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(ServiceError::IoError(format!("Failed to lock {}: {}", path.display(), e)));
+            }
+        }
+    }
+}
+
+fn write_all(file: &mut File, path: &Path, data: &[u8]) -> Result<(), ServiceError> {
+    let deadline = Instant::now() + WRITE_TIMEOUT;
+    let mut written = 0;
+    while written < data.len() {
+        let error = match file.write(&data[written..]) {
+            Ok(0) => ServiceError::IoError(format!("Failed to write to {}: nothing was written", path.display())),
+            Ok(count) => {
+                written += count;
+                continue;
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) if e.kind() == ErrorKind::WouldBlock && Instant::now() < deadline => {
+                std::thread::sleep(RETRY_PAUSE);
+                continue;
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => ServiceError::DeviceError(format!(
+                "{} did not take the job within {}s (printer busy, offline or out of paper?)", path.display(), WRITE_TIMEOUT.as_secs())),
+            Err(e) => ServiceError::IoError(format!("Failed to write to {}: {}", path.display(), e)),
+        };
+        return Err(partly_sent(error, written, data.len()));
+    }
+    Ok(())
+}
+
+fn read_reply(file: &mut File, path: &Path, reply_len: usize) -> Result<Vec<u8>, ServiceError> {
+    let deadline = Instant::now() + REPLY_TIMEOUT;
+    let mut reply = vec![0u8; reply_len];
+    let mut received = 0;
+    while received < reply_len {
+        match file.read(&mut reply[received..]) {
+            Ok(read) if read > 0 => {
+                received += read;
+                continue;
+            }
+            // Nothing yet
+            Ok(_) => {}
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {}
+            Err(e) => return Err(ServiceError::IoError(format!("Failed to read from {}: {}", path.display(), e))),
+        }
+        if Instant::now() >= deadline {
+            return Err(ServiceError::DeviceError(format!("Device at {} did not answer", path.display())));
+        }
+        std::thread::sleep(RETRY_PAUSE);
+    }
+    Ok(reply)
+}
+
+#[async_trait]
+impl Transport for DeviceFileTransport {
+    async fn send(&self, data: &[u8]) -> Result<(), ServiceError> {
+        let data = data.to_vec();
+        self.run(move |path| {
+            let mut file = open(path, false)?;
+            write_all(&mut file, path, &data)?;
+            debug!("Wrote {} bytes to {}", data.len(), path.display());
+            Ok(())
+        }).await
+    }
+
+    async fn request(&self, data: &[u8], reply_len: usize) -> Result<Vec<u8>, ServiceError> {
+        if !self.can_answer() {
+            return Err(ServiceError::DeviceError(format!("{} can't answer (only devices can, not files or FIFOs)", self.path.display())));
+        }
+        let data = data.to_vec();
+        self.run(move |path| {
+            let mut file = open(path, true)?;
+            // Forget anything the printer sent on its own, so it isn't taken for the answer
+            let mut old = [0u8; 256];
+            while matches!(file.read(&mut old), Ok(read) if read > 0) {}
+            write_all(&mut file, path, &data)?;
+            read_reply(&mut file, path, reply_len)
+        }).await
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    fn transport(path: &Path) -> DeviceFileTransport {
+        DeviceFileTransport::new(path.to_string_lossy().into_owned())
+    }
+
+    fn mkfifo(path: &Path) {
+        let name = CString::new(path.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(name.as_ptr(), 0o600) }, 0);
+    }
+
+    #[tokio::test]
+    async fn appends_jobs_to_a_plain_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("printer.bin");
+        std::fs::write(&path, b"old ").unwrap();
+
+        let printer = transport(&path);
+        printer.send(b"first ").await.unwrap();
+        printer.send(b"second").await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"old first second");
+    }
+
+    #[tokio::test]
+    async fn writes_jobs_to_a_fifo() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("printer.fifo");
+        mkfifo(&path);
+
+        // Nobody reading: fails at once instead of hanging
+        let error = transport(&path).send(b"job").await.unwrap_err();
+        assert!(error.to_string().contains("Nothing is reading"), "{}", error);
+
+        let mut reader = OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK).open(&path).unwrap();
+        transport(&path).send(b"\x1b@hello").await.unwrap();
+        let mut received = Vec::new();
+        reader.read_to_end(&mut received).unwrap();
+        assert_eq!(received, b"\x1b@hello");
+    }
+
+    #[tokio::test]
+    async fn waits_for_another_program_then_gives_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("printer.bin");
+        let other = File::create(&path).unwrap();
+        other.lock().unwrap();
+
+        let error = transport(&path).send(b"job").await.unwrap_err();
+        assert!(error.to_string().contains("used by another program"), "{}", error);
+        assert_eq!(std::fs::read(&path).unwrap(), b"");
+    }
+
+    #[tokio::test]
+    async fn plain_files_cannot_answer() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let printer = transport(file.path());
+        assert!(!printer.can_answer());
+        let error = printer.request(b"\x10\x04\x01", 1).await.unwrap_err();
+        assert!(error.to_string().contains("can't answer"), "{}", error);
+    }
+}
@@ -0,0 +1,50 @@
+pub mod device_file;
+pub mod network;
+pub mod ports;
+pub mod serial;
+
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use crate::errors::ServiceError;
+
+// =========================================================================
+// TRANSPORTS
+// =========================================================================
+// A transport only moves bytes to a device (TCP socket, COM port...). It
+// doesn't know what the bytes mean, so receipt printers and label printers
+// can share the same connection code.
+
+// How long we wait for a device to answer a request
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Turns a failed write into `PartiallySent` when some of the `len` bytes got
+// out: part of the job may be on the paper already, so the queue must not
+// send it again or it prints twice.
+pub fn partly_sent(error: ServiceError, written: usize, len: usize) -> ServiceError {
+    if written == 0 {
+        return error;
+    }
+    ServiceError::PartiallySent(format!("{} ({} of {} bytes were sent)", error, written, len))
+}
+
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn send(&self, data: &[u8]) -> Result<(), ServiceError>;
+
+    // Sends `data` and reads exactly `reply_len` bytes back (e.g. a status query).
+    // Fails if the device doesn't answer within REPLY_TIMEOUT.
+    async fn request(&self, data: &[u8], reply_len: usize) -> Result<Vec<u8>, ServiceError>;
+}
+
+// A shared transport (e.g. a serial port the hotplug watcher also looks after)
+#[async_trait]
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    async fn send(&self, data: &[u8]) -> Result<(), ServiceError> {
+        (**self).send(data).await
+    }
+
+    async fn request(&self, data: &[u8], reply_len: usize) -> Result<Vec<u8>, ServiceError> {
+        (**self).request(data, reply_len).await
+    }
+}
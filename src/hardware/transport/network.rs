@@ -0,0 +1,218 @@
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use serde::Deserialize;
+use socket2::{SockRef, TcpKeepalive};
+use crate::errors::ServiceError;
+use crate::hardware::transport::{partly_sent, Transport, REPLY_TIMEOUT};
+use tokio::net::TcpStream;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+// Raw TCP printing (port 9100, a.k.a. "JetDirect" / "RAW")
+//
+// The connection stays open between jobs, so a receipt at rush hour doesn't
+// wait for a new TCP handshake. It is closed after `idle_timeout_secs`
+// without jobs (most printers accept only one connection at a time), and
+// opened again transparently if the printer dropped it in the meantime.
+// A job is only sent again if none of it was sent; a job that broke off
+// halfway fails with `PartiallySent`, which the queue never retries.
+// Status queries use the open connection if there is one, but don't keep
+// it (or a new one) open: the status monitor asks every few seconds.
+// Closing is always a clean half-close: we say we're done and give the
+// printer a moment to finish, instead of resetting the connection (which
+// makes some printers lose the end of the last job).
+
+// How long we wait for the printer to close its side after we closed ours
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+// TCP settings of a network printer, e.g. network = { idle_timeout_secs = 0 }
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct NetworkOptions {
+    pub connect_timeout_ms: u64, // Give up connecting after this long (default: 3000)
+    pub write_timeout_ms: u64,   // Give up sending one job after this long (default: 10000)
+    pub idle_timeout_secs: u64,  // Close the connection after this long without jobs (default: 30, 0 = after every job)
+    pub keepalive_secs: u64,     // Check an idle connection is still alive every... (default: 15, 0 = off)
+}
+
+impl Default for NetworkOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout_ms: 3000,
+            write_timeout_ms: 10_000,
+            idle_timeout_secs: 30,
+            keepalive_secs: 15,
+        }
+    }
+}
+
+pub struct NetworkTransport {
+    address: String,
+    options: NetworkOptions,
+    // Shared with the timer that closes it when idle
+    connection: Arc<Mutex<Connection>>,
+}
+
+#[derive(Default)]
+struct Connection {
+    stream: Option<TcpStream>,
+    // Counts the jobs, so the idle timer knows whether another job came in meanwhile
+    jobs: u64,
+}
+
+impl NetworkTransport {
+    pub fn new(address: String) -> Self {
+        Self::with_options(address, NetworkOptions::default())
+    }
+
+    pub fn with_options(address: String, options: NetworkOptions) -> Self {
+        Self { address, options, connection: Arc::new(Mutex::new(Connection::default())) }
+    }
+
+    async fn connect(&self) -> Result<TcpStream, ServiceError> {
+        let timeout = Duration::from_millis(self.options.connect_timeout_ms);
+        let stream = tokio::time::timeout(timeout, TcpStream::connect(&self.address)).await
+            .map_err(|_| ServiceError::IoError(format!("Failed to connect to printer at {}: timed out", self.address)))?
+            .map_err(|e| ServiceError::IoError(format!("Failed to connect to printer at {}: {}", self.address, e)))?;
+
+        // Small jobs and status queries go out at once instead of waiting to fill a packet
+        let _ = stream.set_nodelay(true);
+        // Notices a printer that was switched off while the connection was idle
+        if self.options.keepalive_secs > 0 {
+            let keepalive = TcpKeepalive::new().with_time(Duration::from_secs(self.options.keepalive_secs));
+            if let Err(e) = SockRef::from(&stream).set_tcp_keepalive(&keepalive) {
+                warn!("Cannot enable TCP keep-alive for printer at {}: {}", self.address, e);
+            }
+        }
+        debug!("Connected to printer at {}", self.address);
+        Ok(stream)
+    }
+
+    // Sends `data` on the open connection (or a new one). If the printer dropped
+    // the open connection since the last job and none of `data` got through,
+    // tries once more on a new one. Returns the stream and whether it was reused.
+    async fn send_on(&self, connection: &mut Connection, data: &[u8]) -> Result<(TcpStream, bool), ServiceError> {
+        let reused = connection.stream.take().filter(still_open);
+        let was_reused = reused.is_some();
+        let mut stream = match reused {
+            Some(stream) => stream,
+            None => self.connect().await?,
+        };
+        let mut written = 0;
+        match self.write(&mut stream, data, &mut written).await {
+            Ok(_) => Ok((stream, was_reused)),
+            Err(e) if was_reused && written == 0 => {
+                debug!("Connection to printer at {} was lost ({}), reconnecting", self.address, e);
+                let mut stream = self.connect().await?;
+                let mut written = 0;
+                self.write(&mut stream, data, &mut written).await
+                    .map_err(|e| partly_sent(e, written, data.len()))?;
+                Ok((stream, false))
+            }
+            Err(e) => Err(partly_sent(e, written, data.len())),
+        }
+    }
+
+    // Counts in `written` the bytes that were handed to the network
+    async fn write(&self, stream: &mut TcpStream, data: &[u8], written: &mut usize) -> Result<(), ServiceError> {
+        let timeout = Duration::from_millis(self.options.write_timeout_ms);
+        tokio::time::timeout(timeout, async {
+            while *written < data.len() {
+                match stream.write(&data[*written..]).await? {
+                    0 => return Err(std::io::ErrorKind::WriteZero.into()),
+                    n => *written += n,
+                }
+            }
+            stream.flush().await
+        }).await
+            .map_err(|_| ServiceError::IoError(format!("Failed to write to printer at {}: timed out", self.address)))?
+            .map_err(|e| ServiceError::IoError(format!("Failed to write to printer: {}", e)))
+    }
+
+    // After a status query: keep a connection that was already open (without
+    // restarting its idle timer), close one that was opened for the query
+    async fn finish_query(&self, connection: &mut Connection, stream: TcpStream, reused: bool) {
+        if reused && self.options.idle_timeout_secs > 0 {
+            connection.stream = Some(stream);
+        } else {
+            close_gracefully(stream).await;
+        }
+    }
+
+    // After a job: keep the connection for the next one, or close it
+    async fn finish(&self, connection: &mut Connection, stream: TcpStream) {
+        connection.jobs += 1;
+        if self.options.idle_timeout_secs == 0 {
+            close_gracefully(stream).await;
+            return;
+        }
+        connection.stream = Some(stream);
+
+        let jobs = connection.jobs;
+        let idle = Duration::from_secs(self.options.idle_timeout_secs);
+        let shared = self.connection.clone();
+        let address = self.address.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(idle).await;
+            let mut connection = shared.lock().await;
+            // Nothing sent since this job: the connection has been idle long enough
+            if connection.jobs == jobs {
+                if let Some(stream) = connection.stream.take() {
+                    debug!("Closing idle connection to printer at {}", address);
+                    close_gracefully(stream).await;
+                }
+            }
+        });
+    }
+}
+
+// Throws away whatever the printer sent on its own since the last job
+// (automatic status messages...). false = the printer closed the connection.
+fn still_open(stream: &TcpStream) -> bool {
+    let mut buffer = [0u8; 256];
+    loop {
+        match stream.try_read(&mut buffer) {
+            Ok(0) => return false,
+            Ok(_) => continue,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return true,
+            Err(_) => return false,
+        }
+    }
+}
+
+// Half-close (FIN instead of a reset), then wait for the printer to close its side
+async fn close_gracefully(mut stream: TcpStream) {
+    if stream.shutdown().await.is_err() {
+        return;
+    }
+    let mut buffer = [0u8; 256];
+    let _ = tokio::time::timeout(CLOSE_TIMEOUT, async {
+        while matches!(stream.read(&mut buffer).await, Ok(n) if n > 0) {}
+    }).await;
+}
+
+#[async_trait]
+impl Transport for NetworkTransport {
+    // Sends the raw bytes to the device (e.g., 192.168.1.200:9100) over the open connection.
+    async fn send(&self, data: &[u8]) -> Result<(), ServiceError> {
+        // One job at a time on the connection
+        let mut connection = self.connection.lock().await;
+        let (stream, _) = self.send_on(&mut connection, data).await?;
+        self.finish(&mut connection, stream).await;
+        Ok(())
+    }
+    // Port 9100 is bidirectional: the printer answers on the same connection.
+    async fn request(&self, data: &[u8], reply_len: usize) -> Result<Vec<u8>, ServiceError> {
+        let mut connection = self.connection.lock().await;
+        let (mut stream, reused) = self.send_on(&mut connection, data).await?;
+
+        let mut reply = vec![0u8; reply_len];
+        tokio::time::timeout(REPLY_TIMEOUT, stream.read_exact(&mut reply)).await
+            .map_err(|_| ServiceError::DeviceError(format!("Printer at {} did not answer", self.address)))?
+            .map_err(|e| ServiceError::IoError(format!("Failed to read from printer: {}", e)))?;
+        self.finish_query(&mut connection, stream, reused).await;
+        Ok(reply)
+    }
+}
@@ -0,0 +1,259 @@
+use std::fmt;
+use serde::Serialize;
+use serialport::{SerialPortInfo, SerialPortType};
+use crate::errors::ServiceError;
+
+// =========================================================================
+// SERIAL PORTS & USB DEVICES
+// =========================================================================
+// "COM3" or "/dev/ttyUSB0" change when a cable goes into another USB socket.
+// A USB serial device can instead be named by its USB ids, which never change:
+//   connection = "usb:vid=0x0416,pid=0x5011,serial=ABC123:9600"
+// (serial= is only needed when two identical devices are plugged in).
+// The current port name is looked up every time the port is opened.
+
+/// Where a serial device is plugged in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortAddress {
+    // "COM3", "/dev/ttyUSB0"...
+    Name(String),
+    // Whatever port the USB device with these ids has right now
+    Usb { vid: u16, pid: u16, serial: Option<String> },
+}
+
+impl PortAddress {
+    /// Reads "COM3" or "usb:vid=0x0416,pid=0x5011[,serial=ABC123]" (without the baud rate).
+    pub fn parse(text: &str) -> Result<Self, ServiceError> {
+        let Some(spec) = text.strip_prefix("usb:") else {
+            return Ok(PortAddress::Name(text.to_string()));
+        };
+        let invalid = |why: &str| ServiceError::ConfigError(format!(
+            "Invalid USB port \"{}\": {} (expected usb:vid=0x0416,pid=0x5011 or usb:vid=0x0416,pid=0x5011,serial=ABC123)", text, why));
+
+        let (mut vid, mut pid, mut serial) = (None, None, None);
+        for part in spec.split(',') {
+            match part.trim().split_once('=') {
+                Some(("vid", value)) => vid = Some(parse_id(value).ok_or_else(|| invalid("bad vid"))?),
+                Some(("pid", value)) => pid = Some(parse_id(value).ok_or_else(|| invalid("bad pid"))?),
+                Some(("serial", value)) if !value.is_empty() => serial = Some(value.to_string()),
+                _ => return Err(invalid(&format!("unknown part \"{}\"", part))),
+            }
+        }
+        match (vid, pid) {
+            (Some(vid), Some(pid)) => Ok(PortAddress::Usb { vid, pid, serial }),
+            _ => Err(invalid("vid and pid are required")),
+        }
+    }
+
+    /// The name to open right now. USB devices are looked up among the plugged-in ports.
+    pub fn resolve(&self) -> Result<String, ServiceError> {
+        if let PortAddress::Name(name) = self {
+            return Ok(name.clone());
+        }
+        let ports = serialport::available_ports()
+            .map_err(|e| ServiceError::IoError(format!("Failed to list serial ports: {}", e)))?;
+        self.find_in(&ports)
+    }
+
+    /// Is the device among `ports` (the ports of this computer right now)?
+    pub fn is_plugged_in(&self, ports: &[SerialPortInfo]) -> bool {
+        match self {
+            // Ports that aren't listed may still exist (e.g. /dev/serial/by-id/... links)
+            PortAddress::Name(name) => ports.iter().any(|port| &port.port_name == name)
+                || (cfg!(unix) && std::path::Path::new(name).exists()),
+            PortAddress::Usb { .. } => self.find_in(ports).is_ok(),
+        }
+    }
+
+    fn find_in(&self, ports: &[SerialPortInfo]) -> Result<String, ServiceError> {
+        let (vid, pid, serial) = match self {
+            PortAddress::Name(name) => return Ok(name.clone()),
+            PortAddress::Usb { vid, pid, serial } => (*vid, *pid, serial),
+        };
+        let mut matches: Vec<String> = ports.iter()
+            .filter(|port| match &port.port_type {
+                SerialPortType::UsbPort(usb) => usb.vid == vid && usb.pid == pid
+                    && serial.as_ref().is_none_or(|serial| usb.serial_number.as_ref() == Some(serial)),
+                _ => false,
+            })
+            .map(|port| port.port_name.clone())
+            .collect();
+        // macOS lists every device twice (/dev/cu.x and /dev/tty.x); cu. is the one to use
+        let twins: Vec<String> = matches.iter().filter_map(|name| name.strip_prefix("/dev/cu.")).map(|rest| format!("/dev/tty.{}", rest)).collect();
+        matches.retain(|name| !twins.contains(name));
+        matches.sort();
+
+        match matches.len() {
+            0 => Err(ServiceError::DeviceError(format!("USB device {} is not plugged in", self))),
+            1 => Ok(matches.remove(0)),
+            _ => Err(ServiceError::ConfigError(format!(
+                "Several USB devices match {} ({}); add serial=... to pick one", self, matches.join(", ")))),
+        }
+    }
+}
+
+impl fmt::Display for PortAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortAddress::Name(name) => write!(f, "{}", name),
+            PortAddress::Usb { vid, pid, serial: None } => write!(f, "usb:vid=0x{:04x},pid=0x{:04x}", vid, pid),
+            PortAddress::Usb { vid, pid, serial: Some(serial) } => write!(f, "usb:vid=0x{:04x},pid=0x{:04x},serial={}", vid, pid, serial),
+        }
+    }
+}
+
+// "0x0416" or "0416" (always hexadecimal, like in every USB tool)
+fn parse_id(text: &str) -> Option<u16> {
+    let text = text.trim();
+    let hex = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).unwrap_or(text);
+    u16::from_str_radix(hex, 16).ok()
+}
+
+/// One serial port of this computer, as returned by "list_ports".
+#[derive(Serialize, Debug, Clone)]
+pub struct PortInfo {
+    pub port_name: String,
+    // "usb", "pci", "bluetooth" or "unknown"
+    pub kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serial_number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manufacturer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product: Option<String>,
+    // What to write in `connection` to always find this device (USB only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usb_connection: Option<String>,
+}
+
+impl From<SerialPortInfo> for PortInfo {
+    fn from(port: SerialPortInfo) -> Self {
+        let mut info = PortInfo {
+            port_name: port.port_name,
+            kind: "unknown",
+            vid: None,
+            pid: None,
+            serial_number: None,
+            manufacturer: None,
+            product: None,
+            usb_connection: None,
+        };
+        match port.port_type {
+            SerialPortType::UsbPort(usb) => {
+                let address = PortAddress::Usb { vid: usb.vid, pid: usb.pid, serial: usb.serial_number.clone() };
+                info.kind = "usb";
+                info.vid = Some(format!("0x{:04x}", usb.vid));
+                info.pid = Some(format!("0x{:04x}", usb.pid));
+                info.serial_number = usb.serial_number;
+                info.manufacturer = usb.manufacturer;
+                info.product = usb.product;
+                info.usb_connection = Some(address.to_string());
+            }
+            SerialPortType::PciPort => info.kind = "pci",
+            SerialPortType::BluetoothPort => info.kind = "bluetooth",
+            SerialPortType::Unknown => {}
+        }
+        info
+    }
+}
+
+/// Every serial port of this computer, sorted by name.
+pub fn list_ports() -> Result<Vec<PortInfo>, ServiceError> {
+    let mut ports: Vec<PortInfo> = serialport::available_ports()
+        .map_err(|e| ServiceError::IoError(format!("Failed to list serial ports: {}", e)))?
+        .into_iter()
+        .map(PortInfo::from)
+        .collect();
+    ports.sort_by(|a, b| a.port_name.cmp(&b.port_name));
+    Ok(ports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serialport::UsbPortInfo;
+
+    fn usb_port(name: &str, vid: u16, pid: u16, serial: Option<&str>) -> SerialPortInfo {
+        SerialPortInfo {
+            port_name: name.into(),
+            port_type: SerialPortType::UsbPort(UsbPortInfo {
+                vid,
+                pid,
+                serial_number: serial.map(String::from),
+                manufacturer: None,
+                product: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn parses_port_names() {
+        assert_eq!(PortAddress::parse("COM3").unwrap(), PortAddress::Name("COM3".into()));
+        assert_eq!(PortAddress::parse("/dev/ttyUSB0").unwrap(), PortAddress::Name("/dev/ttyUSB0".into()));
+    }
+
+    #[test]
+    fn parses_usb_ids() {
+        assert_eq!(PortAddress::parse("usb:vid=0x0416,pid=0x5011").unwrap(),
+            PortAddress::Usb { vid: 0x0416, pid: 0x5011, serial: None });
+        assert_eq!(PortAddress::parse("usb:pid=5011, vid=0X0416 ,serial=ABC123").unwrap(),
+            PortAddress::Usb { vid: 0x0416, pid: 0x5011, serial: Some("ABC123".into()) });
+    }
+
+    #[test]
+    fn rejects_bad_usb_specs() {
+        for spec in [
+            "usb:",
+            "usb:vid=0x0416",
+            "usb:pid=0x5011",
+            "usb:vid=0x10000,pid=0x5011",
+            "usb:vid=xyz,pid=0x5011",
+            "usb:vid=,pid=0x5011",
+            "usb:vid=0x0416,pid=0x5011,serial=",
+            "usb:vid=0x0416,pid=0x5011,speed=9600",
+            "usb:vid=0x0416;pid=0x5011",
+        ] {
+            assert!(PortAddress::parse(spec).is_err(), "{} was accepted", spec);
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for spec in ["COM3", "usb:vid=0x0416,pid=0x5011", "usb:vid=0x0416,pid=0x5011,serial=ABC123"] {
+            assert_eq!(PortAddress::parse(spec).unwrap().to_string(), spec);
+        }
+    }
+
+    #[test]
+    fn finds_the_usb_device() {
+        let ports = [
+            usb_port("/dev/ttyUSB0", 0x0403, 0x6001, None),
+            usb_port("/dev/ttyACM0", 0x0416, 0x5011, Some("A")),
+            usb_port("/dev/ttyACM1", 0x0416, 0x5011, Some("B")),
+        ];
+        let any = PortAddress::parse("usb:vid=0x0416,pid=0x5011").unwrap();
+        let second = PortAddress::parse("usb:vid=0x0416,pid=0x5011,serial=B").unwrap();
+        let missing = PortAddress::parse("usb:vid=0x0416,pid=0x5011,serial=C").unwrap();
+        assert_eq!(PortAddress::parse("usb:vid=0x0403,pid=0x6001").unwrap().find_in(&ports).unwrap(), "/dev/ttyUSB0");
+        assert_eq!(second.find_in(&ports).unwrap(), "/dev/ttyACM1");
+        // Two identical devices: serial= must pick one
+        assert!(matches!(any.find_in(&ports), Err(ServiceError::ConfigError(_))));
+        assert!(matches!(missing.find_in(&ports), Err(ServiceError::DeviceError(_))));
+        assert!(second.is_plugged_in(&ports));
+        assert!(!missing.is_plugged_in(&ports));
+    }
+
+    #[test]
+    fn prefers_cu_devices_on_macos() {
+        let ports = [
+            usb_port("/dev/tty.usbserial-1", 0x0416, 0x5011, None),
+            usb_port("/dev/cu.usbserial-1", 0x0416, 0x5011, None),
+        ];
+        let address = PortAddress::parse("usb:vid=0x0416,pid=0x5011").unwrap();
+        assert_eq!(address.find_in(&ports).unwrap(), "/dev/cu.usbserial-1");
+    }
+}
@@ -0,0 +1,239 @@
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use serde::Deserialize;
+use crate::errors::ServiceError;
+use crate::hardware::transport::{partly_sent, ports::PortAddress, Transport, REPLY_TIMEOUT};
+use tokio_serial::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, SerialPortBuilderExt, SerialStream, StopBits};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+// COM port / USB virtual COM port ("COM3", or "usb:vid=...,pid=..." see ports.rs)
+//
+// The port is opened on the first job and stays open (other programs can't
+// use it meanwhile). If writing fails before any byte went out, e.g. because
+// the USB cable was pulled and plugged back in, the port is opened again once
+// (a USB device is looked up again, in case it came back under another name).
+// A job that was partly written fails with `PartiallySent` instead, which the
+// queue never retries: writing it again would print the first part twice.
+
+// Line settings of a serial device, e.g. serial = { parity = "even", flow_control = "rts_cts" }
+// The baud rate stays in `connection` ("COM3:19200").
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SerialOptions {
+    pub data_bits: u8,                  // 5, 6, 7 or 8 (default: 8)
+    pub parity: SerialParity,           // "none" (default), "odd" or "even"
+    pub stop_bits: u8,                  // 1 (default) or 2
+    pub flow_control: SerialFlowControl, // "none" (default), "rts_cts" (hardware) or "xon_xoff" (software)
+    pub dtr: Option<bool>,              // Set DTR to this level after opening (default: leave it)
+    pub rts: Option<bool>,              // Set RTS to this level after opening (not with "rts_cts")
+    // Write pacing, for printers whose buffer overflows on big pictures:
+    // send `chunk_size` bytes, wait `chunk_delay_ms`, send the next ones...
+    pub chunk_size: usize,              // 0 = everything at once (default)
+    pub chunk_delay_ms: u64,
+}
+
+impl Default for SerialOptions {
+    fn default() -> Self {
+        Self {
+            data_bits: 8,
+            parity: SerialParity::None,
+            stop_bits: 1,
+            flow_control: SerialFlowControl::None,
+            dtr: None,
+            rts: None,
+            chunk_size: 0,
+            chunk_delay_ms: 0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SerialParity {
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SerialFlowControl {
+    None,
+    RtsCts,
+    XonXoff,
+}
+
+pub struct SerialTransport {
+    port_name: String,
+    baud_rate: u32,
+    options: SerialOptions,
+    // Opened on first use, then kept
+    port: Mutex<Option<SerialStream>>,
+}
+
+/// A serial transport shared between its device and the hotplug watcher.
+pub type SharedSerialTransport = Arc<SerialTransport>;
+
+impl SerialTransport {
+    pub fn new(port_name: String, baud_rate: u32) -> Self {
+        Self::with_options(port_name, baud_rate, SerialOptions::default())
+    }
+
+    pub fn with_options(port_name: String, baud_rate: u32, options: SerialOptions) -> Self {
+        Self { port_name, baud_rate, options, port: Mutex::new(None) }
+    }
+
+    /// The port as configured ("COM3", "usb:vid=...,pid=...").
+    pub fn address(&self) -> &str {
+        &self.port_name
+    }
+
+    /// Closes the port (its device was unplugged); the next job opens it again.
+    pub async fn close(&self) {
+        self.port.lock().await.take();
+    }
+
+    /// Opens the port again right away (its device was plugged back in).
+    pub async fn reopen(&self) -> Result<(), ServiceError> {
+        let mut port = self.port.lock().await;
+        port.take();
+        *port = Some(self.open()?);
+        info!("Serial port {} opened again", self.port_name);
+        Ok(())
+    }
+
+    fn open(&self) -> Result<SerialStream, ServiceError> {
+        let options = &self.options;
+        let data_bits = match options.data_bits {
+            5 => DataBits::Five,
+            6 => DataBits::Six,
+            7 => DataBits::Seven,
+            8 => DataBits::Eight,
+            other => return Err(ServiceError::ConfigError(format!("Invalid data_bits {} for {} (use 5 to 8)", other, self.port_name))),
+        };
+        let stop_bits = match options.stop_bits {
+            1 => StopBits::One,
+            2 => StopBits::Two,
+            other => return Err(ServiceError::ConfigError(format!("Invalid stop_bits {} for {} (use 1 or 2)", other, self.port_name))),
+        };
+        let parity = match options.parity {
+            SerialParity::None => Parity::None,
+            SerialParity::Odd => Parity::Odd,
+            SerialParity::Even => Parity::Even,
+        };
+        let flow_control = match options.flow_control {
+            SerialFlowControl::None => FlowControl::None,
+            SerialFlowControl::RtsCts => FlowControl::Hardware,
+            SerialFlowControl::XonXoff => FlowControl::Software,
+        };
+
+        let port_name = PortAddress::parse(&self.port_name)?.resolve()?;
+        let mut port = tokio_serial::new(&port_name, self.baud_rate)
+            .data_bits(data_bits)
+            .parity(parity)
+            .stop_bits(stop_bits)
+            .flow_control(flow_control)
+            .open_native_async()
+            .map_err(|e| ServiceError::IoError(format!("Failed to open serial port {}: {}", self.port_name, e)))?;
+
+        // Some devices only listen once DTR (or RTS) is raised, others reset when it is
+        let set_line = |result: tokio_serial::Result<()>, line: &str| result
+            .map_err(|e| ServiceError::IoError(format!("Failed to set {} on {}: {}", line, self.port_name, e)));
+        if let Some(level) = options.dtr {
+            set_line(port.write_data_terminal_ready(level), "DTR")?;
+        }
+        if let (Some(level), false) = (options.rts, options.flow_control == SerialFlowControl::RtsCts) {
+            set_line(port.write_request_to_send(level), "RTS")?;
+        }
+        debug!("Opened serial port {} ({}) at {} baud", port_name, self.port_name, self.baud_rate);
+        Ok(port)
+    }
+
+    // Writes `data` on the open port (opening it if needed). If the open port
+    // fails before anything was written, it is opened again and the data
+    // written once more.
+    async fn send_on(&self, port: &mut Option<SerialStream>, data: &[u8]) -> Result<(), ServiceError> {
+        let reused = port.is_some();
+        let mut stream = match port.take() {
+            Some(stream) => stream,
+            None => self.open()?,
+        };
+        let mut written = 0;
+        let result = match self.write(&mut stream, data, &mut written).await {
+            Err(e) if reused && written == 0 => {
+                debug!("Serial port {} failed ({}), opening it again", self.port_name, e);
+                drop(stream);
+                stream = self.open()?;
+                let mut written = 0;
+                self.write(&mut stream, data, &mut written).await
+                    .map_err(|e| partly_sent(e, written, data.len()))
+            }
+            result => result.map_err(|e| partly_sent(e, written, data.len())),
+        };
+        // Keep the port only if it works
+        if result.is_ok() {
+            *port = Some(stream);
+        }
+        result
+    }
+
+    // Counts in `written` the bytes that went out
+    async fn write(&self, stream: &mut SerialStream, data: &[u8], written: &mut usize) -> Result<(), ServiceError> {
+        let failed = |e: std::io::Error| ServiceError::IoError(format!("Failed to write to serial port {}: {}", self.port_name, e));
+        // chunk_size 0 = everything in one go
+        let chunk_size = match self.options.chunk_size {
+            0 => data.len().max(1),
+            size => size,
+        };
+        let pause = Duration::from_millis(self.options.chunk_delay_ms);
+        for (i, chunk) in data.chunks(chunk_size).enumerate() {
+            if i > 0 {
+                tokio::time::sleep(pause).await;
+            }
+            let mut sent = 0;
+            while sent < chunk.len() {
+                match stream.write(&chunk[sent..]).await.map_err(failed)? {
+                    0 => return Err(failed(std::io::ErrorKind::WriteZero.into())),
+                    n => {
+                        sent += n;
+                        *written += n;
+                    }
+                }
+            }
+        }
+        stream.flush().await.map_err(failed)
+    }
+}
+
+#[async_trait]
+impl Transport for SerialTransport {
+    async fn send(&self, data: &[u8]) -> Result<(), ServiceError> {
+        // One job at a time on the port
+        let mut port = self.port.lock().await;
+        self.send_on(&mut port, data).await
+    }
+    async fn request(&self, data: &[u8], reply_len: usize) -> Result<Vec<u8>, ServiceError> {
+        let mut port = self.port.lock().await;
+        // Forget anything the device sent on its own, so it isn't taken for the answer
+        if let Some(stream) = port.as_ref() {
+            let _ = stream.clear(ClearBuffer::Input);
+        }
+        self.send_on(&mut port, data).await?;
+
+        let Some(stream) = port.as_mut() else {
+            return Err(ServiceError::IoError(format!("Serial port {} is not open", self.port_name)));
+        };
+        let mut reply = vec![0u8; reply_len];
+        let result = tokio::time::timeout(REPLY_TIMEOUT, stream.read_exact(&mut reply)).await
+            .map_err(|_| ServiceError::DeviceError(format!("Device on {} did not answer", self.port_name)))
+            .and_then(|read| read.map_err(|e| ServiceError::IoError(format!("Failed to read from serial port {}: {}", self.port_name, e))));
+        if let Err(ServiceError::IoError(_)) = &result {
+            // A broken port is opened again next time
+            *port = None;
+        }
+        result.map(|_| reply)
+    }
+}
@@ -0,0 +1,232 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use axum::extract::rejection::JsonRejection;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Path, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::IntoResponse;
+use axum::routing::{get, post, put};
+use axum::{Extension, Json, Router};
+use serde::de::DeserializeOwned;
+use tokio::net::TcpListener;
+use crate::device_manager::DeviceManager;
+use crate::encoder::Label;
+use crate::errors::{ErrorKind, ServiceError};
+use crate::routing::OrderData;
+use crate::security::{peer_id, signing, ApiKey, LoginError, SecurityManager, Signature};
+use crate::socket::{authorize, execute_command, login_refused, Command, DisplayData, PrintData, Response};
+use tracing::{info, warn};
+
+// =========================================================================
+// HTTP REST API
+// =========================================================================
+// For programs that can't keep a WebSocket open (back-office scripts, old
+// desktop apps). Every endpoint runs the same command as the WebSocket
+// message of the same name and answers with the same JSON.
+// The token goes in a header: "Authorization: Bearer <auth_token>"
+// Wrong tokens lock the client out for a while (429 Too Many Requests).
+// Keys with a `signing_secret` also sign every request (X-Timestamp, X-Nonce
+// and X-Signature headers, see security/signing.rs).
+//
+// e.g. curl -X POST http://127.0.0.1:7778/v1/printers/printer_kitchen/print \
+//        -H "Authorization: Bearer 7777" -H "Content-Type: application/json" \
+//        -d '{ "text": "Hello", "auto_cut": true }'
+
+#[derive(Clone)]
+struct AppState {
+    devices: Arc<DeviceManager>,
+    security: Arc<SecurityManager>,
+}
+
+type Reply = (StatusCode, Json<Response>);
+
+// Same limit as axum's JSON bodies
+const MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+
+pub async fn run_http_server(port: u16, devices: Arc<DeviceManager>, security: Arc<SecurityManager>) -> Result<(), ServiceError> {
+    let addr = format!("127.0.0.1:{}", port);
+    let listener = TcpListener::bind(&addr).await
+        .map_err(|e| ServiceError::IoError(e.to_string()))?;
+
+    let state = AppState { devices, security };
+    let app = Router::new()
+        .route("/v1/devices", get(list_devices))
+        .route("/v1/metrics", get(get_metrics))
+        .route("/v1/ports", get(list_ports))
+        .route("/v1/printers/{id}/print", post(print))
+        .route("/v1/printers/{id}/cut", post(cut))
+        .route("/v1/printers/{id}/status", get(printer_status))
+        .route("/v1/printers/{id}/jobs", get(list_jobs))
+        .route("/v1/orders", post(print_order))
+        .route("/v1/label_printers/{id}/print", post(print_label))
+        .route("/v1/drawers/{id}/open", post(open_drawer))
+        .route("/v1/displays/{id}", put(update_display))
+        .route("/v1/jobs/{id}", get(job_status).delete(cancel_job))
+        // Runs before every endpoint above
+        .route_layer(middleware::from_fn_with_state(state.clone(), authenticate))
+        .with_state(state);
+
+    info!("HTTP API listening on {}", addr);
+    // The client's address is needed to count its failed logins
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await
+        .map_err(|e| ServiceError::IoError(e.to_string()))
+}
+
+// -------------------------------------------------------------------------
+// ENDPOINTS
+// -------------------------------------------------------------------------
+
+async fn list_devices(State(state): State<AppState>, Extension(key): Extension<Arc<ApiKey>>) -> Reply {
+    run(&state, &key, Ok(Command::ListDevices)).await
+}
+
+async fn list_ports(State(state): State<AppState>, Extension(key): Extension<Arc<ApiKey>>) -> Reply {
+    run(&state, &key, Ok(Command::ListPorts)).await
+}
+
+async fn get_metrics(State(state): State<AppState>, Extension(key): Extension<Arc<ApiKey>>) -> Reply {
+    run(&state, &key, Ok(Command::GetMetrics)).await
+}
+
+async fn print(State(state): State<AppState>, Extension(key): Extension<Arc<ApiKey>>, Path(id): Path<String>, body: Result<Json<PrintData>, JsonRejection>) -> Reply {
+    let command = body_of(body).map(|data| Command::Print { device_id: id, data });
+    run(&state, &key, command).await
+}
+
+async fn cut(State(state): State<AppState>, Extension(key): Extension<Arc<ApiKey>>, Path(id): Path<String>) -> Reply {
+    run(&state, &key, Ok(Command::Cut { device_id: id })).await
+}
+
+async fn printer_status(State(state): State<AppState>, Extension(key): Extension<Arc<ApiKey>>, Path(id): Path<String>) -> Reply {
+    run(&state, &key, Ok(Command::GetStatus { device_id: id })).await
+}
+
+async fn list_jobs(State(state): State<AppState>, Extension(key): Extension<Arc<ApiKey>>, Path(id): Path<String>) -> Reply {
+    run(&state, &key, Ok(Command::ListJobs { device_id: id })).await
+}
+
+async fn print_order(State(state): State<AppState>, Extension(key): Extension<Arc<ApiKey>>, body: Result<Json<OrderData>, JsonRejection>) -> Reply {
+    let command = body_of(body).map(|data| Command::PrintOrder { data });
+    run(&state, &key, command).await
+}
+
+async fn print_label(State(state): State<AppState>, Extension(key): Extension<Arc<ApiKey>>, Path(id): Path<String>, body: Result<Json<Label>, JsonRejection>) -> Reply {
+    let command = body_of(body).map(|data| Command::PrintLabel { device_id: id, data });
+    run(&state, &key, command).await
+}
+
+async fn open_drawer(State(state): State<AppState>, Extension(key): Extension<Arc<ApiKey>>, Path(id): Path<String>) -> Reply {
+    run(&state, &key, Ok(Command::OpenDrawer { device_id: id })).await
+}
+
+async fn update_display(State(state): State<AppState>, Extension(key): Extension<Arc<ApiKey>>, Path(id): Path<String>, body: Result<Json<DisplayData>, JsonRejection>) -> Reply {
+    let command = body_of(body).map(|data| Command::DisplayUpdate { device_id: id, data });
+    run(&state, &key, command).await
+}
+
+async fn job_status(State(state): State<AppState>, Extension(key): Extension<Arc<ApiKey>>, Path(id): Path<String>) -> Reply {
+    run(&state, &key, Ok(Command::JobStatus { job_id: id })).await
+}
+
+async fn cancel_job(State(state): State<AppState>, Extension(key): Extension<Arc<ApiKey>>, Path(id): Path<String>) -> Reply {
+    run(&state, &key, Ok(Command::CancelJob { job_id: id })).await
+}
+
+// -------------------------------------------------------------------------
+// HELPERS
+// -------------------------------------------------------------------------
+
+// Checks the origin, the token and (for keys that need it) the signature,
+// then hands the key to the endpoint
+async fn authenticate(State(state): State<AppState>, ConnectInfo(addr): ConnectInfo<SocketAddr>, request: Request, next: Next) -> axum::response::Response {
+    let origin = request.headers().get(header::ORIGIN).map(|value| value.to_str().unwrap_or("(invalid)"));
+    if !state.security.origin_allowed(origin) {
+        warn!("[Audit] Rejected HTTP request: origin {} is not allowed", origin.unwrap_or_default());
+        return error(StatusCode::FORBIDDEN, "Origin not allowed".into()).into_response();
+    }
+
+    let token = request.headers().get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let Some(token) = token else {
+        warn!("HTTP request without a token");
+        return error(StatusCode::UNAUTHORIZED, "Authentication required".into()).into_response();
+    };
+    // Wrong tokens count towards the same lockout as on the WebSocket
+    let peer = peer_id(addr.ip(), origin);
+    let key = match state.security.login(&peer, token) {
+        Ok(key) => key,
+        Err(e) => {
+            let status = match e {
+                LoginError::LockedOut(_) => StatusCode::TOO_MANY_REQUESTS,
+                LoginError::InvalidToken(_) => StatusCode::UNAUTHORIZED,
+            };
+            return error(status, login_refused(&state.devices, &peer, e)).into_response();
+        }
+    };
+
+    let mut request = request;
+    if key.requires_signature() {
+        // The signature covers the body, so read it here and put it back for the endpoint
+        let (parts, body) = request.into_parts();
+        let Ok(body) = axum::body::to_bytes(body, MAX_BODY_SIZE).await else {
+            return error(StatusCode::PAYLOAD_TOO_LARGE, "Request body too large".into()).into_response();
+        };
+        let header = |name: &str| parts.headers.get(name).and_then(|value| value.to_str().ok());
+        let path = parts.uri.path_and_query().map(|path| path.as_str()).unwrap_or("/");
+        let payload = signing::http_payload(parts.method.as_str(), path,
+            header("x-timestamp").unwrap_or_default(), header("x-nonce").unwrap_or_default(), &body);
+        let signature = Signature {
+            timestamp: header("x-timestamp").and_then(|timestamp| timestamp.parse().ok()),
+            nonce: header("x-nonce"),
+            signature: header("x-signature"),
+        };
+        if let Err(message) = state.security.verify_signature(&key, &payload, signature) {
+            warn!("[Audit] Key '{}' sent a request with a bad signature: {}", key.name, message);
+            return error(StatusCode::UNAUTHORIZED, message).into_response();
+        }
+        request = Request::from_parts(parts, Body::from(body));
+    }
+
+    request.extensions_mut().insert(key);
+    next.run(request).await
+}
+
+// Runs the command exactly like a WebSocket message
+async fn run(state: &AppState, key: &ApiKey, command: Result<Command, String>) -> Reply {
+    match command {
+        Ok(command) => {
+            if let Err(message) = authorize(key, &command, &state.devices).await {
+                warn!("[Audit] Key '{}' denied: {}", key.name, message);
+                return error(StatusCode::FORBIDDEN, message);
+            }
+            let response = execute_command(command, &state.devices).await;
+            (http_status(&response), Json(response))
+        }
+        Err(message) => error(StatusCode::BAD_REQUEST, message),
+    }
+}
+
+// A body that isn't valid JSON becomes the same error as on the WebSocket
+fn body_of<T: DeserializeOwned>(body: Result<Json<T>, JsonRejection>) -> Result<T, String> {
+    body.map(|Json(data)| data).map_err(|e| format!("Invalid JSON format: {}", e.body_text()))
+}
+
+fn error(status: StatusCode, message: String) -> Reply {
+    (status, Json(Response { request_id: None, status: "error".into(), error: None, device_id: None, message: Some(message), data: None }))
+}
+
+// The WebSocket answers only say "ok" or "error"; pick the HTTP code from the kind of error
+fn http_status(response: &Response) -> StatusCode {
+    if response.status == "ok" {
+        return StatusCode::OK;
+    }
+    match response.error {
+        Some(ErrorKind::NotFound) => StatusCode::NOT_FOUND,
+        // The service is fine, the printer (or drawer...) isn't
+        Some(ErrorKind::Device | ErrorKind::PartiallySent | ErrorKind::Io) => StatusCode::BAD_GATEWAY,
+        Some(ErrorKind::Internal) => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST,
+    }
+}
@@ -1,8 +1,16 @@
 pub mod config;
 pub mod security;
 pub mod socket;
+pub mod http;
+pub mod tls;
 pub mod device_manager;
+pub mod queue;
+pub mod spool;
+pub mod routing;
+pub mod events;
+pub mod metrics;
 pub mod hardware;
+pub mod encoder;
 pub mod logging;
 pub mod errors;
 pub mod utils;
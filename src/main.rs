@@ -1,9 +1,38 @@
 use std::sync::Arc;
-use tracing::{info, error};
-use pos_hardware_lib::{config, logging, device_manager, security, socket, utils};
+use tracing::{info, warn, error};
+use pos_hardware_lib::{config, logging, device_manager, http, security, socket, spool, tls, utils};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    // "pos_hardware_service gen-cert [folder]" creates the certificates for wss:// and exits
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("gen-cert") {
+        let dir = std::path::PathBuf::from(args.get(2).map(String::as_str).unwrap_or("certs"));
+        tls::generate_local_certificates(&dir)?;
+        println!("Certificates written to {}", dir.display());
+        println!("1. Trust {} as a root certificate on this computer (once).", dir.join(tls::CA_CERT).display());
+        println!("2. Add to config.toml:");
+        println!("   [tls]");
+        println!("   cert_path = \"{}\"", dir.join(tls::SERVER_CERT).display());
+        println!("   key_path = \"{}\"", dir.join(tls::SERVER_KEY).display());
+        return Ok(());
+    }
+    // "pos_hardware_service gen-key <name>" creates an API key for one client and exits
+    if args.get(1).map(String::as_str) == Some("gen-key") {
+        let name = args.get(2).map(String::as_str).unwrap_or("my_client");
+        let (key, key_hash) = security::generate_api_key();
+        println!("API key for \"{}\" (give it to the client; it is not stored anywhere, keep it secret):", name);
+        println!("   {}", key);
+        println!("Add to config.toml and pick the scopes (print, drawer, display, status, admin):");
+        println!("   [[api_keys]]");
+        println!("   name = \"{}\"", name);
+        println!("   key_hash = \"{}\"", key_hash);
+        println!("   scopes = [\"print\", \"status\"]");
+        println!("   # devices = [\"printer_kiosk\"]  # Optional: only these devices");
+        println!("   # signing_secret = \"{}\"  # Optional: only accept commands signed with this (give it to the client too)", security::generate_signing_secret());
+        return Ok(());
+    }
+
     // ------------------------------------------------------------------------
     // STEP 1: Load Configuration
     // ------------------------------------------------------------------------
@@ -44,7 +73,9 @@ async fn main() -> anyhow::Result<()> {
     // ------------------------------------------------------------------------
     // This 'DeviceManager' is the brain that holds connections to all your printers.
     // It creates the "driver" for each printer defined in config.toml.
-    let device_manager = Arc::new(device_manager::DeviceManager::new());
+    // Print jobs that were still in the spool folder are queued again.
+    let spool = spool::Spool::open(&settings.spool);
+    let device_manager = Arc::new(device_manager::DeviceManager::with_spool(spool));
     match device_manager.load_from_config(&settings.devices).await {
         Ok(_) => info!("Device configuration loaded successfully"),
         Err(e) => {
@@ -52,20 +83,60 @@ async fn main() -> anyhow::Result<()> {
             return Err(e.into());
         }
     }
+    // Checks printers and drawers in the background and tells subscribed
+    // clients when something changes (paper out, drawer left open...)
+    device_manager.spawn_status_monitor(std::time::Duration::from_secs(settings.events.status_poll_secs));
+    // Closes and reopens serial/USB devices when their cable is pulled out and plugged back in
+    device_manager.spawn_hotplug_watcher(std::time::Duration::from_secs(settings.events.hotplug_poll_secs));
 
     // ------------------------------------------------------------------------
     // STEP 4: Initialize Security
     // ------------------------------------------------------------------------
     // This stores the password/token that the POS app must provide to be allowed in.
-    let security = Arc::new(security::SecurityManager::new(settings.auth_token.clone()));
+    // It also knows which web pages may connect, and how long a client may take to log in.
+    let auth_timeout = settings.auth_timeout_secs.map(std::time::Duration::from_secs).unwrap_or(security::DEFAULT_AUTH_TIMEOUT);
+    let max_skew = settings.signature_max_skew_secs.map(std::time::Duration::from_secs).unwrap_or(security::signing::DEFAULT_MAX_SKEW);
+    let security = Arc::new(security::SecurityManager::with_policy(settings.auth_token.clone(), &settings.api_keys, settings.allowed_origins.clone(), auth_timeout, max_skew));
+    if !security.restricts_origins() {
+        warn!("allowed_origins is empty: any web page opened on this computer may connect. List your POS address in config.toml.");
+    }
+
+    // ------------------------------------------------------------------------
+    // STEP 5: Start the HTTP API (optional)
+    // ------------------------------------------------------------------------
+    // Same commands as the WebSocket, for programs that can only make HTTP requests.
+    if let Some(http_port) = settings.http_port {
+        let devices = device_manager.clone();
+        let security = security.clone();
+        tokio::spawn(async move {
+            if let Err(e) = http::run_http_server(http_port, devices, security).await {
+                error!("HTTP API stopped: {}", e);
+            }
+        });
+    }
 
     // ------------------------------------------------------------------------
-    // STEP 5: Start WebSocket Server
+    // STEP 6: Start WebSocket Server
     // ------------------------------------------------------------------------
     // This opens the network port (e.g. 8080) and waits for the POS app (client)
     // to connect. It creates a loop that runs forever until you stop the program.
+    // With [tls] in config.toml the server speaks wss:// (certificate files are watched for renewals)
+    let tls = match &settings.tls {
+        Some(tls_config) => match tls::Tls::load(tls_config) {
+            Ok(tls) => {
+                tls.watch(std::time::Duration::from_secs(10));
+                Some(tls)
+            }
+            Err(e) => {
+                error!("Failed to load the TLS certificate: {}", e);
+                return Err(e.into());
+            }
+        },
+        None => None,
+    };
+
     info!("Initializing WebSocket server...");
-    if let Err(e) = socket::run_server(settings.port, device_manager, security).await {
+    if let Err(e) = socket::run_server(settings.port, device_manager, security, tls).await {
         error!("Server crashed: {}", e);
         return Err(e.into());
     }
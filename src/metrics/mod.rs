@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// =========================================================================
+// METRICS
+// =========================================================================
+// Counters that only go up while the service runs (they restart at 0).
+// Admin keys read them with { "type": "get_metrics" } or GET /v1/metrics,
+// e.g. to alert when someone is guessing tokens.
+
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    pub fn increment(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Logins with a wrong token
+pub static AUTH_FAILURES: Counter = Counter::new();
+// Failed logins that locked a client (or everybody) out
+pub static AUTH_LOCKOUTS: Counter = Counter::new();
+
+/// Every counter, by name.
+pub fn snapshot() -> serde_json::Value {
+    serde_json::json!({
+        "auth_failures_total": AUTH_FAILURES.get(),
+        "auth_lockouts_total": AUTH_LOCKOUTS.get(),
+    })
+}
@@ -0,0 +1,218 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use async_trait::async_trait;
+use serde::Deserialize;
+use crate::encoder::{receipt, CodePage, PrinterStatus, ReceiptEncoder};
+use crate::encoder::document::CutMode;
+use crate::errors::{ErrorKind, ServiceError};
+use crate::hardware::printer::profile::PrinterProfile;
+use crate::hardware::traits::Printer;
+use crate::queue::PrintQueue;
+use tracing::{info, warn};
+
+// =========================================================================
+// PRINTER GROUPS
+// =========================================================================
+// A group is a "virtual printer" made of real printers. The POS prints to
+// the group id like to any printer id, and the group decides where the job
+// goes. The group has its own queue and spool like a printer, so a job is
+// only given up when NO member could print it.
+//
+// Receipts are laid out once, with the first member's profile, so the
+// members of a group should be the same model (or at least the same paper
+// width and command set).
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupPolicy {
+    // Always the first member; the next ones only when it fails (jam, offline...)
+    #[default]
+    Failover,
+    // Members take turns; a failing member is skipped
+    RoundRobin,
+    // Every member prints a copy. Each copy goes into that member's own
+    // queue and is retried there, so one jammed printer doesn't hold up the others.
+    Broadcast,
+}
+
+pub struct PrinterGroup {
+    id: String,
+    policy: GroupPolicy,
+    members: Vec<Arc<PrintQueue>>,
+    // Member that gets the next round-robin job
+    next: AtomicUsize,
+}
+
+impl PrinterGroup {
+    /// Fails if `members` is empty.
+    pub fn new(id: String, policy: GroupPolicy, members: Vec<Arc<PrintQueue>>) -> Result<Self, ServiceError> {
+        if members.is_empty() {
+            return Err(ServiceError::ConfigError(format!("Printer group {} has no usable printers", id)));
+        }
+        Ok(Self { id, policy, members, next: AtomicUsize::new(0) })
+    }
+
+    // Tries the members one after the other, starting at `first`,
+    // until one of them prints the job.
+    async fn print_on_first_available(&self, data: &[u8], first: usize) -> Result<(), ServiceError> {
+        let mut errors = Vec::new();
+        for offset in 0..self.members.len() {
+            let member = &self.members[(first + offset) % self.members.len()];
+            match member.print_raw(data).await {
+                Ok(_) => {
+                    info!("[Group {}] Printed on {}", self.id, member.printer_id());
+                    return Ok(());
+                }
+                // Part of the job is on this printer's paper: printing it on the next
+                // one (or retrying it from the group's spool) would print it twice
+                Err(e) if e.kind() == ErrorKind::PartiallySent => {
+                    warn!("[Group {}] {} failed halfway, not trying the next printer: {}", self.id, member.printer_id(), e);
+                    return Err(e);
+                }
+                Err(e) => {
+                    warn!("[Group {}] {} failed, trying the next printer: {}", self.id, member.printer_id(), e);
+                    errors.push(format!("{}: {}", member.printer_id(), e));
+                }
+            }
+        }
+        Err(ServiceError::DeviceError(format!("No printer of group {} could print: {}", self.id, errors.join("; "))))
+    }
+}
+
+#[async_trait]
+impl Printer for PrinterGroup {
+    fn profile(&self) -> &PrinterProfile {
+        self.members[0].profile()
+    }
+
+    fn codepage(&self) -> CodePage {
+        self.members[0].codepage()
+    }
+
+    async fn print_text(&self, text: &str) -> Result<(), ServiceError> {
+        let data = receipt::plain_text_job(self.profile(), self.codepage(), text);
+        self.print_raw(&data).await
+    }
+
+    async fn cut_paper(&self) -> Result<(), ServiceError> {
+        let data = ReceiptEncoder::for_printer(self).cut(CutMode::Partial);
+        self.print_raw(&data).await
+    }
+
+    async fn print_raw(&self, data: &[u8]) -> Result<(), ServiceError> {
+        match self.policy {
+            GroupPolicy::Failover => self.print_on_first_available(data, 0).await,
+            GroupPolicy::RoundRobin => {
+                let first = self.next.fetch_add(1, Ordering::Relaxed) % self.members.len();
+                self.print_on_first_available(data, first).await
+            }
+            GroupPolicy::Broadcast => {
+                for member in &self.members {
+                    let job = member.enqueue(data.to_vec(), None);
+                    info!("[Group {}] Copy queued on {} as job {}", self.id, member.printer_id(), job.job_id);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // A group has no single status; ask one of its printers instead.
+    async fn status(&self) -> Result<PrinterStatus, ServiceError> {
+        let members: Vec<&str> = self.members.iter().map(|m| m.printer_id()).collect();
+        Err(ServiceError::InvalidCommand(format!(
+            "{} is a printer group, ask one of its printers for the status: {}", self.id, members.join(", ")
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::broadcast;
+    use crate::queue::JobState;
+    use crate::queue::tests::{wait_for, wait_until, ScriptedPrinter};
+    use crate::spool::Spool;
+
+    // A group of printers "a", "b", "c"... answering print jobs with `results`
+    fn group(policy: GroupPolicy, results: Vec<Vec<Result<(), ServiceError>>>) -> (PrinterGroup, Vec<Arc<ScriptedPrinter>>) {
+        let printers: Vec<_> = results.into_iter().map(ScriptedPrinter::new).collect();
+        let members = printers.iter().enumerate()
+            .map(|(i, printer)| {
+                let id = ((b'a' + i as u8) as char).to_string();
+                PrintQueue::new(id, printer.clone(), Arc::new(Spool::disabled()), broadcast::channel(16).0)
+            })
+            .collect();
+        (PrinterGroup::new("group".into(), policy, members).unwrap(), printers)
+    }
+
+    fn offline() -> Result<(), ServiceError> {
+        Err(ServiceError::DeviceError("offline".into()))
+    }
+
+    fn jobs_per_printer(printers: &[Arc<ScriptedPrinter>]) -> Vec<usize> {
+        printers.iter().map(|printer| printer.sent().len()).collect()
+    }
+
+    #[test]
+    fn needs_members() {
+        assert!(PrinterGroup::new("group".into(), GroupPolicy::Failover, vec![]).is_err());
+    }
+
+    #[tokio::test]
+    async fn failover_tries_members_in_order() {
+        let (group, printers) = group(GroupPolicy::Failover, vec![vec![offline()], vec![], vec![]]);
+        group.print_raw(b"one").await.unwrap();
+        // The first printer is back: it gets the next job again
+        group.print_raw(b"two").await.unwrap();
+        assert_eq!(printers[0].sent(), [b"one".to_vec(), b"two".to_vec()]);
+        assert_eq!(printers[1].sent(), [b"one".to_vec()]);
+        assert_eq!(jobs_per_printer(&printers), [2, 1, 0]);
+    }
+
+    #[tokio::test]
+    async fn fails_when_no_member_could_print() {
+        let (group, printers) = group(GroupPolicy::Failover, vec![vec![offline()], vec![offline()]]);
+        let error = group.print_raw(b"job").await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Device);
+        assert!(error.to_string().contains("a: Device error: offline; b: Device error: offline"), "{}", error);
+        assert_eq!(jobs_per_printer(&printers), [1, 1]);
+    }
+
+    #[tokio::test]
+    async fn stops_at_a_partly_sent_job() {
+        let partly = Err(ServiceError::PartiallySent("2 of 3 bytes were sent".into()));
+        let (group, printers) = group(GroupPolicy::Failover, vec![vec![partly], vec![]]);
+        let error = group.print_raw(b"job").await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PartiallySent);
+        assert_eq!(jobs_per_printer(&printers), [1, 0]);
+    }
+
+    #[tokio::test]
+    async fn round_robin_takes_turns_and_skips_failing_members() {
+        let (group, printers) = group(GroupPolicy::RoundRobin, vec![vec![], vec![offline()], vec![]]);
+        for _ in 0..3 {
+            group.print_raw(b"job").await.unwrap();
+        }
+        // a, then b failed and c took its job, then c's own turn
+        assert_eq!(jobs_per_printer(&printers), [1, 1, 2]);
+
+        group.print_raw(b"job").await.unwrap();
+        group.print_raw(b"job").await.unwrap();
+        assert_eq!(jobs_per_printer(&printers), [2, 2, 2]);
+    }
+
+    #[tokio::test]
+    async fn broadcast_queues_a_copy_on_every_member() {
+        let (group, printers) = group(GroupPolicy::Broadcast, vec![vec![], vec![offline()]]);
+        group.print_raw(b"menu").await.unwrap();
+
+        // Each copy is printed by its member's queue, and the offline one retried there
+        let ok = group.members[0].jobs()[0].job_id.clone();
+        let failing = group.members[1].jobs()[0].job_id.clone();
+        wait_for(&group.members[0], &ok, JobState::Completed).await;
+        let waiting = wait_until(&group.members[1], &failing, |job| job.attempts == 1 && job.state == JobState::Queued).await;
+        assert_eq!(waiting.error.as_deref(), Some("Device error: offline"));
+        assert_eq!(printers[0].sent(), [b"menu".to_vec()]);
+        assert_eq!(printers[1].sent(), [b"menu".to_vec()]);
+    }
+}
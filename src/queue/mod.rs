@@ -0,0 +1,749 @@
+pub mod group;
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use async_trait::async_trait;
+use serde::Serialize;
+use tokio::sync::{broadcast, oneshot, Notify};
+use crate::encoder::{receipt, CodePage, PrinterStatus, ReceiptEncoder};
+use crate::encoder::document::CutMode;
+use crate::errors::{ErrorKind, ServiceError};
+use crate::events::{self, Event};
+use crate::hardware::printer::profile::PrinterProfile;
+use crate::hardware::traits::Printer;
+use crate::spool::{Spool, SpooledJob};
+use tracing::{debug, info, warn};
+
+// =========================================================================
+// PRINT QUEUE
+// =========================================================================
+// Every printer gets ONE queue with ONE worker task in front of it. Print
+// jobs, cuts, drawer kicks and status queries from all clients wait in line
+// and reach the printer strictly one after the other. Without it, two
+// terminals printing at the same time could mix their bytes on a network
+// printer, or get "port busy" on a serial one.
+//
+// The queue is itself a `Printer`, so everything that used to talk to the
+// printer directly (socket commands, printer-driven drawers) goes through it.
+//
+// Print jobs from the "print" command ("spooled" jobs) are also saved in the
+// spool and retried until they print or expire. Jobs somebody is waiting for (cuts,
+// drawer kicks, status) are tried once, so they fail fast when the printer is
+// offline instead of hanging until it comes back.
+//
+// A job the printer got only part of (`ServiceError::PartiallySent`) is never
+// retried: the part that reached the printer is already on the paper, and
+// sending the whole job again would print it twice.
+
+// How many finished print jobs we remember per printer (for job_status / list_jobs).
+// Status queries aren't kept: the status monitor sends one every few seconds.
+const HISTORY_LEN: usize = 50;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Printing,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    // Bytes to print (receipts, cuts, drawer kicks)
+    Print,
+    // Bytes sent to the printer, waiting for its answer
+    Query,
+    // Real-time status request
+    Status,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct JobInfo {
+    pub job_id: String,
+    pub printer_id: String,
+    pub kind: JobKind,
+    pub state: JobState,
+    // Number of jobs that will run before this one (only while queued)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<usize>,
+    // Unix timestamp (seconds)
+    pub created_at: u64,
+    // When a spooled job is given up if it still couldn't print (Unix timestamp)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    pub attempts: u32,
+    // Last error (also set while a spooled job waits for its next attempt)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// What a job has to do once it reaches the printer
+enum Work {
+    Print(Vec<u8>),
+    Query { data: Vec<u8>, reply_len: usize },
+    Status,
+}
+
+// What a finished job hands back to whoever is waiting for it
+enum Output {
+    Printed,
+    Reply(Vec<u8>),
+    Status(PrinterStatus),
+}
+
+struct Job {
+    info: JobInfo,
+    work: Work,
+    // Set when the caller waits for the result (e.g. a drawer kick).
+    // Fire-and-forget print jobs have nobody waiting.
+    done: Option<oneshot::Sender<Result<Output, ServiceError>>>,
+    // Set for spooled print jobs, which are retried until they expire
+    spooled: Option<Spooled>,
+}
+
+struct Spooled {
+    order: u64,
+    expires_at: u64,
+    // No new attempt before this moment (after a failed attempt)
+    retry_at: Option<Instant>,
+}
+
+// What the worker should do next
+struct NextStep {
+    job: Option<Job>,
+    // Spooled jobs that waited too long
+    expired: Vec<Job>,
+    // How long until a spooled job may be retried
+    wait: Option<Duration>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    pending: VecDeque<Job>,
+    running: Option<JobInfo>,
+    // Most recent first
+    finished: VecDeque<JobInfo>,
+}
+
+pub struct PrintQueue {
+    printer_id: String,
+    printer: Arc<dyn Printer>,
+    state: Mutex<QueueState>,
+    // Wakes the worker up when a job is added
+    wake: Notify,
+    spool: Arc<Spool>,
+    events: broadcast::Sender<Event>,
+}
+
+impl PrintQueue {
+    /// Wraps a printer in a queue and starts its worker task.
+    pub fn new(printer_id: String, printer: Arc<dyn Printer>, spool: Arc<Spool>, events: broadcast::Sender<Event>) -> Arc<Self> {
+        let queue = Arc::new(Self {
+            printer_id,
+            printer,
+            state: Mutex::new(QueueState::default()),
+            wake: Notify::new(),
+            spool,
+            events,
+        });
+        tokio::spawn(queue.clone().run());
+        queue
+    }
+
+    pub fn printer_id(&self) -> &str {
+        &self.printer_id
+    }
+
+    /// Adds print data to the end of the queue without waiting for it to print.
+    /// The job is saved in the spool and retried until it prints or `expires_in`
+    /// (default: the spool's job expiry) has passed.
+    /// The returned info contains the job id and the queue position.
+    pub fn enqueue(&self, data: Vec<u8>, expires_in: Option<Duration>) -> JobInfo {
+        let created_at = unix_now();
+        let expires_at = created_at + expires_in.unwrap_or_else(|| self.spool.job_expiry()).as_secs();
+        let job_id = uuid::Uuid::new_v4().to_string();
+        let order = unix_now_micros();
+
+        // Save it first: once the client has a job id, the job must survive a crash
+        self.spool.save(&SpooledJob {
+            job_id: job_id.clone(),
+            printer_id: self.printer_id.clone(),
+            created_at,
+            expires_at,
+            order,
+            attempts: 0,
+            last_error: None,
+            data: SpooledJob::encode_bytes(&data),
+        });
+
+        let spooled = Spooled { order, expires_at, retry_at: None };
+        let info = self.new_info(job_id, JobKind::Print, created_at, Some(expires_at));
+        self.push(Job { info, work: Work::Print(data), done: None, spooled: Some(spooled) })
+    }
+
+    /// Queues a job found in the spool when the service started.
+    pub fn restore(&self, saved: SpooledJob) -> Result<(), ServiceError> {
+        let data = saved.bytes()?;
+        let mut info = self.new_info(saved.job_id, JobKind::Print, saved.created_at, Some(saved.expires_at));
+        info.attempts = saved.attempts;
+        info.error = saved.last_error;
+
+        let spooled = Spooled { order: saved.order, expires_at: saved.expires_at, retry_at: None };
+        self.push(Job { info, work: Work::Print(data), done: None, spooled: Some(spooled) });
+        Ok(())
+    }
+
+    /// Looks up a queued, running or recently finished job.
+    pub fn job(&self, job_id: &str) -> Option<JobInfo> {
+        self.jobs().into_iter().find(|job| job.job_id == job_id)
+    }
+
+    /// All jobs of this printer: the running one, the queued ones in order,
+    /// then the recently finished ones (most recent first).
+    pub fn jobs(&self) -> Vec<JobInfo> {
+        let state = self.state.lock().unwrap();
+        let ahead = state.running.is_some() as usize;
+
+        let mut jobs: Vec<JobInfo> = state.running.iter().cloned().collect();
+        for (index, job) in state.pending.iter().enumerate() {
+            jobs.push(JobInfo { position: Some(ahead + index), ..job.info.clone() });
+        }
+        jobs.extend(state.finished.iter().cloned());
+        jobs
+    }
+
+    /// Removes a job that hasn't started yet. Jobs already being printed
+    /// can't be stopped, since part of the data may be on the paper.
+    pub fn cancel(&self, job_id: &str) -> Result<JobInfo, ServiceError> {
+        let mut state = self.state.lock().unwrap();
+
+        let Some(index) = state.pending.iter().position(|job| job.info.job_id == job_id) else {
+            let message = if state.running.as_ref().is_some_and(|job| job.job_id == job_id) {
+                format!("Job {} is already printing", job_id)
+            } else {
+                format!("Job {} is not waiting in the queue", job_id)
+            };
+            return Err(ServiceError::InvalidCommand(message));
+        };
+
+        let mut job = state.pending.remove(index).expect("index is valid");
+        job.info.state = JobState::Cancelled;
+        info!("[Queue {}] Job {} cancelled", self.printer_id, job_id);
+        if job.spooled.is_some() {
+            self.spool.remove(job_id);
+        }
+
+        if let Some(done) = job.done {
+            let _ = done.send(Err(ServiceError::DeviceError(format!("Job {} was cancelled", job_id))));
+        }
+        Self::remember(&mut state, job.info.clone());
+        Ok(job.info)
+    }
+
+    /// Retries waiting spooled jobs right away (e.g. the printer was just plugged back in).
+    pub fn retry_now(&self) {
+        let mut state = self.state.lock().unwrap();
+        for spooled in state.pending.iter_mut().filter_map(|job| job.spooled.as_mut()) {
+            spooled.retry_at = None;
+        }
+        drop(state);
+        self.wake.notify_one();
+    }
+
+    fn new_info(&self, job_id: String, kind: JobKind, created_at: u64, expires_at: Option<u64>) -> JobInfo {
+        JobInfo {
+            job_id,
+            printer_id: self.printer_id.clone(),
+            kind,
+            state: JobState::Queued,
+            position: None,
+            created_at,
+            expires_at,
+            attempts: 0,
+            error: None,
+        }
+    }
+
+    fn push(&self, job: Job) -> JobInfo {
+        let mut info = job.info.clone();
+        {
+            let mut state = self.state.lock().unwrap();
+            info.position = Some(state.pending.len() + state.running.is_some() as usize);
+            state.pending.push_back(job);
+        }
+        self.wake.notify_one();
+
+        if info.kind == JobKind::Print {
+            info!("[Queue {}] Job {} queued ({} ahead)", self.printer_id, info.job_id, info.position.unwrap_or(0));
+        } else {
+            debug!("[Queue {}] Job {} queued ({:?}, {} ahead)", self.printer_id, info.job_id, info.kind, info.position.unwrap_or(0));
+        }
+        info
+    }
+
+    // Queues the work and waits until the worker has done it.
+    async fn run_job(&self, work: Work) -> Result<Output, ServiceError> {
+        let kind = match work {
+            Work::Print(_) => JobKind::Print,
+            Work::Query { .. } => JobKind::Query,
+            Work::Status => JobKind::Status,
+        };
+        let info = self.new_info(uuid::Uuid::new_v4().to_string(), kind, unix_now(), None);
+        let (done, result) = oneshot::channel();
+        self.push(Job { info, work, done: Some(done), spooled: None });
+        result.await
+            .map_err(|_| ServiceError::InternalError(format!("Print queue of {} stopped", self.printer_id)))?
+    }
+
+    // The worker: takes the oldest job that may run, runs it, and sleeps
+    // when there is nothing to do (or until the next retry is due).
+    async fn run(self: Arc<Self>) {
+        loop {
+            let step = {
+                let mut state = self.state.lock().unwrap();
+                Self::next_step(&mut state)
+            };
+
+            for mut job in step.expired {
+                let attempts = job.info.attempts;
+                let last_error = job.info.error.take().unwrap_or_else(|| "never tried".into());
+                let error = format!("Job expired after {} attempt(s): {}", attempts, last_error);
+                self.finish(job, Err(ServiceError::DeviceError(error)));
+            }
+
+            let Some(mut job) = step.job else {
+                match step.wait {
+                    Some(wait) => {
+                        tokio::select! {
+                            _ = self.wake.notified() => {}
+                            _ = tokio::time::sleep(wait) => {}
+                        }
+                    }
+                    None => self.wake.notified().await,
+                }
+                continue;
+            };
+
+            job.info.attempts += 1;
+            let result = match &job.work {
+                Work::Print(data) => self.printer.print_raw(data).await.map(|_| Output::Printed),
+                Work::Query { data, reply_len } => self.printer.query_raw(data, *reply_len).await.map(Output::Reply),
+                Work::Status => self.printer.status().await.map(Output::Status),
+            };
+
+            match (result, &mut job.spooled) {
+                // Spooled job failed before anything was sent: keep it at the front
+                // of the queue and try again later
+                (Err(e), Some(spooled)) if e.kind() != ErrorKind::PartiallySent && unix_now() < spooled.expires_at => {
+                    let delay = self.spool.retry_delay(job.info.attempts);
+                    warn!("[Queue {}] Job {} failed (attempt {}), retrying in {}s: {}",
+                        self.printer_id, job.info.job_id, job.info.attempts, delay.as_secs(), e);
+                    spooled.retry_at = Some(Instant::now() + delay);
+                    job.info.error = Some(e.to_string());
+                    job.info.state = JobState::Queued;
+                    self.save_spooled(&job);
+
+                    let mut state = self.state.lock().unwrap();
+                    state.running = None;
+                    state.pending.push_front(job);
+                }
+                (result, _) => self.finish(job, result),
+            }
+        }
+    }
+
+    // Picks the next job. Jobs run in order, except that a spooled job waiting
+    // for its retry lets the jobs behind it through (but never other spooled
+    // jobs, so tickets still print in the order they were sent).
+    fn next_step(state: &mut QueueState) -> NextStep {
+        let now = Instant::now();
+        let now_secs = unix_now();
+
+        let (expired, pending): (Vec<Job>, Vec<Job>) = state.pending.drain(..)
+            .partition(|job| job.spooled.as_ref().is_some_and(|s| now_secs >= s.expires_at));
+        state.pending = pending.into();
+
+        let mut wait = None;
+        let mut spooled_waiting = false;
+        let mut pick = None;
+        for (index, job) in state.pending.iter().enumerate() {
+            match &job.spooled {
+                Some(_) if spooled_waiting => continue,
+                Some(Spooled { retry_at: Some(at), .. }) if *at > now => {
+                    spooled_waiting = true;
+                    wait = Some(*at - now);
+                }
+                _ => {
+                    pick = Some(index);
+                    break;
+                }
+            }
+        }
+
+        let job = pick.and_then(|index| state.pending.remove(index));
+        if let Some(job) = &job {
+            state.running = Some(JobInfo { state: JobState::Printing, position: None, ..job.info.clone() });
+        }
+        NextStep { job, expired, wait }
+    }
+
+    // Records the final result, cleans up the spool and tells the clients.
+    fn finish(&self, job: Job, result: Result<Output, ServiceError>) {
+        let mut info = job.info;
+        info.position = None;
+        match &result {
+            Ok(_) => {
+                info.state = JobState::Completed;
+                info.error = None;
+            }
+            Err(e) => {
+                // An offline printer fails every status poll: not worth a warning each time
+                if info.kind == JobKind::Print {
+                    warn!("[Queue {}] Job {} failed: {}", self.printer_id, info.job_id, e);
+                } else {
+                    debug!("[Queue {}] Job {} failed: {}", self.printer_id, info.job_id, e);
+                }
+                info.state = JobState::Failed;
+                info.error = Some(e.to_string());
+            }
+        }
+
+        if job.spooled.is_some() {
+            self.spool.remove(&info.job_id);
+            // e.g. { "event": "job_completed", "topic": "job.completed", "device_id": "printer_kitchen", "data": { "job_id": "...", ... } }
+            let topic = if result.is_ok() { events::JOB_COMPLETED } else { events::JOB_FAILED };
+            // Nobody listening is fine
+            let _ = self.events.send(Event::new(topic, &self.printer_id, &info));
+        }
+
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.running.as_ref().is_some_and(|running| running.job_id == info.job_id) {
+                state.running = None;
+            }
+            if info.kind == JobKind::Print {
+                Self::remember(&mut state, info);
+            }
+        }
+        if let Some(done) = job.done {
+            let _ = done.send(result);
+        }
+    }
+
+    fn save_spooled(&self, job: &Job) {
+        let (Some(spooled), Work::Print(data)) = (&job.spooled, &job.work) else { return };
+        self.spool.save(&SpooledJob {
+            job_id: job.info.job_id.clone(),
+            printer_id: self.printer_id.clone(),
+            created_at: job.info.created_at,
+            expires_at: spooled.expires_at,
+            order: spooled.order,
+            attempts: job.info.attempts,
+            last_error: job.info.error.clone(),
+            data: SpooledJob::encode_bytes(data),
+        });
+    }
+
+    fn remember(state: &mut QueueState, info: JobInfo) {
+        state.finished.push_front(info);
+        state.finished.truncate(HISTORY_LEN);
+    }
+}
+
+#[async_trait]
+impl Printer for PrintQueue {
+    fn profile(&self) -> &PrinterProfile {
+        self.printer.profile()
+    }
+
+    fn codepage(&self) -> CodePage {
+        self.printer.codepage()
+    }
+
+    async fn print_text(&self, text: &str) -> Result<(), ServiceError> {
+        let data = receipt::plain_text_job(self.profile(), self.codepage(), text);
+        self.print_raw(&data).await
+    }
+
+    async fn cut_paper(&self) -> Result<(), ServiceError> {
+        let data = ReceiptEncoder::for_printer(self).cut(CutMode::Partial);
+        self.print_raw(&data).await
+    }
+
+    async fn print_raw(&self, data: &[u8]) -> Result<(), ServiceError> {
+        self.run_job(Work::Print(data.to_vec())).await.map(|_| ())
+    }
+
+    async fn query_raw(&self, data: &[u8], reply_len: usize) -> Result<Vec<u8>, ServiceError> {
+        match self.run_job(Work::Query { data: data.to_vec(), reply_len }).await? {
+            Output::Reply(reply) => Ok(reply),
+            _ => Err(ServiceError::InternalError("Unexpected job result".into())),
+        }
+    }
+
+    fn supports_status(&self) -> bool {
+        self.printer.supports_status()
+    }
+
+    async fn status(&self) -> Result<PrinterStatus, ServiceError> {
+        match self.run_job(Work::Status).await? {
+            Output::Status(status) => Ok(status),
+            _ => Err(ServiceError::InternalError("Unexpected job result".into())),
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn unix_now_micros() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_micros() as u64).unwrap_or(0)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tokio::sync::Semaphore;
+
+    // A printer that answers print jobs from a script and remembers what it got
+    pub(crate) struct ScriptedPrinter {
+        profile: PrinterProfile,
+        // Results of the next print_raw calls (Ok once the script runs out)
+        results: Mutex<VecDeque<Result<(), ServiceError>>>,
+        pub(crate) sent: Mutex<Vec<Vec<u8>>>,
+        // When set, every job waits for a permit (keeps a job "printing" as long as the test wants)
+        hold: Option<Semaphore>,
+    }
+
+    impl ScriptedPrinter {
+        pub(crate) fn new(results: Vec<Result<(), ServiceError>>) -> Arc<Self> {
+            Arc::new(Self {
+                profile: PrinterProfile::default(),
+                results: Mutex::new(results.into()),
+                sent: Mutex::new(Vec::new()),
+                hold: None,
+            })
+        }
+
+        // A printer that finishes a job only when the test calls `release`
+        pub(crate) fn held() -> Arc<Self> {
+            Arc::new(Self {
+                profile: PrinterProfile::default(),
+                results: Mutex::new(VecDeque::new()),
+                sent: Mutex::new(Vec::new()),
+                hold: Some(Semaphore::new(0)),
+            })
+        }
+
+        pub(crate) fn release(&self, jobs: usize) {
+            self.hold.as_ref().expect("printer is not held").add_permits(jobs);
+        }
+
+        pub(crate) fn sent(&self) -> Vec<Vec<u8>> {
+            self.sent.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl Printer for ScriptedPrinter {
+        fn profile(&self) -> &PrinterProfile {
+            &self.profile
+        }
+
+        async fn print_text(&self, text: &str) -> Result<(), ServiceError> {
+            self.print_raw(text.as_bytes()).await
+        }
+
+        async fn cut_paper(&self) -> Result<(), ServiceError> {
+            self.print_raw(b"cut").await
+        }
+
+        async fn print_raw(&self, data: &[u8]) -> Result<(), ServiceError> {
+            self.sent.lock().unwrap().push(data.to_vec());
+            if let Some(hold) = &self.hold {
+                hold.acquire().await.unwrap().forget();
+            }
+            self.results.lock().unwrap().pop_front().unwrap_or(Ok(()))
+        }
+    }
+
+    pub(crate) fn queue(printer: Arc<ScriptedPrinter>) -> Arc<PrintQueue> {
+        PrintQueue::new("printer".into(), printer, Arc::new(Spool::disabled()), broadcast::channel(16).0)
+    }
+
+    // Waits (up to 5s) until the worker has brought the job where the test wants it
+    pub(crate) async fn wait_until(queue: &PrintQueue, job_id: &str, done: impl Fn(&JobInfo) -> bool) -> JobInfo {
+        for _ in 0..500 {
+            if let Some(job) = queue.job(job_id).filter(&done) {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("job {} got stuck: {:?}", job_id, queue.job(job_id));
+    }
+
+    pub(crate) async fn wait_for(queue: &PrintQueue, job_id: &str, state: JobState) -> JobInfo {
+        wait_until(queue, job_id, |job| job.state == state).await
+    }
+
+    #[tokio::test]
+    async fn retries_spooled_jobs_that_failed() {
+        let printer = ScriptedPrinter::new(vec![Err(ServiceError::DeviceError("offline".into()))]);
+        let queue = queue(printer.clone());
+        let job = queue.enqueue(b"ticket".to_vec(), None);
+
+        let waiting = wait_until(&queue, &job.job_id, |job| job.attempts == 1 && job.state == JobState::Queued).await;
+        assert_eq!(waiting.error.as_deref(), Some("Device error: offline"));
+
+        queue.retry_now();
+        let done = wait_for(&queue, &job.job_id, JobState::Completed).await;
+        assert_eq!(done.attempts, 2);
+        assert_eq!(printer.sent(), [b"ticket".to_vec(), b"ticket".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn never_resends_partly_sent_jobs() {
+        let printer = ScriptedPrinter::new(vec![Err(ServiceError::PartiallySent("3 of 6 bytes were sent".into()))]);
+        let queue = queue(printer.clone());
+        let job = queue.enqueue(b"ticket".to_vec(), None);
+
+        let failed = wait_for(&queue, &job.job_id, JobState::Failed).await;
+        assert_eq!(failed.attempts, 1);
+        assert_eq!(failed.error.as_deref(), Some("Partly sent: 3 of 6 bytes were sent"));
+
+        queue.retry_now();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(printer.sent(), [b"ticket".to_vec()]);
+    }
+
+    fn job(id: &str, spooled: Option<Spooled>) -> Job {
+        let info = JobInfo {
+            job_id: id.into(),
+            printer_id: "printer".into(),
+            kind: JobKind::Print,
+            state: JobState::Queued,
+            position: None,
+            created_at: unix_now(),
+            expires_at: spooled.as_ref().map(|s| s.expires_at),
+            attempts: 0,
+            error: None,
+        };
+        Job { info, work: Work::Print(id.as_bytes().to_vec()), done: None, spooled }
+    }
+
+    fn spooled(retry_in: Option<Duration>) -> Option<Spooled> {
+        Some(Spooled { order: 0, expires_at: unix_now() + 3600, retry_at: retry_in.map(|delay| Instant::now() + delay) })
+    }
+
+    fn ids(jobs: &VecDeque<Job>) -> Vec<&str> {
+        jobs.iter().map(|job| job.info.job_id.as_str()).collect()
+    }
+
+    #[test]
+    fn jobs_overtake_a_spooled_job_waiting_for_its_retry() {
+        let mut state = QueueState::default();
+        state.pending.push_back(job("waiting", spooled(Some(Duration::from_secs(60)))));
+        state.pending.push_back(job("ticket", spooled(None)));
+        state.pending.push_back(job("drawer", None));
+
+        let step = PrintQueue::next_step(&mut state);
+        // The drawer kick goes first, the next ticket still waits for the one before it
+        assert_eq!(step.job.unwrap().info.job_id, "drawer");
+        assert!(step.wait.is_some_and(|wait| wait <= Duration::from_secs(60)));
+        assert_eq!(state.running.as_ref().unwrap().state, JobState::Printing);
+        assert_eq!(ids(&state.pending), ["waiting", "ticket"]);
+
+        // Only spooled jobs left: nothing to do until the retry is due
+        state.running = None;
+        let step = PrintQueue::next_step(&mut state);
+        assert!(step.job.is_none());
+        assert!(step.wait.is_some());
+    }
+
+    #[test]
+    fn a_due_retry_goes_first() {
+        let mut state = QueueState::default();
+        state.pending.push_back(job("retry", spooled(Some(Duration::ZERO))));
+        state.pending.push_back(job("drawer", None));
+
+        let step = PrintQueue::next_step(&mut state);
+        assert_eq!(step.job.unwrap().info.job_id, "retry");
+        assert!(step.wait.is_none());
+        assert_eq!(ids(&state.pending), ["drawer"]);
+    }
+
+    #[test]
+    fn takes_out_expired_jobs() {
+        let mut state = QueueState::default();
+        let mut late = spooled(None);
+        late.as_mut().unwrap().expires_at = unix_now() - 1;
+        state.pending.push_back(job("late", late));
+        state.pending.push_back(job("ticket", spooled(None)));
+
+        let step = PrintQueue::next_step(&mut state);
+        assert_eq!(step.expired.iter().map(|job| job.info.job_id.as_str()).collect::<Vec<_>>(), ["late"]);
+        assert_eq!(step.job.unwrap().info.job_id, "ticket");
+        assert!(state.pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn expired_jobs_fail_without_printing() {
+        let printer = ScriptedPrinter::new(vec![]);
+        let queue = queue(printer.clone());
+        let job = queue.enqueue(b"late".to_vec(), Some(Duration::ZERO));
+
+        let failed = wait_for(&queue, &job.job_id, JobState::Failed).await;
+        assert_eq!(failed.error.as_deref(), Some("Device error: Job expired after 0 attempt(s): never tried"));
+        assert!(printer.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn waiting_callers_get_one_attempt() {
+        let printer = ScriptedPrinter::new(vec![Err(ServiceError::DeviceError("offline".into()))]);
+        let queue = queue(printer.clone());
+
+        assert!(queue.cut_paper().await.is_err());
+        assert_eq!(printer.sent().len(), 1);
+        let jobs = queue.jobs();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!((jobs[0].state, jobs[0].attempts), (JobState::Failed, 1));
+    }
+
+    #[tokio::test]
+    async fn counts_positions_and_cancels_waiting_jobs() {
+        let printer = ScriptedPrinter::held();
+        let queue = queue(printer.clone());
+
+        let first = queue.enqueue(b"first".to_vec(), None);
+        assert_eq!(first.position, Some(0));
+        wait_for(&queue, &first.job_id, JobState::Printing).await;
+        let second = queue.enqueue(b"second".to_vec(), None);
+        let third = queue.enqueue(b"third".to_vec(), None);
+        assert_eq!(second.position, Some(1));
+        assert_eq!(third.position, Some(2));
+
+        let error = queue.cancel(&first.job_id).unwrap_err();
+        assert!(error.to_string().contains("already printing"), "{}", error);
+        assert_eq!(queue.cancel(&second.job_id).unwrap().state, JobState::Cancelled);
+        let error = queue.cancel(&second.job_id).unwrap_err();
+        assert!(error.to_string().contains("not waiting"), "{}", error);
+
+        // The third job moved up
+        let positions: Vec<_> = queue.jobs().iter().map(|job| (job.job_id.clone(), job.state, job.position)).collect();
+        assert_eq!(positions, [
+            (first.job_id.clone(), JobState::Printing, None),
+            (third.job_id.clone(), JobState::Queued, Some(1)),
+            (second.job_id.clone(), JobState::Cancelled, None),
+        ]);
+
+        printer.release(2);
+        wait_for(&queue, &third.job_id, JobState::Completed).await;
+        assert_eq!(printer.sent(), [b"first".to_vec(), b"third".to_vec()]);
+        assert_eq!(queue.job(&first.job_id).unwrap().state, JobState::Completed);
+    }
+}
@@ -0,0 +1,272 @@
+use serde::Deserialize;
+use crate::encoder::Block;
+use crate::encoder::document::{Align, CutMode, TextStyle};
+use crate::errors::ServiceError;
+
+// =========================================================================
+// ORDER ROUTING
+// =========================================================================
+// The POS sends the whole order once ("print_order"); the service splits it
+// into one ticket per station (bar, grill, pizza...) using the rules from
+// config.toml, and prints each ticket on the station's printer. The POS no
+// longer needs to know which printer makes what.
+//
+// e.g. [[devices.routing.rules]]
+//      category = "drinks"
+//      printer = "printer_bar"
+//
+// Rules are checked from top to bottom; the first one that matches an item wins.
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct RoutingConfig {
+    #[serde(default)]
+    pub rules: Vec<RoutingRule>,
+    // Printer for items that match no rule. Without it, such orders are refused.
+    pub default_printer: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RoutingRule {
+    // An item matches if it has this category and/or station (not case-sensitive).
+    // A rule with neither matches every item.
+    pub category: Option<String>,
+    pub station: Option<String>,
+    // Printer (or printer group) id
+    pub printer: String,
+    // Name printed at the top of the ticket (default: the rule's station, or the printer id)
+    pub title: Option<String>,
+}
+
+// e.g. { "type": "print_order", "data": {
+//          "order_number": "42", "table": "7", "note": "Birthday",
+//          "items": [
+//            { "name": "Cola", "quantity": 2, "category": "drinks" },
+//            { "name": "Burger", "category": "food", "station": "grill", "notes": ["no onions"] }
+//          ] } }
+#[derive(Deserialize, Debug, Clone)]
+pub struct OrderData {
+    pub order_number: String,
+    pub table: Option<String>,
+    pub note: Option<String>,
+    pub items: Vec<OrderItem>,
+    #[serde(default = "default_true")]
+    pub auto_cut: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct OrderItem {
+    pub name: String,
+    #[serde(default = "default_quantity")]
+    pub quantity: u32,
+    pub category: Option<String>,
+    pub station: Option<String>,
+    #[serde(default)]
+    pub notes: Vec<String>,
+}
+
+/// One station's part of an order.
+#[derive(Debug, Clone)]
+pub struct Ticket {
+    pub printer_id: String,
+    pub title: String,
+    pub items: Vec<OrderItem>,
+}
+
+pub struct Router {
+    config: RoutingConfig,
+}
+
+impl Router {
+    pub fn new(config: RoutingConfig) -> Self {
+        Self { config }
+    }
+
+    /// Every printer id the rules can send tickets to.
+    pub fn printer_ids(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = self.config.rules.iter().map(|r| r.printer.as_str()).collect();
+        ids.extend(self.config.default_printer.as_deref());
+        ids
+    }
+
+    /// Splits an order into tickets, one per station, keeping the item order.
+    /// Fails (without printing anything) if an item can't be routed.
+    pub fn split(&self, order: &OrderData) -> Result<Vec<Ticket>, ServiceError> {
+        if order.items.is_empty() {
+            return Err(ServiceError::InvalidCommand("The order has no items".into()));
+        }
+
+        let mut tickets: Vec<Ticket> = Vec::new();
+        let mut unrouted: Vec<&str> = Vec::new();
+
+        for item in &order.items {
+            let target = match self.config.rules.iter().find(|rule| Self::matches(rule, item)) {
+                Some(rule) => {
+                    let title = rule.title.clone()
+                        .or_else(|| rule.station.clone())
+                        .unwrap_or_else(|| rule.printer.clone());
+                    Some((rule.printer.clone(), title))
+                }
+                None => self.config.default_printer.clone().map(|printer| (printer.clone(), printer)),
+            };
+            let Some((printer_id, title)) = target else {
+                unrouted.push(&item.name);
+                continue;
+            };
+
+            // Items for the same printer and title end up on the same ticket
+            match tickets.iter_mut().find(|t| t.printer_id == printer_id && t.title == title) {
+                Some(ticket) => ticket.items.push(item.clone()),
+                None => tickets.push(Ticket { printer_id, title, items: vec![item.clone()] }),
+            }
+        }
+
+        if !unrouted.is_empty() {
+            return Err(ServiceError::InvalidCommand(format!(
+                "No routing rule (and no default_printer) for: {}", unrouted.join(", ")
+            )));
+        }
+        Ok(tickets)
+    }
+
+    fn matches(rule: &RoutingRule, item: &OrderItem) -> bool {
+        let same = |wanted: &Option<String>, actual: &Option<String>| match wanted {
+            Some(wanted) => actual.as_ref().is_some_and(|actual| actual.eq_ignore_ascii_case(wanted)),
+            None => true,
+        };
+        same(&rule.category, &item.category) && same(&rule.station, &item.station)
+    }
+}
+
+impl Ticket {
+    /// Lays out the ticket as a receipt document:
+    /// station name, order number (and table), the items, the order note.
+    pub fn document(&self, order: &OrderData) -> Vec<Block> {
+        let center_big = TextStyle { align: Align::Center, bold: true, double_width: true, double_height: true, ..TextStyle::default() };
+        let center_tall = TextStyle { align: Align::Center, bold: true, double_height: true, ..TextStyle::default() };
+        let item_style = TextStyle { bold: true, double_height: true, ..TextStyle::default() };
+
+        let mut blocks = vec![
+            Block::Text { text: self.title.to_uppercase(), style: center_big },
+            Block::Text { text: format!("Order #{}", order.order_number), style: center_tall.clone() },
+        ];
+        if let Some(table) = &order.table {
+            blocks.push(Block::Text { text: format!("Table {}", table), style: center_tall });
+        }
+        blocks.push(Block::Rule { character: '=' });
+
+        for item in &self.items {
+            blocks.push(Block::Text { text: format!("{} x {}", item.quantity, item.name), style: item_style.clone() });
+            for note in &item.notes {
+                blocks.push(Block::Text { text: format!("   - {}", note), style: TextStyle::default() });
+            }
+        }
+
+        if let Some(note) = &order.note {
+            blocks.push(Block::Rule { character: '-' });
+            blocks.push(Block::Text { text: format!("NOTE: {}", note), style: TextStyle { bold: true, ..TextStyle::default() } });
+        }
+
+        if order.auto_cut {
+            blocks.push(Block::Cut { mode: CutMode::Partial });
+        }
+        blocks
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_quantity() -> u32 {
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn router(rules: serde_json::Value, default_printer: Option<&str>) -> Router {
+        let rules = serde_json::from_value(rules).unwrap();
+        Router::new(RoutingConfig { rules, default_printer: default_printer.map(String::from) })
+    }
+
+    fn order(items: serde_json::Value) -> OrderData {
+        serde_json::from_value(serde_json::json!({ "order_number": "42", "items": items })).unwrap()
+    }
+
+    // (printer, title, item names) of every ticket
+    fn summary(tickets: &[Ticket]) -> Vec<(&str, &str, Vec<&str>)> {
+        tickets.iter()
+            .map(|t| (t.printer_id.as_str(), t.title.as_str(), t.items.iter().map(|i| i.name.as_str()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let router = router(serde_json::json!([
+            { "category": "drinks", "station": "terrace", "printer": "printer_terrace" },
+            { "category": "drinks", "printer": "printer_bar", "title": "Bar" },
+            { "printer": "printer_kitchen" },
+        ]), None);
+        let tickets = router.split(&order(serde_json::json!([
+            { "name": "Cola", "category": "drinks", "station": "terrace" },
+            { "name": "Beer", "category": "drinks" },
+            { "name": "Burger", "category": "food" },
+        ]))).unwrap();
+        assert_eq!(summary(&tickets), [
+            ("printer_terrace", "terrace", vec!["Cola"]),
+            ("printer_bar", "Bar", vec!["Beer"]),
+            ("printer_kitchen", "printer_kitchen", vec!["Burger"]),
+        ]);
+    }
+
+    #[test]
+    fn matches_without_case() {
+        let router = router(serde_json::json!([{ "category": "Drinks", "printer": "printer_bar" }]), None);
+        let tickets = router.split(&order(serde_json::json!([{ "name": "Cola", "category": "DRINKS" }]))).unwrap();
+        assert_eq!(summary(&tickets), [("printer_bar", "printer_bar", vec!["Cola"])]);
+    }
+
+    #[test]
+    fn merges_items_of_one_station_in_order() {
+        let router = router(serde_json::json!([
+            { "category": "drinks", "printer": "printer_bar", "title": "Bar" },
+            { "category": "food", "printer": "printer_kitchen", "title": "Kitchen" },
+        ]), None);
+        let tickets = router.split(&order(serde_json::json!([
+            { "name": "Cola", "category": "drinks" },
+            { "name": "Burger", "category": "food" },
+            { "name": "Beer", "category": "drinks" },
+        ]))).unwrap();
+        assert_eq!(summary(&tickets), [
+            ("printer_bar", "Bar", vec!["Cola", "Beer"]),
+            ("printer_kitchen", "Kitchen", vec!["Burger"]),
+        ]);
+    }
+
+    #[test]
+    fn unmatched_items_go_to_the_default_printer() {
+        let router = router(serde_json::json!([{ "category": "drinks", "printer": "printer_bar" }]), Some("printer_kitchen"));
+        let tickets = router.split(&order(serde_json::json!([
+            { "name": "Soup" },
+            { "name": "Cola", "category": "drinks" },
+        ]))).unwrap();
+        assert_eq!(summary(&tickets), [
+            ("printer_kitchen", "printer_kitchen", vec!["Soup"]),
+            ("printer_bar", "printer_bar", vec!["Cola"]),
+        ]);
+    }
+
+    #[test]
+    fn refuses_orders_with_unrouted_items() {
+        let router = router(serde_json::json!([{ "category": "drinks", "printer": "printer_bar" }]), None);
+        let error = router.split(&order(serde_json::json!([
+            { "name": "Cola", "category": "drinks" },
+            { "name": "Soup", "category": "food" },
+            { "name": "Salad" },
+        ]))).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid command: No routing rule (and no default_printer) for: Soup, Salad");
+
+        assert!(router.split(&order(serde_json::json!([]))).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::metrics;
+
+// =========================================================================
+// BRUTE-FORCE PROTECTION
+// =========================================================================
+// Wrong tokens are counted per client ("peer": IP address + the web page it
+// came from) and for the whole service. After a few free attempts the client
+// is locked out, twice as long after every further failure:
+//   per peer:  3 free attempts, then 1s, 2s, 4s... up to 15 minutes
+//   globally: 20 free attempts, then 1s, 2s, 4s... up to 1 minute
+// While locked out, even the right token is refused. The global lock only
+// applies to peers that sent a wrong token recently: a website guessing
+// from many origins is slowed down, the POS (which never fails) is not.
+// Counters are forgotten after 15 minutes without failures, and a peer's on
+// a successful login.
+
+const PEER_FREE_ATTEMPTS: u32 = 3;
+const PEER_MAX_LOCK: Duration = Duration::from_secs(15 * 60);
+const GLOBAL_FREE_ATTEMPTS: u32 = 20;
+const GLOBAL_MAX_LOCK: Duration = Duration::from_secs(60);
+const FORGET_AFTER: Duration = Duration::from_secs(15 * 60);
+
+/// A connection is closed after this many wrong tokens.
+pub const MAX_FAILURES_PER_CONNECTION: u32 = 5;
+
+/// Who is logging in, for counting failures: "<ip> <origin>".
+/// All clients are on this computer (same IP), so the web page they came from
+/// keeps a bad website from locking out the POS.
+pub fn peer_id(ip: IpAddr, origin: Option<&str>) -> String {
+    format!("{} {}", ip, origin.unwrap_or("-"))
+}
+
+/// One wrong token, as counted for its peer.
+#[derive(Debug, Clone, Copy)]
+pub struct FailedLogin {
+    // Failures of this peer in a row
+    pub attempts: u32,
+    // Set when this failure locked the peer (or everybody) out
+    pub locked_for: Option<Duration>,
+}
+
+#[derive(Default)]
+struct Failures {
+    count: u32,
+    last_failure: Option<Instant>,
+    locked_until: Option<Instant>,
+}
+
+impl Failures {
+    // Locked? Then for how much longer.
+    fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.locked_until.filter(|until| *until > now).map(|until| until - now)
+    }
+
+    fn is_stale(&self, now: Instant) -> bool {
+        self.last_failure.is_none_or(|last| now - last > FORGET_AFTER)
+    }
+
+    // Counts one failure; returns the new lock, if this failure starts one
+    fn fail(&mut self, now: Instant, free_attempts: u32, max_lock: Duration) -> Option<Duration> {
+        if self.is_stale(now) {
+            *self = Failures::default();
+        }
+        self.count += 1;
+        self.last_failure = Some(now);
+        let over = self.count.checked_sub(free_attempts).filter(|over| *over > 0)?;
+        let lock = Duration::from_secs(1u64 << (over - 1).min(20)).min(max_lock);
+        self.locked_until = Some(now + lock);
+        Some(lock)
+    }
+}
+
+#[derive(Default)]
+pub struct Lockout {
+    peers: Mutex<HashMap<String, Failures>>,
+    global: Mutex<Failures>,
+}
+
+impl Lockout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Err(wait) if `peer` must wait before trying again.
+    pub fn check(&self, peer: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let (failed_recently, own) = match self.peers.lock().unwrap().get(peer) {
+            Some(failures) => (!failures.is_stale(now), failures.remaining(now)),
+            None => (false, None),
+        };
+        // Peers without failures are never held back by the others
+        let global = if failed_recently { self.global.lock().unwrap().remaining(now) } else { None };
+        match global.max(own) {
+            Some(wait) => Err(wait),
+            None => Ok(()),
+        }
+    }
+
+    /// Counts a wrong token.
+    pub fn record_failure(&self, peer: &str) -> FailedLogin {
+        let now = Instant::now();
+        metrics::AUTH_FAILURES.increment();
+
+        let global = self.global.lock().unwrap().fail(now, GLOBAL_FREE_ATTEMPTS, GLOBAL_MAX_LOCK);
+        let (attempts, own) = {
+            let mut peers = self.peers.lock().unwrap();
+            // Don't keep every peer that ever failed once
+            peers.retain(|_, failures| !failures.is_stale(now));
+            let failures = peers.entry(peer.to_string()).or_default();
+            let lock = failures.fail(now, PEER_FREE_ATTEMPTS, PEER_MAX_LOCK);
+            (failures.count, lock)
+        };
+
+        let locked_for = global.max(own);
+        if locked_for.is_some() {
+            metrics::AUTH_LOCKOUTS.increment();
+        }
+        FailedLogin { attempts, locked_for }
+    }
+
+    pub fn record_success(&self, peer: &str) {
+        self.peers.lock().unwrap().remove(peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locks_double_after_the_free_attempts() {
+        let start = Instant::now();
+        let mut failures = Failures::default();
+        let locks: Vec<Option<u64>> = (0..8)
+            .map(|i| failures.fail(start + Duration::from_secs(i), 3, Duration::from_secs(10)).map(|lock| lock.as_secs()))
+            .collect();
+        assert_eq!(locks, [None, None, None, Some(1), Some(2), Some(4), Some(8), Some(10)]);
+        assert_eq!(failures.remaining(start + Duration::from_secs(7)), Some(Duration::from_secs(10)));
+        assert_eq!(failures.remaining(start + Duration::from_secs(17)), None);
+    }
+
+    #[test]
+    fn forgets_failures_after_a_quiet_time() {
+        let start = Instant::now();
+        let mut failures = Failures::default();
+        for _ in 0..5 {
+            failures.fail(start, 3, PEER_MAX_LOCK);
+        }
+        let later = start + FORGET_AFTER + Duration::from_secs(1);
+        assert!(failures.is_stale(later));
+        assert_eq!(failures.fail(later, 3, PEER_MAX_LOCK), None);
+        assert_eq!(failures.count, 1);
+    }
+
+    #[test]
+    fn huge_failure_counts_stay_at_the_max_lock() {
+        let start = Instant::now();
+        let mut failures = Failures { count: u32::MAX - 1, last_failure: Some(start), locked_until: None };
+        assert_eq!(failures.fail(start, 3, PEER_MAX_LOCK), Some(PEER_MAX_LOCK));
+    }
+
+    #[test]
+    fn locks_out_only_the_failing_peer() {
+        let lockout = Lockout::new();
+        for attempt in 1..=PEER_FREE_ATTEMPTS {
+            let failed = lockout.record_failure("127.0.0.1 https://evil.test");
+            assert_eq!(failed.attempts, attempt);
+            assert!(failed.locked_for.is_none());
+            assert!(lockout.check("127.0.0.1 https://evil.test").is_ok());
+        }
+        let failed = lockout.record_failure("127.0.0.1 https://evil.test");
+        assert_eq!(failed.locked_for, Some(Duration::from_secs(1)));
+        assert!(lockout.check("127.0.0.1 https://evil.test").is_err());
+        assert!(lockout.check("127.0.0.1 -").is_ok());
+    }
+
+    #[test]
+    fn success_clears_the_peer() {
+        let lockout = Lockout::new();
+        lockout.record_failure("127.0.0.1 -");
+        lockout.record_failure("127.0.0.1 -");
+        lockout.record_success("127.0.0.1 -");
+        assert_eq!(lockout.record_failure("127.0.0.1 -").attempts, 1);
+    }
+
+    #[test]
+    fn global_lock_spares_peers_without_failures() {
+        let lockout = Lockout::new();
+        // One wrong token from each of many origins: no peer reaches its own limit
+        let mut last = None;
+        for i in 0..=GLOBAL_FREE_ATTEMPTS {
+            last = lockout.record_failure(&format!("127.0.0.1 https://{}.evil.test", i)).locked_for;
+        }
+        assert_eq!(last, Some(Duration::from_secs(1)));
+        // The guessing peers wait...
+        assert!(lockout.check("127.0.0.1 https://0.evil.test").is_err());
+        // ...the POS, which never sent a wrong token, doesn't
+        assert!(lockout.check("127.0.0.1 -").is_ok());
+        assert!(lockout.check("127.0.0.1 https://pos.example.com").is_ok());
+    }
+}
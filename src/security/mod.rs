@@ -1,14 +1,329 @@
-#[derive(Clone)]
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use crate::config::ApiKeyConfig;
+use tracing::error;
+
+pub mod lockout;
+pub mod signing;
+
+pub use lockout::{peer_id, FailedLogin, Lockout, MAX_FAILURES_PER_CONNECTION};
+pub use signing::Signature;
+use signing::ReplayGuard;
+
+// How long a new connection may take to send "auth" before it is dropped
+pub const DEFAULT_AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+// =========================================================================
+// API KEYS & PERMISSIONS
+// =========================================================================
+// Every client logs in with an API key. A key has a name (for the logs), a
+// list of scopes (what it may do) and optionally a list of devices (which
+// printers, drawers... it may use). The self-service kiosk gets ["print"],
+// so it can print but never open a cash drawer.
+//
+// Keys are not stored in config.toml, only a salted SHA-256 hash of them
+// ("pos_hardware_service gen-key <name>" creates a key and its hash).
+// The old `auth_token` still works as a key with every scope.
+// A key can also require every command to be signed (see signing.rs).
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    Print,   // print, print_order, print_label, cut, cancel_job
+    Drawer,  // open_drawer
+    Display, // display_update
+    Status,  // get_status, job_status, list_jobs, list_devices, list_ports
+    Admin,   // everything, plus get_metrics and "security.*" events
+}
+
+impl Scope {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scope::Print => "print",
+            Scope::Drawer => "drawer",
+            Scope::Display => "display",
+            Scope::Status => "status",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+pub struct ApiKey {
+    pub name: String,
+    scopes: Vec<Scope>,
+    // None = every device
+    devices: Option<Vec<String>>,
+    salt: Vec<u8>,
+    hash: [u8; 32],
+    // Some = every command must be signed with this secret
+    signing_secret: Option<Vec<u8>>,
+}
+
+impl ApiKey {
+    /// May this key do `scope` (on `device_id`, if the command targets one)?
+    pub fn allows(&self, scope: Scope, device_id: Option<&str>) -> bool {
+        let scope_ok = self.scopes.contains(&Scope::Admin) || self.scopes.contains(&scope);
+        let device_ok = match (&self.devices, device_id) {
+            (Some(devices), Some(device_id)) => devices.iter().any(|d| d == device_id),
+            _ => true,
+        };
+        scope_ok && device_ok
+    }
+
+    pub fn scopes(&self) -> &[Scope] {
+        &self.scopes
+    }
+
+    pub fn requires_signature(&self) -> bool {
+        self.signing_secret.is_some()
+    }
+
+    fn matches(&self, token: &str) -> bool {
+        hash_key(&self.salt, token).ct_eq(&self.hash).into()
+    }
+}
+
+/// Why a login was refused.
+#[derive(Debug)]
+pub enum LoginError {
+    // Wrong token (counted towards a lockout)
+    InvalidToken(FailedLogin),
+    // Too many wrong tokens; the token isn't even checked until this much time has passed
+    LockedOut(Duration),
+}
+
 pub struct SecurityManager {
-    expected_token: String,
+    keys: Vec<Arc<ApiKey>>,
+    // Web pages allowed to connect, e.g. "https://pos.example.com" or "https://*.example.com".
+    // Empty = any page (not recommended).
+    allowed_origins: Vec<String>,
+    auth_timeout: Duration,
+    lockout: Lockout,
+    // Nonces of signed commands already seen
+    replay_guard: ReplayGuard,
 }
 
 impl SecurityManager {
+    // One token with every permission; accepts connections from any web page
     pub fn new(token: String) -> Self {
-        Self { expected_token: token }
+        Self::with_policy(token, &[], Vec::new(), DEFAULT_AUTH_TIMEOUT, signing::DEFAULT_MAX_SKEW)
+    }
+
+    /// `token` is the full-access `auth_token` (empty = none, only `api_keys`).
+    /// Keys with a malformed hash are logged and left out.
+    /// `max_skew` is how old (or new) a signed command's timestamp may be.
+    pub fn with_policy(token: String, api_keys: &[ApiKeyConfig], allowed_origins: Vec<String>, auth_timeout: Duration, max_skew: Duration) -> Self {
+        let mut keys = Vec::new();
+        if !token.is_empty() {
+            let salt = random_bytes();
+            let hash = hash_key(&salt, &token);
+            keys.push(Arc::new(ApiKey { name: "auth_token".into(), scopes: vec![Scope::Admin], devices: None, salt, hash, signing_secret: None }));
+        }
+        for config in api_keys {
+            match parse_key_hash(&config.key_hash) {
+                Some((salt, hash)) => keys.push(Arc::new(ApiKey {
+                    name: config.name.clone(),
+                    scopes: config.scopes.clone(),
+                    devices: config.devices.clone(),
+                    salt,
+                    hash,
+                    signing_secret: config.signing_secret.as_ref().map(|secret| secret.as_bytes().to_vec()),
+                })),
+                None => error!("API key '{}' has an invalid key_hash (expected \"sha256:<salt>:<hash>\"), ignoring it", config.name),
+            }
+        }
+
+        let allowed_origins = allowed_origins.iter()
+            .map(|origin| origin.trim_end_matches('/').to_ascii_lowercase())
+            .collect();
+        Self { keys, allowed_origins, auth_timeout, lockout: Lockout::new(), replay_guard: ReplayGuard::new(max_skew) }
+    }
+
+    /// Finds the key for this token. Every key is checked in constant time,
+    /// so the answer time doesn't tell how close a guess was.
+    pub fn authenticate(&self, token: &str) -> Option<Arc<ApiKey>> {
+        let mut found = None;
+        for key in &self.keys {
+            if key.matches(token) && found.is_none() {
+                found = Some(key.clone());
+            }
+        }
+        found
+    }
+
+    /// `authenticate` with brute-force protection: wrong tokens from `peer`
+    /// (see `peer_id`) are counted and lock it out for a while.
+    pub fn login(&self, peer: &str, token: &str) -> Result<Arc<ApiKey>, LoginError> {
+        self.lockout.check(peer).map_err(LoginError::LockedOut)?;
+        match self.authenticate(token) {
+            Some(key) => {
+                self.lockout.record_success(peer);
+                Ok(key)
+            }
+            None => Err(LoginError::InvalidToken(self.lockout.record_failure(peer))),
+        }
+    }
+
+    /// Checks a command's signature, if `key` only accepts signed commands.
+    /// `payload` is what the signature covers (see signing.rs).
+    pub fn verify_signature(&self, key: &ApiKey, payload: &[u8], signature: Signature) -> Result<(), String> {
+        let Some(secret) = &key.signing_secret else { return Ok(()) };
+        let (Some(timestamp), Some(nonce), Some(signature)) = (signature.timestamp, signature.nonce, signature.signature) else {
+            return Err("Signature required: this key only accepts signed commands (timestamp, nonce, signature)".into());
+        };
+        if !signing::signature_matches(secret, payload, signature) {
+            return Err("Invalid signature".into());
+        }
+        // Only after the signature: nobody else can use up a nonce
+        self.replay_guard.check(&key.name, timestamp, nonce)
+    }
+
+    pub fn auth_timeout(&self) -> Duration {
+        self.auth_timeout
+    }
+
+    pub fn restricts_origins(&self) -> bool {
+        !self.allowed_origins.is_empty()
+    }
+
+    /// May a client with this `Origin` header connect?
+    /// Browsers always send the header; programs (no header) are always allowed,
+    /// a random website can't connect without one.
+    pub fn origin_allowed(&self, origin: Option<&str>) -> bool {
+        let Some(origin) = origin else { return true };
+        if self.allowed_origins.is_empty() {
+            return true;
+        }
+        let origin = origin.to_ascii_lowercase();
+        self.allowed_origins.iter().any(|allowed| origin_matches(allowed, &origin))
+    }
+}
+
+/// Creates a new random API key and the `key_hash` to put in config.toml.
+pub fn generate_api_key() -> (String, String) {
+    let key = to_hex(&[random_bytes(), random_bytes()].concat());
+    let salt = random_bytes();
+    let key_hash = format!("sha256:{}:{}", to_hex(&salt), to_hex(&hash_key(&salt, &key)));
+    (key, key_hash)
+}
+
+/// Creates a random `signing_secret` (shared by the service and the client).
+pub fn generate_signing_secret() -> String {
+    to_hex(&[random_bytes(), random_bytes()].concat())
+}
+
+fn hash_key(salt: &[u8], token: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(token.as_bytes());
+    hasher.finalize().into()
+}
+
+// "sha256:<salt hex>:<hash hex>"
+fn parse_key_hash(text: &str) -> Option<(Vec<u8>, [u8; 32])> {
+    let mut parts = text.trim().split(':');
+    if parts.next()? != "sha256" {
+        return None;
+    }
+    let salt = from_hex(parts.next()?)?;
+    let hash = from_hex(parts.next()?)?.try_into().ok()?;
+    if parts.next().is_some() || salt.is_empty() {
+        return None;
+    }
+    Some((salt, hash))
+}
+
+// 16 bytes from the OS random generator (a UUID v4 is exactly that, minus 6 fixed bits)
+fn random_bytes() -> Vec<u8> {
+    uuid::Uuid::new_v4().as_bytes().to_vec()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// "https://*.example.com" matches "https://a.example.com" and "https://a.b.example.com",
+// but not "https://example.com" (list it separately). "*" matches every origin.
+fn origin_matches(allowed: &str, origin: &str) -> bool {
+    if allowed == "*" {
+        return true;
+    }
+    match allowed.split_once("://*.") {
+        Some((scheme, domain)) => origin
+            .strip_prefix(scheme)
+            .and_then(|rest| rest.strip_prefix("://"))
+            .and_then(|host| host.strip_suffix(domain))
+            // Only a host name may come before the domain ("https://evil.com/x.example.com" is no subdomain)
+            .is_some_and(|subdomain| subdomain.len() > 1 && subdomain.ends_with('.')
+                && subdomain.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.')),
+        None => allowed == origin,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_origins(origins: &[&str]) -> SecurityManager {
+        let origins = origins.iter().map(|origin| origin.to_string()).collect();
+        SecurityManager::with_policy("token".into(), &[], origins, DEFAULT_AUTH_TIMEOUT, signing::DEFAULT_MAX_SKEW)
+    }
+
+    #[test]
+    fn exact_origins() {
+        assert!(origin_matches("https://pos.example.com", "https://pos.example.com"));
+        assert!(!origin_matches("https://pos.example.com", "http://pos.example.com"));
+        assert!(!origin_matches("https://pos.example.com", "https://pos.example.com:8443"));
+        assert!(!origin_matches("https://pos.example.com", "https://pos.example.com.evil.com"));
+    }
+
+    #[test]
+    fn wildcard_origins() {
+        let allowed = "https://*.example.com";
+        assert!(origin_matches(allowed, "https://a.example.com"));
+        assert!(origin_matches(allowed, "https://a.b.example.com"));
+        assert!(origin_matches(allowed, "https://my-shop.example.com"));
+        // The domain itself must be listed separately
+        assert!(!origin_matches(allowed, "https://example.com"));
+        assert!(!origin_matches(allowed, "https://.example.com"));
+        assert!(!origin_matches(allowed, "https://evilexample.com"));
+        assert!(!origin_matches(allowed, "https://a.example.com.evil.com"));
+        assert!(!origin_matches(allowed, "https://evil.com/x.example.com"));
+        assert!(!origin_matches(allowed, "https://evil.com#.example.com"));
+        assert!(!origin_matches(allowed, "https://evil.com?.example.com"));
+        assert!(!origin_matches(allowed, "https://user@evil.com:1.example.com"));
+        assert!(!origin_matches(allowed, "http://a.example.com"));
+        assert!(!origin_matches(allowed, "https://a.example.com:8443"));
+        assert!(origin_matches("*", "https://anything.test"));
+    }
+
+    #[test]
+    fn origin_allowed_ignores_case_and_trailing_slash() {
+        let security = with_origins(&["https://POS.example.com/", "https://*.shop.test"]);
+        assert!(security.origin_allowed(Some("https://pos.example.com")));
+        assert!(security.origin_allowed(Some("HTTPS://Till-1.Shop.Test")));
+        assert!(!security.origin_allowed(Some("https://evil.test")));
+        assert!(!security.origin_allowed(Some("null")));
+        // Programs don't send an Origin header
+        assert!(security.origin_allowed(None));
     }
 
-    pub fn validate_token(&self, token: &str) -> bool {
-        self.expected_token == token
+    #[test]
+    fn no_allowed_origins_allows_everyone() {
+        let security = with_origins(&[]);
+        assert!(!security.restricts_origins());
+        assert!(security.origin_allowed(Some("https://evil.test")));
     }
 }
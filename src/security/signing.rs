@@ -0,0 +1,223 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use super::from_hex;
+
+// =========================================================================
+// SIGNED COMMANDS
+// =========================================================================
+// A key with a `signing_secret` only accepts commands signed with that secret,
+// so someone who sniffed the token still can't open the drawer with it.
+// Every command carries three extra values:
+//   timestamp: Unix time in seconds, at most `signature_max_skew_secs` away from our clock
+//   nonce:     any unique string (e.g. a random UUID), accepted only once
+//   signature: HMAC-SHA256 of the command with the secret as key, in hex
+//
+// WebSocket: the three are fields of the message. The signature covers the
+// message without "signature", as JSON with sorted keys (at every level) and
+// no spaces:
+//   {"device_id":"drawer_main","nonce":"5f0c...","timestamp":1760000000,"type":"open_drawer"}
+// HTTP: they go in the X-Timestamp, X-Nonce and X-Signature headers, and the
+// signature covers "<METHOD>\n<path>\n<timestamp>\n<nonce>\n<body>".
+
+pub const DEFAULT_MAX_SKEW: Duration = Duration::from_secs(30);
+
+// Nonces remembered at once; more signed commands than this within the
+// time window are refused rather than forgetting a nonce too early
+const NONCE_CACHE_SIZE: usize = 10_000;
+const MAX_NONCE_LEN: usize = 128;
+
+/// The signature values of one command (None = missing).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Signature<'a> {
+    pub timestamp: Option<u64>,
+    pub nonce: Option<&'a str>,
+    pub signature: Option<&'a str>,
+}
+
+/// What the signature of a WebSocket message covers: the message without its
+/// "signature" field, with sorted keys and no spaces.
+pub fn canonical_message(message: &serde_json::Value) -> String {
+    let mut message = message.clone();
+    if let Some(fields) = message.as_object_mut() {
+        fields.remove("signature");
+    }
+    let mut out = String::new();
+    write_canonical(&message, &mut out);
+    out
+}
+
+fn write_canonical(value: &serde_json::Value, out: &mut String) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            let mut keys: Vec<&String> = fields.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::Value::from(key.as_str()).to_string());
+                out.push(':');
+                write_canonical(&fields[key], out);
+            }
+            out.push('}');
+        }
+        serde_json::Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+/// What the signature of an HTTP request covers.
+pub fn http_payload(method: &str, path: &str, timestamp: &str, nonce: &str, body: &[u8]) -> Vec<u8> {
+    let mut payload = format!("{}\n{}\n{}\n{}\n", method, path, timestamp, nonce).into_bytes();
+    payload.extend_from_slice(body);
+    payload
+}
+
+// Compares in constant time
+pub(super) fn signature_matches(secret: &[u8], payload: &[u8], signature: &str) -> bool {
+    let Some(signature) = from_hex(signature) else { return false };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret) else { return false };
+    mac.update(payload);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Rejects old commands and commands seen before.
+pub(super) struct ReplayGuard {
+    max_skew: Duration,
+    seen: Mutex<SeenNonces>,
+}
+
+#[derive(Default)]
+struct SeenNonces {
+    nonces: HashSet<String>,
+    // Oldest first, to forget them once their timestamp can't be accepted anymore
+    order: VecDeque<(Instant, String)>,
+}
+
+impl ReplayGuard {
+    pub(super) fn new(max_skew: Duration) -> Self {
+        Self { max_skew, seen: Mutex::new(SeenNonces::default()) }
+    }
+
+    /// Accepts `nonce` of key `key_name` once, if `timestamp` is close enough to now.
+    pub(super) fn check(&self, key_name: &str, timestamp: u64, nonce: &str) -> Result<(), String> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        if now.abs_diff(timestamp) > self.max_skew.as_secs() {
+            return Err(format!("Expired signature: the timestamp is {}s away from the service's clock (max {}s)",
+                now.abs_diff(timestamp), self.max_skew.as_secs()));
+        }
+        if nonce.is_empty() || nonce.len() > MAX_NONCE_LEN {
+            return Err(format!("Invalid nonce: use 1 to {} characters", MAX_NONCE_LEN));
+        }
+
+        let now = Instant::now();
+        let mut seen = self.seen.lock().unwrap();
+        // A timestamp is accepted from max_skew before to max_skew after it was
+        // made, so its nonce must be remembered that long
+        while let Some((since, _)) = seen.order.front() {
+            if now.duration_since(*since) <= self.max_skew * 2 {
+                break;
+            }
+            let (_, old) = seen.order.pop_front().unwrap();
+            seen.nonces.remove(&old);
+        }
+
+        let nonce = format!("{}:{}", key_name, nonce);
+        if seen.nonces.contains(&nonce) {
+            return Err("Replayed command: this nonce was already used".into());
+        }
+        if seen.nonces.len() >= NONCE_CACHE_SIZE {
+            return Err("Too many signed commands, try again in a few seconds".into());
+        }
+        seen.nonces.insert(nonce.clone());
+        seen.order.push_back((now, nonce));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    #[test]
+    fn canonical_message_sorts_keys_and_drops_the_signature() {
+        let message = json!({
+            "type": "print", "signature": "ab12", "timestamp": 1760000000,
+            "data": { "text": "Café", "document": [{ "type": "cut", "mode": "full" }] },
+        });
+        assert_eq!(canonical_message(&message),
+            r#"{"data":{"document":[{"mode":"full","type":"cut"}],"text":"Café"},"timestamp":1760000000,"type":"print"}"#);
+    }
+
+    #[test]
+    fn canonical_message_keeps_nested_signature_fields() {
+        let message = json!({ "data": { "signature": "x" } });
+        assert_eq!(canonical_message(&message), r#"{"data":{"signature":"x"}}"#);
+    }
+
+    #[test]
+    fn signature_matches_hmac_sha256() {
+        // RFC 4231, test case 2
+        let signature = "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843";
+        assert!(signature_matches(b"Jefe", b"what do ya want for nothing?", signature));
+        assert!(signature_matches(b"Jefe", b"what do ya want for nothing?", &signature.to_uppercase()));
+        assert!(!signature_matches(b"Jefe", b"what do ya want for nothing!", signature));
+        assert!(!signature_matches(b"jefe", b"what do ya want for nothing?", signature));
+        assert!(!signature_matches(b"Jefe", b"what do ya want for nothing?", &signature[..62]));
+        assert!(!signature_matches(b"Jefe", b"what do ya want for nothing?", "not hex"));
+        assert!(!signature_matches(b"Jefe", b"what do ya want for nothing?", ""));
+    }
+
+    #[test]
+    fn http_payload_joins_the_parts() {
+        assert_eq!(http_payload("POST", "/v1/print", "1760000000", "n1", b"{}"), b"POST\n/v1/print\n1760000000\nn1\n{}");
+    }
+
+    #[test]
+    fn replay_guard_accepts_a_nonce_once() {
+        let guard = ReplayGuard::new(DEFAULT_MAX_SKEW);
+        assert!(guard.check("pos", now(), "n1").is_ok());
+        assert!(guard.check("pos", now(), "n1").unwrap_err().starts_with("Replayed"));
+        assert!(guard.check("pos", now(), "n2").is_ok());
+        // Nonces are per key
+        assert!(guard.check("admin", now(), "n1").is_ok());
+    }
+
+    #[test]
+    fn replay_guard_rejects_timestamps_outside_the_skew() {
+        let guard = ReplayGuard::new(Duration::from_secs(30));
+        assert!(guard.check("pos", now() - 25, "a").is_ok());
+        assert!(guard.check("pos", now() + 25, "b").is_ok());
+        assert!(guard.check("pos", now() - 60, "c").unwrap_err().starts_with("Expired"));
+        assert!(guard.check("pos", now() + 60, "d").unwrap_err().starts_with("Expired"));
+        assert!(guard.check("pos", 0, "e").unwrap_err().starts_with("Expired"));
+        // A refused command doesn't use up its nonce
+        assert!(guard.check("pos", now(), "c").is_ok());
+    }
+
+    #[test]
+    fn replay_guard_rejects_bad_nonces() {
+        let guard = ReplayGuard::new(DEFAULT_MAX_SKEW);
+        assert!(guard.check("pos", now(), "").is_err());
+        assert!(guard.check("pos", now(), &"x".repeat(MAX_NONCE_LEN + 1)).is_err());
+        assert!(guard.check("pos", now(), &"x".repeat(MAX_NONCE_LEN)).is_ok());
+    }
+}
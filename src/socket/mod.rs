@@ -1,11 +1,26 @@
-use tokio::net::{TcpListener, TcpStream};
-use tokio_tungstenite::accept_async;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use tokio_tungstenite::accept_hdr_async;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request as HandshakeRequest, Response as HandshakeResponse};
+use tokio_tungstenite::tungstenite::http::{header, StatusCode};
 use futures::{StreamExt, SinkExt};
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use crate::device_manager::DeviceManager;
-use crate::security::SecurityManager;
-use crate::errors::ServiceError;
+use crate::security::{peer_id, signing, ApiKey, LoginError, Scope, SecurityManager, Signature, MAX_FAILURES_PER_CONNECTION};
+use crate::tls::Tls;
+use crate::errors::{ErrorKind, ServiceError};
+use crate::encoder::{Block, Label, ReceiptEncoder};
+use crate::encoder::document::CutMode;
+use crate::routing::OrderData;
+use crate::hardware::transport::ports;
+use crate::events::{self, Event, Subscriptions};
+use crate::metrics;
 use tracing::{info, error, warn, debug};
 
 // =========================================================================
@@ -14,16 +29,75 @@ use tracing::{info, error, warn, debug};
 // These structures define the JSON messages that the POS Client sends to us.
 // e.g., { "type": "auth", "token": "..." }
 // e.g., { "type": "print", "device_id": "printer_kitchen", "data": { "text": "Hello" } }
+// e.g., { "type": "print", "device_id": "printer_kitchen", "data": { "document": [{ "type": "text", "text": "Hello", "bold": true }] } }
+// e.g., { "type": "print_label", "device_id": "label_deli", "data": { "width_mm": 58, "height_mm": 40, "fields": [...] } }
+// e.g., { "type": "print_order", "data": { "order_number": "42", "items": [{ "name": "Cola", "quantity": 2, "category": "drinks" }] } }
+// e.g., { "type": "get_status", "device_id": "printer_kitchen" }
+// e.g., { "type": "job_status", "job_id": "6f1c..." }
+// e.g., { "type": "subscribe", "topics": ["printer.status", "drawer.state"] }
+// e.g., { "type": "list_devices" }
+// e.g., { "type": "list_ports" }
+// e.g., { "type": "get_metrics" }
+//
+// Any command may carry a "request_id"; it is copied into the answer so the
+// client can send several commands without waiting and match the answers.
+// e.g., { "type": "open_drawer", "device_id": "drawer_main", "request_id": "a1" }
+//
+// Keys with a `signing_secret` must also sign every command (see security/signing.rs):
+// e.g., { "type": "open_drawer", "device_id": "drawer_main", "timestamp": 1760000000, "nonce": "5f0c...", "signature": "9a3e..." }
+
+// One message from the client: the command, plus its optional request id and signature.
+#[derive(Deserialize, Debug)]
+pub struct Request {
+    pub request_id: Option<String>,
+    pub timestamp: Option<u64>,
+    pub nonce: Option<String>,
+    pub signature: Option<String>,
+    #[serde(flatten)]
+    pub command: Command,
+}
 
 #[derive(Deserialize, Debug)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Command {
     // Step 1: Client must send this first to log in.
     Auth { token: String },
+
+    // Start/stop receiving events pushed by the service ("printer.status",
+    // "drawer.state", "job.completed"... or "job.*", "*").
+    // New connections are subscribed to "job.*".
+    Subscribe { topics: Vec<String> },
+    Unsubscribe { topics: Vec<String> },
     
     // Command to print something.
+    // `device_id` can be a printer or a printer group.
     Print { device_id: String, data: PrintData },
 
+    // Command to print a kitchen order: the service splits it into one
+    // ticket per station and sends each ticket to the right printer.
+    PrintOrder { data: OrderData },
+
+    // Command to print a label (price tag, deli label...) on a label printer.
+    PrintLabel { device_id: String, data: Label },
+
+    // Command to ask a printer for its real-time status (paper, cover, errors...).
+    GetStatus { device_id: String },
+
+    // Commands to follow or cancel queued print jobs.
+    JobStatus { job_id: String },
+    CancelJob { job_id: String },
+    ListJobs { device_id: String },
+
+    // Command to list every configured printer, drawer and display.
+    ListDevices,
+
+    // Command to list the serial ports of this computer, with their USB ids
+    // (to write `connection = "usb:vid=...,pid=..."` in config.toml).
+    ListPorts,
+
+    // Command to read the service's counters (failed logins...). Admin keys only.
+    GetMetrics,
+
     // Command to cut the paper (standalone).
     Cut { device_id: String },
     
@@ -34,11 +108,26 @@ pub enum Command {
     DisplayUpdate { device_id: String, data: DisplayData },
 }
 
+impl Command {
+    // Commands that wait for the device itself (a reply, a cut, a drawer...).
+    // Print jobs aren't slow: they are queued and answered at once.
+    fn is_slow(&self) -> bool {
+        matches!(self,
+            Command::PrintLabel { .. } | Command::GetStatus { .. } | Command::Cut { .. }
+            | Command::OpenDrawer { .. } | Command::DisplayUpdate { .. })
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct PrintData {
     pub text: Option<String>,
+    // Structured receipt, compiled to printer commands by the encoder
+    pub document: Option<Vec<Block>>,
     #[serde(default)]
     pub auto_cut: bool, // Defaults to false if missing in JSON
+    // Give up if the printer is still unreachable after this many seconds
+    // (default: `job_expiry_secs` from the [spool] config)
+    pub expires_in_secs: Option<u64>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -47,180 +136,577 @@ pub struct DisplayData {
     pub line2: String,
 }
 
+// What we know about one connected client
+struct Session {
+    // Who is connected, for counting failed logins (see `peer_id`)
+    peer: String,
+    // The API key the client logged in with (None = not logged in yet)
+    key: Option<Arc<ApiKey>>,
+    // Wrong tokens on this connection; it is closed at MAX_FAILURES_PER_CONNECTION
+    failed_logins: u32,
+    subscriptions: Subscriptions,
+}
+
+impl Session {
+    // Events go to logged-in clients that subscribed to the topic
+    // and whose key may see that device
+    fn wants(&self, event: &events::Event) -> bool {
+        let Some(key) = &self.key else { return false };
+        let scope = match event.topic.as_str() {
+            events::JOB_COMPLETED | events::JOB_FAILED => Scope::Print,
+            events::DRAWER_STATE => Scope::Drawer,
+            topic if topic.starts_with("security.") => Scope::Admin,
+            _ => Scope::Status,
+        };
+        // Events without a device (e.g. security events) only need the scope
+        let device_id = Some(event.device_id.as_str()).filter(|id| !id.is_empty());
+        self.subscriptions.wants(&event.topic) && key.allows(scope, device_id)
+    }
+}
+
 #[derive(Serialize, Debug)]
 pub struct Response {
-    pub status: String, // "ok" or "error"
+    // Copied from the request, if it had one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    // "ok" or "error". Slow commands sent with a request_id first get
+    // "accepted", then "completed" or "failed" once the device is done.
+    pub status: String,
+    // What went wrong, for the HTTP status code (not sent to clients)
+    #[serde(skip)]
+    pub error: Option<ErrorKind>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub device_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub message: Option<String>,
+    // Command-specific result (e.g. the printer status for "get_status")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
 }
 
 // -------------------------------------------------------------------------
 // SERVER LOGIC
 // -------------------------------------------------------------------------
 
-pub async fn run_server(port: u16, devices: Arc<DeviceManager>, security: Arc<SecurityManager>) -> Result<(), ServiceError> {
+// With `tls`, clients connect with wss:// instead of ws://
+pub async fn run_server(port: u16, devices: Arc<DeviceManager>, security: Arc<SecurityManager>, tls: Option<Arc<Tls>>) -> Result<(), ServiceError> {
     let addr = format!("127.0.0.1:{}", port);
     // Bind to the local TCP port
     let listener = TcpListener::bind(&addr).await
         .map_err(|e| ServiceError::IoError(e.to_string()))?;
     
-    info!("WebSocket server listening on {}", addr);
+    info!("WebSocket server listening on {}://{}", if tls.is_some() { "wss" } else { "ws" }, addr);
 
     // Accept incoming connections in a loop
-    while let Ok((stream, _)) = listener.accept().await {
+    while let Ok((stream, addr)) = listener.accept().await {
         let devices = devices.clone();
         let security = security.clone();
+        info!("Incoming connection from {}", addr);
+        // The handshakes count towards the login deadline, so a silent client can't hold the connection
+        let deadline = tokio::time::Instant::now() + security.auth_timeout();
         // Spawn a new background task for each client connection
-        tokio::spawn(accept_connection(stream, devices, security));
+        match &tls {
+            Some(tls) => {
+                let acceptor = tls.acceptor();
+                tokio::spawn(async move {
+                    // TLS handshake first, then the same WebSocket as without TLS
+                    match tokio::time::timeout_at(deadline, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => accept_connection(stream, addr, devices, security, deadline).await,
+                        Ok(Err(e)) => warn!("TLS handshake with {} failed: {}", addr, e),
+                        Err(_) => warn!("TLS handshake with {} timed out", addr),
+                    }
+                });
+            }
+            None => {
+                tokio::spawn(accept_connection(stream, addr, devices, security, deadline));
+            }
+        }
     }
 
     Ok(())
 }
 
-async fn accept_connection(stream: TcpStream, devices: Arc<DeviceManager>, security: Arc<SecurityManager>) {
-    let addr = stream.peer_addr().unwrap_or_else(|_| "unknown".parse().unwrap());
-    info!("Incoming connection from {}", addr);
-
-    // Perform the WebSocket Handshake (upgrade TCP to WebSocket)
-    let ws_stream = match accept_async(stream).await {
-        Ok(ws) => ws,
-        Err(e) => {
+// `deadline`: when a client that hasn't logged in yet is disconnected
+async fn accept_connection<S>(stream: S, addr: SocketAddr, devices: Arc<DeviceManager>, security: Arc<SecurityManager>, deadline: tokio::time::Instant)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // Perform the WebSocket Handshake (upgrade TCP to WebSocket).
+    // Browsers say which web page opens the connection (Origin header); pages
+    // that are not in `allowed_origins` are turned away before they can try a token.
+    let mut origin = None;
+    #[allow(clippy::result_large_err)] // tungstenite decides the callback's signature
+    let check_origin = |request: &HandshakeRequest, response: HandshakeResponse| -> Result<HandshakeResponse, ErrorResponse> {
+        origin = request.headers().get(header::ORIGIN).map(|value| value.to_str().unwrap_or("(invalid)").to_string());
+        let origin = origin.as_deref();
+        if security.origin_allowed(origin) {
+            return Ok(response);
+        }
+        warn!("[Audit] Rejected WebSocket connection from {}: origin {} is not allowed", addr, origin.unwrap_or_default());
+        let mut rejection = ErrorResponse::new(Some("Origin not allowed".into()));
+        *rejection.status_mut() = StatusCode::FORBIDDEN;
+        Err(rejection)
+    };
+    let ws_stream = match tokio::time::timeout_at(deadline, accept_hdr_async(stream, check_origin)).await {
+        Ok(Ok(ws)) => ws,
+        Ok(Err(e)) => {
             error!("Error during the websocket handshake: {}", e);
             return;
         }
+        Err(_) => {
+            warn!("WebSocket handshake from {} timed out", addr);
+            return;
+        }
     };
 
     let (mut write, mut read) = ws_stream.split();
-    let mut authenticated = false; // connection starts unauthenticated
-
-    // Loop through every message the client sends
-    while let Some(msg_result) = read.next().await {
-        match msg_result {
-            Ok(msg) => {
-                if msg.is_text() {
-                    let text = msg.to_text().unwrap();
-                    debug!("Received: {}", text);
-
-                    // Process the command and get a result
-                    let result = process_message(text, &mut authenticated, &devices, &security).await;
-                    
-                    // Send the result back to the client as JSON
-                    let response_json = serde_json::to_string(&result).unwrap();
-                    if let Err(e) = write.send(tokio_tungstenite::tungstenite::Message::Text(response_json)).await {
-                        error!("Failed to send response: {}", e);
+    let mut session = Session {
+        peer: peer_id(addr.ip(), origin.as_deref()),
+        key: None,
+        failed_logins: 0,
+        subscriptions: Subscriptions::new(),
+    };
+
+    // Events from the devices (job results, printer status...), passed on to the
+    // client. Only received after a successful login: the status monitor polls
+    // the printers only while someone is listening.
+    let mut events: Option<broadcast::Receiver<Event>> = None;
+
+    // Answers of slow commands that run in the background
+    let (replies_tx, mut replies) = mpsc::unbounded_channel::<Response>();
+
+    // A client that doesn't log in within `auth_timeout` is disconnected
+    let login_deadline = tokio::time::sleep_until(deadline);
+    tokio::pin!(login_deadline);
+
+    // Wait for whatever comes first: a message from the client, a finished
+    // background command, a job event or the login deadline
+    loop {
+        tokio::select! {
+            _ = &mut login_deadline, if session.key.is_none() => {
+                warn!("[Audit] Client {} did not authenticate in time, disconnecting", addr);
+                let response = Response { request_id: None, status: "error".into(), error: Some(ErrorKind::Auth), device_id: None, message: Some("Authentication timeout".into()), data: None };
+                let _ = write.send(Message::Text(serde_json::to_string(&response).unwrap())).await;
+                let _ = write.send(Message::Close(None)).await;
+                break;
+            }
+            msg_result = read.next() => {
+                let Some(msg_result) = msg_result else { break };
+                match msg_result {
+                    Ok(msg) => {
+                        if msg.is_text() {
+                            let text = msg.to_text().unwrap();
+                            debug!("Received: {}", redacted(text));
+
+                            // Process the command and get a result
+                            let result = process_message(text, &mut session, &devices, &security, &replies_tx).await;
+                            if session.key.is_some() && events.is_none() {
+                                events = Some(devices.subscribe_events());
+                            }
+
+                            // Send the result back to the client as JSON
+                            let response_json = serde_json::to_string(&result).unwrap();
+                            if let Err(e) = write.send(Message::Text(response_json)).await {
+                                error!("Failed to send response: {}", e);
+                                break;
+                            }
+
+                            // Someone guessing tokens doesn't get to keep trying on this connection
+                            if session.failed_logins >= MAX_FAILURES_PER_CONNECTION {
+                                warn!("[Audit] Closing connection from {} after {} failed logins", addr, session.failed_logins);
+                                let _ = write.send(Message::Close(None)).await;
+                                break;
+                            }
+                        } else if msg.is_close() {
+                            info!("Client disconnected");
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        error!("Error processing message: {}", e);
                         break;
                     }
-                } else if msg.is_close() {
-                    info!("Client disconnected");
+                }
+            }
+            Some(response) = replies.recv() => {
+                let response_json = serde_json::to_string(&response).unwrap();
+                if let Err(e) = write.send(Message::Text(response_json)).await {
+                    error!("Failed to send response: {}", e);
                     break;
                 }
             }
-            Err(e) => {
-                error!("Error processing message: {}", e);
-                break;
+            event = next_event(&mut events) => {
+                match event {
+                    // Only logged-in clients may see events, and only the topics they asked for
+                    Ok(event) if session.wants(&event) => {
+                        let event_json = serde_json::to_string(&event).unwrap();
+                        if let Err(e) = write.send(Message::Text(event_json)).await {
+                            error!("Failed to send event: {}", e);
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => warn!("Client {} missed {} events", addr, missed),
+                    // Only happens when the service shuts down
+                    Err(RecvError::Closed) => break,
+                }
             }
         }
     }
 }
 
-async fn process_message(text: &str, authenticated: &mut bool, devices: &Arc<DeviceManager>, security: &Arc<SecurityManager>) -> Response {
-    let command: Result<Command, _> = serde_json::from_str(text);
+// The next device event, or never before the client has logged in
+async fn next_event(events: &mut Option<broadcast::Receiver<Event>>) -> Result<Event, RecvError> {
+    match events {
+        Some(events) => events.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn process_message(
+    text: &str,
+    session: &mut Session,
+    devices: &Arc<DeviceManager>,
+    security: &Arc<SecurityManager>,
+    replies: &mpsc::UnboundedSender<Response>,
+) -> Response {
+    let request: Request = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(e) => {
+            warn!("Invalid JSON: {}", e);
+            // Still try to return the request id, so the client knows which message was wrong
+            let request_id = serde_json::from_str::<serde_json::Value>(text).ok()
+                .and_then(|value| value.get("request_id")?.as_str().map(String::from));
+            return Response { request_id, status: "error".into(), error: Some(ErrorKind::InvalidCommand), device_id: None, message: Some(format!("Invalid JSON format: {}", e)), data: None };
+        }
+    };
 
-    match command {
-        Ok(Command::Auth { token }) => {
-            if security.validate_token(&token) {
-                *authenticated = true;
-                Response { status: "ok".into(), device_id: None, message: Some("Authenticated".into()) }
-            } else {
-                warn!("Authentication failed with token: {}", token);
-                Response { status: "error".into(), device_id: None, message: Some("Invalid token".into()) }
+    // Keys with a signing secret only accept signed commands, so a sniffed token isn't enough
+    if let Some(key) = session.key.as_ref().filter(|key| key.requires_signature()) {
+        let payload = serde_json::from_str::<serde_json::Value>(text)
+            .map(|message| signing::canonical_message(&message))
+            .unwrap_or_default();
+        let signature = Signature { timestamp: request.timestamp, nonce: request.nonce.as_deref(), signature: request.signature.as_deref() };
+        if let Err(message) = security.verify_signature(key, payload.as_bytes(), signature) {
+            warn!("[Audit] Key '{}' sent a command with a bad signature: {}", key.name, message);
+            return Response { request_id: request.request_id, status: "error".into(), error: Some(ErrorKind::Auth), device_id: None, message: Some(message), data: None };
+        }
+    }
+    let Request { request_id, command, .. } = request;
+
+    // Every command is checked against the key's scopes and devices
+    if let Some(key) = &session.key {
+        if let Err(message) = authorize(key, &command, devices).await {
+            warn!("[Audit] Key '{}' denied: {}", key.name, message);
+            return Response { request_id, status: "error".into(), error: Some(ErrorKind::Auth), device_id: None, message: Some(message), data: None };
+        }
+    }
+
+    let mut response = match command {
+        Command::Auth { token } => {
+            match security.login(&session.peer, &token) {
+                Ok(key) => {
+                    info!("[Audit] Client logged in with key '{}'", key.name);
+                    let data = serde_json::json!({ "key": key.name, "scopes": key.scopes() });
+                    session.key = Some(key);
+                    session.failed_logins = 0;
+                    Response { request_id: None, status: "ok".into(), error: None, device_id: None, message: Some("Authenticated".into()), data: Some(data) }
+                }
+                Err(e) => {
+                    session.failed_logins += 1;
+                    let message = login_refused(devices, &session.peer, e);
+                    Response { request_id: None, status: "error".into(), error: Some(ErrorKind::Auth), device_id: None, message: Some(message), data: None }
+                }
             }
         }
-        Ok(_) if !*authenticated => {
+        _ if session.key.is_none() => {
             warn!("Unauthorized command attempt");
-            Response { status: "error".into(), device_id: None, message: Some("Authentication required".into()) }
+            Response { request_id: None, status: "error".into(), error: Some(ErrorKind::Auth), device_id: None, message: Some("Authentication required".into()), data: None }
         }
-        Ok(Command::Print { device_id, data }) => {
-            if let Some(printer) = devices.get_printer(&device_id).await {
+        Command::Subscribe { topics } => {
+            match session.subscriptions.subscribe(&topics) {
+                Ok(_) => Response { request_id: None, status: "ok".into(), error: None, device_id: None, message: None, data: Some(serde_json::json!({ "topics": session.subscriptions.topics() })) },
+                Err(e) => Response { request_id: None, status: "error".into(), error: Some(ErrorKind::InvalidCommand), device_id: None, message: Some(e), data: None },
+            }
+        }
+        Command::Unsubscribe { topics } => {
+            session.subscriptions.unsubscribe(&topics);
+            Response { request_id: None, status: "ok".into(), error: None, device_id: None, message: None, data: Some(serde_json::json!({ "topics": session.subscriptions.topics() })) }
+        }
+        // Slow command with a request id: answer "accepted" now and run it in the
+        // background, so the client's next commands don't wait for this device
+        command if request_id.is_some() && command.is_slow() => {
+            let devices = devices.clone();
+            let replies = replies.clone();
+            let id = request_id.clone();
+            tokio::spawn(async move {
+                let mut response = execute_command(command, &devices).await;
+                response.request_id = id;
+                response.status = if response.status == "ok" { "completed".into() } else { "failed".into() };
+                // The client may be gone by now; nothing to do then
+                let _ = replies.send(response);
+            });
+            Response { request_id: None, status: "accepted".into(), error: None, device_id: None, message: None, data: None }
+        }
+        command => execute_command(command, devices).await,
+    };
+    response.request_id = request_id;
+    response
+}
+
+/// Logs a refused login (never the token itself), tells admin clients
+/// subscribed to "security.*" about it, and returns the message for the client.
+pub(crate) fn login_refused(devices: &DeviceManager, peer: &str, error: LoginError) -> String {
+    match error {
+        LoginError::InvalidToken(failure) => {
+            match failure.locked_for {
+                Some(lock) => warn!("[Audit] Failed login from {} ({} in a row), locked out for {}s", peer, failure.attempts, lock.as_secs()),
+                None => warn!("[Audit] Failed login from {} ({} in a row)", peer, failure.attempts),
+            }
+            devices.publish(Event::new(events::AUTH_FAILED, "", serde_json::json!({
+                "peer": peer,
+                "attempts": failure.attempts,
+                "locked_for_secs": failure.locked_for.map(|lock| lock.as_secs()),
+            })));
+            "Invalid token".into()
+        }
+        LoginError::LockedOut(wait) => {
+            let seconds = wait.as_secs_f64().ceil() as u64;
+            warn!("[Audit] Login from {} refused: locked out for another {}s", peer, seconds);
+            format!("Too many failed logins, try again in {} seconds", seconds)
+        }
+    }
+}
+
+// Fields whose value must never appear in the logs
+const SECRET_FIELDS: [&str; 1] = ["token"];
+
+// The message as it may be logged: secrets replaced by "***"
+fn redacted(text: &str) -> String {
+    match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(serde_json::Value::Object(mut fields)) => {
+            for field in SECRET_FIELDS {
+                if let Some(value) = fields.get_mut(field) {
+                    *value = "***".into();
+                }
+            }
+            serde_json::Value::Object(fields).to_string()
+        }
+        Ok(_) => text.to_string(),
+        // Might be a broken "auth" message; don't risk it
+        Err(_) => format!("({} bytes, not valid JSON)", text.len()),
+    }
+}
+
+/// Checks that `key` may run `command`: the right scope, and only on its devices.
+pub(crate) async fn authorize(key: &ApiKey, command: &Command, devices: &DeviceManager) -> Result<(), String> {
+    let (scope, device_ids): (Scope, Vec<String>) = match command {
+        Command::Auth { .. } | Command::Subscribe { .. } | Command::Unsubscribe { .. } => return Ok(()),
+        Command::Print { device_id, .. } | Command::PrintLabel { device_id, .. } | Command::Cut { device_id } => {
+            (Scope::Print, vec![device_id.clone()])
+        }
+        // Every printer the order would be split to (routing errors are reported when it runs)
+        Command::PrintOrder { data } => {
+            let printers = devices.route_order(data).await
+                .map(|routed| routed.into_iter().map(|(_, ticket)| ticket.printer_id).collect())
+                .unwrap_or_default();
+            (Scope::Print, printers)
+        }
+        Command::CancelJob { job_id } => (Scope::Print, job_printer(devices, job_id).await),
+        Command::OpenDrawer { device_id } => (Scope::Drawer, vec![device_id.clone()]),
+        Command::DisplayUpdate { device_id, .. } => (Scope::Display, vec![device_id.clone()]),
+        Command::GetStatus { device_id } | Command::ListJobs { device_id } => (Scope::Status, vec![device_id.clone()]),
+        Command::JobStatus { job_id } => (Scope::Status, job_printer(devices, job_id).await),
+        Command::ListDevices | Command::ListPorts => (Scope::Status, Vec::new()),
+        Command::GetMetrics => (Scope::Admin, Vec::new()),
+    };
+
+    if !key.allows(scope, None) {
+        return Err(format!("Permission denied: this key has no '{}' scope", scope.name()));
+    }
+    // A document with a "drawer" block opens the drawer too
+    if let Command::Print { data, .. } = command {
+        let opens_drawer = data.document.iter().flatten().any(|block| matches!(block, Block::Drawer { .. }));
+        if opens_drawer && !key.allows(Scope::Drawer, None) {
+            return Err(format!("Permission denied: this key has no '{}' scope (the document opens the drawer)", Scope::Drawer.name()));
+        }
+    }
+    match device_ids.iter().find(|device_id| !key.allows(scope, Some(device_id))) {
+        Some(device_id) => Err(format!("Permission denied: this key may not use {}", device_id)),
+        None => Ok(()),
+    }
+}
+
+// The printer a job belongs to (empty if the job doesn't exist)
+async fn job_printer(devices: &DeviceManager, job_id: &str) -> Vec<String> {
+    devices.find_job_queue(job_id).await
+        .map(|queue| vec![queue.printer_id().to_string()])
+        .unwrap_or_default()
+}
+
+// Runs a command of a logged-in client and builds the answer.
+pub(crate) async fn execute_command(command: Command, devices: &Arc<DeviceManager>) -> Response {
+    match command {
+        // Handled by `process_message`, they change the connection's session
+        Command::Auth { .. } | Command::Subscribe { .. } | Command::Unsubscribe { .. } => {
+            Response { request_id: None, status: "error".into(), error: Some(ErrorKind::InvalidCommand), device_id: None, message: Some("Unexpected session command".into()), data: None }
+        }
+        Command::Print { device_id, data } => {
+            if let Some(printer) = devices.get_print_queue(&device_id).await {
                 // Construct a single unified byte buffer for the print job
                 // This ensures "Text + Cut" happens as one atomic operation,
                 // preventing the OS Spooler (Windows) from treating them as separate jobs
                 // which can cause the cut command to fail or be ignored.
                 
+                // Paper width, cutter, code page and command set (ESC/POS or Star)
+                // come from the printer's profile
+                let encoder = ReceiptEncoder::for_printer(printer.as_ref());
                 let mut buffer: Vec<u8> = Vec::new();
                 
                 // 1. Initialize Printer (ESC @) - Standard ESC/POS
                 // This is safe to send even if the printer is already initialized.
-                // It ensures we start with a clean state (no stuck Bold/DoubleWidth modes).
-                buffer.extend_from_slice(&[0x1B, 0x40]);
+                // It ensures we start with a clean state (no stuck Bold/DoubleWidth modes),
+                // then selects the printer's code page (ESC t n) for accented characters.
+                encoder.initialize(&mut buffer);
 
-                // 2. Add content
+                // 2. Add content (plain text first, then the structured document)
                 if let Some(text) = data.text {
-                     buffer.extend_from_slice(text.as_bytes());
-                     // Ensure connection ends with a newline to flush the buffer on some devices
-                     if !text.ends_with('\n') {
-                         buffer.push(b'\n');
+                     encoder.encode_text(&mut buffer, &text);
+                }
+                if let Some(document) = data.document {
+                     if let Err(e) = encoder.encode_document(&mut buffer, &document) {
+                         return Response { request_id: None, status: "error".into(), error: Some(e.kind()), device_id: Some(device_id), message: Some(e.to_string()), data: None };
                      }
                 }
                 
-                // 3. Auto-Cut Sequence
+                // 3. Auto-Cut Sequence (Feed + GS V 66 0, adapted to the printer's cutter)
                 if data.auto_cut {
-                    // Feed 3 lines (ESC d 3) to clear the cutter blade
-                    buffer.extend_from_slice(&[0x1B, 0x64, 0x03]);
-                    // Cut Paper (GS V 66 0)
-                    buffer.extend_from_slice(&[0x1D, 0x56, 0x42, 0x00]);
+                    buffer.extend_from_slice(&encoder.cut(CutMode::Partial));
                 }
 
-                // 4. Send as ONE unified raw command
-                if let Err(e) = printer.print_raw(&buffer).await {
-                     return Response { status: "error".into(), device_id: Some(device_id), message: Some(e.to_string()) };
+                // 4. Queue it as ONE unified raw job. It is saved in the spool and
+                // retried while the printer is offline. We answer right away with the
+                // job id and queue position; a "job_completed"/"job_failed" event follows.
+                let job = printer.enqueue(buffer, data.expires_in_secs.map(Duration::from_secs));
+                Response { request_id: None, status: "ok".into(), error: None, device_id: Some(device_id), message: None, data: serde_json::to_value(job).ok() }
+            } else {
+                Response { request_id: None, status: "error".into(), error: Some(ErrorKind::NotFound), device_id: Some(device_id), message: Some("Device not found".into()), data: None }
+            }
+        }
+        Command::PrintOrder { data } => {
+            let routed = match devices.route_order(&data).await {
+                Ok(routed) => routed,
+                Err(e) => return Response { request_id: None, status: "error".into(), error: Some(e.kind()), device_id: None, message: Some(e.to_string()), data: None },
+            };
+
+            // Lay out every ticket first, so a bad ticket doesn't leave the order half printed
+            let mut jobs = Vec::new();
+            for (printer, ticket) in &routed {
+                let encoder = ReceiptEncoder::for_printer(printer.as_ref());
+                let mut buffer: Vec<u8> = Vec::new();
+                encoder.initialize(&mut buffer);
+                if let Err(e) = encoder.encode_document(&mut buffer, &ticket.document(&data)) {
+                    return Response { request_id: None, status: "error".into(), error: Some(e.kind()), device_id: Some(ticket.printer_id.clone()), message: Some(e.to_string()), data: None };
                 }
+                jobs.push(buffer);
+            }
 
-                Response { status: "ok".into(), device_id: Some(device_id), message: None }
+            let tickets: Vec<serde_json::Value> = routed.iter().zip(jobs).map(|((printer, ticket), buffer)| {
+                let job = printer.enqueue(buffer, None);
+                serde_json::json!({ "station": ticket.title, "printer_id": ticket.printer_id, "items": ticket.items.len(), "job": job })
+            }).collect();
+            info!("Order {} split into {} ticket(s)", data.order_number, tickets.len());
+
+            Response { request_id: None, status: "ok".into(), error: None, device_id: None, message: None, data: Some(serde_json::json!({ "order_number": data.order_number, "tickets": tickets })) }
+        }
+        Command::PrintLabel { device_id, data } => {
+            if let Some(printer) = devices.get_label_printer(&device_id).await {
+                // The driver renders the layout in the printer's language (ZPL, TSPL or EPL)
+                match printer.print_label(&data).await {
+                    Ok(_) => Response { request_id: None, status: "ok".into(), error: None, device_id: Some(device_id), message: None, data: None },
+                    Err(e) => Response { request_id: None, status: "error".into(), error: Some(e.kind()), device_id: Some(device_id), message: Some(e.to_string()), data: None },
+                }
+            } else {
+                Response { request_id: None, status: "error".into(), error: Some(ErrorKind::NotFound), device_id: Some(device_id), message: Some("Device not found".into()), data: None }
+            }
+        }
+        Command::GetStatus { device_id } => {
+            if let Some(printer) = devices.get_printer(&device_id).await {
+                // The printer answers DLE EOT 1-4 over the same connection
+                match printer.status().await {
+                    Ok(status) => Response { request_id: None, status: "ok".into(), error: None, device_id: Some(device_id), message: None, data: serde_json::to_value(status).ok() },
+                    Err(e) => Response { request_id: None, status: "error".into(), error: Some(e.kind()), device_id: Some(device_id), message: Some(e.to_string()), data: None },
+                }
             } else {
-                Response { status: "error".into(), device_id: Some(device_id), message: Some("Device not found".into()) }
+                Response { request_id: None, status: "error".into(), error: Some(ErrorKind::NotFound), device_id: Some(device_id), message: Some("Device not found".into()), data: None }
             }
         }
-        Ok(Command::Cut { device_id }) => {
+        Command::JobStatus { job_id } => {
+            match devices.find_job_queue(&job_id).await.and_then(|queue| queue.job(&job_id)) {
+                Some(job) => Response { request_id: None, status: "ok".into(), error: None, device_id: Some(job.printer_id.clone()), message: None, data: serde_json::to_value(job).ok() },
+                None => Response { request_id: None, status: "error".into(), error: Some(ErrorKind::NotFound), device_id: None, message: Some(format!("Job {} not found", job_id)), data: None },
+            }
+        }
+        Command::CancelJob { job_id } => {
+            if let Some(queue) = devices.find_job_queue(&job_id).await {
+                match queue.cancel(&job_id) {
+                    Ok(job) => Response { request_id: None, status: "ok".into(), error: None, device_id: Some(job.printer_id.clone()), message: Some("Job cancelled".into()), data: serde_json::to_value(job).ok() },
+                    Err(e) => Response { request_id: None, status: "error".into(), error: Some(e.kind()), device_id: None, message: Some(e.to_string()), data: None },
+                }
+            } else {
+                Response { request_id: None, status: "error".into(), error: Some(ErrorKind::NotFound), device_id: None, message: Some(format!("Job {} not found", job_id)), data: None }
+            }
+        }
+        Command::ListJobs { device_id } => {
+            if let Some(queue) = devices.get_print_queue(&device_id).await {
+                Response { request_id: None, status: "ok".into(), error: None, device_id: Some(device_id), message: None, data: serde_json::to_value(queue.jobs()).ok() }
+            } else {
+                Response { request_id: None, status: "error".into(), error: Some(ErrorKind::NotFound), device_id: Some(device_id), message: Some("Device not found".into()), data: None }
+            }
+        }
+        Command::ListDevices => {
+            Response { request_id: None, status: "ok".into(), error: None, device_id: None, message: None, data: Some(devices.list_devices().await) }
+        }
+        Command::ListPorts => {
+            match ports::list_ports() {
+                Ok(ports) => Response { request_id: None, status: "ok".into(), error: None, device_id: None, message: None, data: serde_json::to_value(ports).ok() },
+                Err(e) => Response { request_id: None, status: "error".into(), error: Some(e.kind()), device_id: None, message: Some(e.to_string()), data: None },
+            }
+        }
+        Command::GetMetrics => {
+            Response { request_id: None, status: "ok".into(), error: None, device_id: None, message: None, data: Some(metrics::snapshot()) }
+        }
+        Command::Cut { device_id } => {
             if let Some(printer) = devices.get_printer(&device_id).await {
                 // Use the same robust sequence for independent cuts
-                let cut_seq = [
-                    0x1B, 0x64, 0x03,       // Feed 3 lines
-                    0x1D, 0x56, 0x42, 0x00, // Cut
-                ];
+                let cut_seq = ReceiptEncoder::for_printer(printer.as_ref()).cut(CutMode::Partial);
                 match printer.print_raw(&cut_seq).await {
-                    Ok(_) => Response { status: "ok".into(), device_id: Some(device_id), message: None },
-                    Err(e) => Response { status: "error".into(), device_id: Some(device_id), message: Some(e.to_string()) },
+                    Ok(_) => Response { request_id: None, status: "ok".into(), error: None, device_id: Some(device_id), message: None, data: None },
+                    Err(e) => Response { request_id: None, status: "error".into(), error: Some(e.kind()), device_id: Some(device_id), message: Some(e.to_string()), data: None },
                 }
             } else {
-                Response { status: "error".into(), device_id: Some(device_id), message: Some("Device not found".into()) }
+                Response { request_id: None, status: "error".into(), error: Some(ErrorKind::NotFound), device_id: Some(device_id), message: Some("Device not found".into()), data: None }
             }
         }
-        Ok(Command::OpenDrawer { device_id }) => {
+        Command::OpenDrawer { device_id } => {
             if let Some(drawer) = devices.get_drawer(&device_id).await {
                 match drawer.open().await {
-                    Ok(_) => Response { status: "ok".into(), device_id: Some(device_id), message: None },
-                    Err(e) => Response { status: "error".into(), device_id: Some(device_id), message: Some(e.to_string()) },
+                    Ok(_) => Response { request_id: None, status: "ok".into(), error: None, device_id: Some(device_id), message: None, data: None },
+                    Err(e) => Response { request_id: None, status: "error".into(), error: Some(e.kind()), device_id: Some(device_id), message: Some(e.to_string()), data: None },
                 }
             } else {
-                 Response { status: "error".into(), device_id: Some(device_id), message: Some("Device not found".into()) }
+                 Response { request_id: None, status: "error".into(), error: Some(ErrorKind::NotFound), device_id: Some(device_id), message: Some("Device not found".into()), data: None }
             }
         }
-        Ok(Command::DisplayUpdate { device_id, data }) => {
+        Command::DisplayUpdate { device_id, data } => {
             if let Some(display) = devices.get_display(&device_id).await {
                  match display.show_text(&data.line1, &data.line2).await {
-                    Ok(_) => Response { status: "ok".into(), device_id: Some(device_id), message: None },
-                    Err(e) => Response { status: "error".into(), device_id: Some(device_id), message: Some(e.to_string()) },
+                    Ok(_) => Response { request_id: None, status: "ok".into(), error: None, device_id: Some(device_id), message: None, data: None },
+                    Err(e) => Response { request_id: None, status: "error".into(), error: Some(e.kind()), device_id: Some(device_id), message: Some(e.to_string()), data: None },
                  }
             } else {
-                 Response { status: "error".into(), device_id: Some(device_id), message: Some("Device not found".into()) }
+                 Response { request_id: None, status: "error".into(), error: Some(ErrorKind::NotFound), device_id: Some(device_id), message: Some("Device not found".into()), data: None }
             }
         }
-        Err(e) => {
-            warn!("Invalid JSON: {}", e);
-            Response { status: "error".into(), device_id: None, message: Some(format!("Invalid JSON format: {}", e)) }
-        }
     }
 }
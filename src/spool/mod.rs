@@ -0,0 +1,148 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use crate::config::SpoolConfig;
+use crate::errors::ServiceError;
+use tracing::{info, warn, error};
+
+// =========================================================================
+// PRINT SPOOL
+// =========================================================================
+// Print jobs are written to disk (one JSON file per job) as soon as they are
+// queued, and deleted once they are printed, cancelled or expired. If the
+// printer is offline the job stays in the spool and is retried with a
+// growing delay (2s, 4s, 8s... up to `retry_max_delay_secs`). If the service
+// restarts, the jobs still in the spool are queued again, so an order ticket
+// is never lost because the kitchen printer was switched off.
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SpooledJob {
+    pub job_id: String,
+    pub printer_id: String,
+    // Unix timestamps (seconds)
+    pub created_at: u64,
+    pub expires_at: u64,
+    // Microseconds since 1970, keeps the original order after a restart
+    pub order: u64,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    // The printer bytes, base64 encoded
+    pub data: String,
+}
+
+impl SpooledJob {
+    pub fn bytes(&self) -> Result<Vec<u8>, ServiceError> {
+        base64::engine::general_purpose::STANDARD
+            .decode(&self.data)
+            .map_err(|e| ServiceError::InternalError(format!("Spooled job {} is damaged: {}", self.job_id, e)))
+    }
+
+    pub fn encode_bytes(data: &[u8]) -> String {
+        base64::engine::general_purpose::STANDARD.encode(data)
+    }
+}
+
+pub struct Spool {
+    // None = jobs are only kept in memory (spool disabled or not writable)
+    dir: Option<PathBuf>,
+    job_expiry: Duration,
+    retry_max_delay: Duration,
+}
+
+impl Spool {
+    /// Opens (and creates if needed) the spool directory from the config.
+    /// If it can't be created, jobs are still queued and retried, but not saved.
+    pub fn open(config: &SpoolConfig) -> Self {
+        let dir = PathBuf::from(&config.directory);
+        let dir = match fs::create_dir_all(&dir) {
+            Ok(_) => {
+                info!("Print spool directory: {}", dir.display());
+                Some(dir)
+            }
+            Err(e) => {
+                error!("Cannot create spool directory {}: {}. Jobs will not survive a restart.", dir.display(), e);
+                None
+            }
+        };
+        Self {
+            dir,
+            job_expiry: Duration::from_secs(config.job_expiry_secs),
+            retry_max_delay: Duration::from_secs(config.retry_max_delay_secs.max(1)),
+        }
+    }
+
+    /// A spool that keeps nothing on disk.
+    pub fn disabled() -> Self {
+        let config = SpoolConfig::default();
+        Self {
+            dir: None,
+            job_expiry: Duration::from_secs(config.job_expiry_secs),
+            retry_max_delay: Duration::from_secs(config.retry_max_delay_secs),
+        }
+    }
+
+    /// How long a job may wait for its printer when the client didn't say.
+    pub fn job_expiry(&self) -> Duration {
+        self.job_expiry
+    }
+
+    /// Delay before the next attempt: 2s, 4s, 8s... capped at `retry_max_delay`.
+    pub fn retry_delay(&self, attempts: u32) -> Duration {
+        let seconds = 1u64.checked_shl(attempts.min(30)).unwrap_or(u64::MAX);
+        Duration::from_secs(seconds).min(self.retry_max_delay)
+    }
+
+    pub fn save(&self, job: &SpooledJob) {
+        let Some(path) = self.path(&job.job_id) else { return };
+        // Write to a temporary file first, so a crash never leaves half a job behind
+        let tmp = path.with_extension("tmp");
+        let result = serde_json::to_vec(job)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(&tmp, json).map_err(|e| e.to_string()))
+            .and_then(|_| fs::rename(&tmp, &path).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            error!("Failed to spool job {}: {}", job.job_id, e);
+        }
+    }
+
+    pub fn remove(&self, job_id: &str) {
+        let Some(path) = self.path(job_id) else { return };
+        if let Err(e) = fs::remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to remove spooled job {}: {}", job_id, e);
+            }
+        }
+    }
+
+    /// Reads every job left in the spool (e.g. after a restart), oldest first.
+    pub fn load_all(&self) -> Vec<SpooledJob> {
+        let Some(dir) = &self.dir else { return Vec::new() };
+        let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+
+        let mut jobs: Vec<SpooledJob> = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            match fs::read(&path).map_err(|e| e.to_string()).and_then(|bytes| {
+                serde_json::from_slice::<SpooledJob>(&bytes).map_err(|e| e.to_string())
+            }) {
+                Ok(job) => jobs.push(job),
+                Err(e) => warn!("Skipping unreadable spool file {}: {}", path.display(), e),
+            }
+        }
+        jobs.sort_by_key(|job| job.order);
+        jobs
+    }
+
+    fn path(&self, job_id: &str) -> Option<PathBuf> {
+        // Job ids are UUIDs we generated; refuse anything that could escape the directory
+        if job_id.is_empty() || !job_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return None;
+        }
+        self.dir.as_ref().map(|dir| dir.join(format!("{}.json", job_id)))
+    }
+}
@@ -0,0 +1,198 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use rcgen::{BasicConstraints, CertificateParams, CidrSubnet, DnType, ExtendedKeyUsagePurpose, GeneralSubtree, IsCa, KeyPair, KeyUsagePurpose, NameConstraints};
+use tokio_rustls::rustls::{self, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+use crate::config::TlsConfig;
+use crate::errors::ServiceError;
+use tracing::{info, warn, error};
+
+// =========================================================================
+// TLS (wss://)
+// =========================================================================
+// A POS web app served over https:// may not talk to ws://localhost (mixed
+// content). With a certificate, the service speaks wss:// instead.
+//
+// 1. `pos_hardware_service gen-cert` creates a small local CA and a
+//    certificate for localhost signed by it (in the "certs" folder).
+// 2. Install certs/ca.pem as a trusted root on the POS computer (once).
+//    The CA can only sign certificates for localhost, 127.0.0.1 and ::1
+//    (name constraints), so its key (certs/ca-key.pem) can't be used to
+//    impersonate real websites.
+// 3. Point [tls] in config.toml at certs/localhost.pem and certs/localhost-key.pem.
+//
+// The certificate files are checked every few seconds; replaced files are
+// loaded without restarting the service (running connections keep the old one).
+
+pub const CA_CERT: &str = "ca.pem";
+pub const CA_KEY: &str = "ca-key.pem";
+pub const SERVER_CERT: &str = "localhost.pem";
+pub const SERVER_KEY: &str = "localhost-key.pem";
+
+pub struct Tls {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    // Swapped when the certificate files change
+    acceptor: RwLock<TlsAcceptor>,
+}
+
+impl Tls {
+    pub fn load(config: &TlsConfig) -> Result<Arc<Self>, ServiceError> {
+        let cert_path = PathBuf::from(&config.cert_path);
+        let key_path = PathBuf::from(&config.key_path);
+        let acceptor = build_acceptor(&cert_path, &key_path)?;
+        info!("TLS certificate loaded from {}", cert_path.display());
+        Ok(Arc::new(Self { cert_path, key_path, acceptor: RwLock::new(acceptor) }))
+    }
+
+    /// The acceptor for the next connection (always the newest certificate).
+    pub fn acceptor(&self) -> TlsAcceptor {
+        self.acceptor.read().unwrap().clone()
+    }
+
+    /// Checks the certificate files every `every` and reloads them when they change.
+    /// A broken new certificate is logged and the old one stays in use.
+    pub fn watch(self: &Arc<Self>, every: Duration) {
+        let tls = self.clone();
+        tokio::spawn(async move {
+            let mut seen = tls.modified();
+            loop {
+                tokio::time::sleep(every).await;
+                let modified = tls.modified();
+                if modified == seen {
+                    continue;
+                }
+                seen = modified;
+                match build_acceptor(&tls.cert_path, &tls.key_path) {
+                    Ok(acceptor) => {
+                        *tls.acceptor.write().unwrap() = acceptor;
+                        info!("TLS certificate reloaded from {}", tls.cert_path.display());
+                    }
+                    Err(e) => error!("New TLS certificate not loaded, keeping the old one: {}", e),
+                }
+            }
+        });
+    }
+
+    fn modified(&self) -> (Option<SystemTime>, Option<SystemTime>) {
+        let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+        (modified(&self.cert_path), modified(&self.key_path))
+    }
+}
+
+fn build_acceptor(cert_path: &Path, key_path: &Path) -> Result<TlsAcceptor, ServiceError> {
+    let open = |path: &Path| File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| ServiceError::ConfigError(format!("Cannot read {}: {}", path.display(), e)));
+
+    let certs = rustls_pemfile::certs(&mut open(cert_path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| ServiceError::ConfigError(format!("Invalid certificate {}: {}", cert_path.display(), e)))?;
+    if certs.is_empty() {
+        return Err(ServiceError::ConfigError(format!("No certificate found in {}", cert_path.display())));
+    }
+    let key = rustls_pemfile::private_key(&mut open(key_path)?)
+        .map_err(|e| ServiceError::ConfigError(format!("Invalid private key {}: {}", key_path.display(), e)))?
+        .ok_or_else(|| ServiceError::ConfigError(format!("No private key found in {}", key_path.display())))?;
+
+    let config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(|e| ServiceError::ConfigError(format!("TLS setup failed: {}", e)))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+// -------------------------------------------------------------------------
+// CERTIFICATE GENERATION ("gen-cert")
+// -------------------------------------------------------------------------
+
+/// Creates (or renews) the localhost certificate in `dir`.
+/// An existing CA is reused, so renewing doesn't need the CA to be trusted again.
+pub fn generate_local_certificates(dir: &Path) -> Result<(), ServiceError> {
+    fs::create_dir_all(dir)?;
+    let failed = |e: rcgen::Error| ServiceError::InternalError(format!("Certificate generation failed: {}", e));
+    let year = current_year();
+
+    // 1. The local CA (only the POS computer trusts it)
+    let ca_cert_path = dir.join(CA_CERT);
+    let ca_key_path = dir.join(CA_KEY);
+    let existing = if ca_cert_path.exists() && ca_key_path.exists() {
+        let params = CertificateParams::from_ca_cert_pem(&fs::read_to_string(&ca_cert_path)?).map_err(failed)?;
+        let key = KeyPair::from_pem(&fs::read_to_string(&ca_key_path)?).map_err(failed)?;
+        // CAs made by older versions could sign certificates for any website
+        if params.name_constraints.is_some() {
+            info!("Reusing the CA in {}", ca_cert_path.display());
+            Some((params, key))
+        } else {
+            warn!("The CA in {} is not limited to localhost; creating a new one. Remove the old one from the trusted roots and trust the new one.", ca_cert_path.display());
+            None
+        }
+    } else {
+        None
+    };
+    let new_ca = existing.is_none();
+    let (ca_params, ca_key) = match existing {
+        Some(ca) => ca,
+        None => {
+            let mut params = CertificateParams::default();
+            params.distinguished_name.push(DnType::CommonName, "POS Hardware Service Local CA");
+            params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+            params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+            // Only certificates for this computer
+            params.name_constraints = Some(NameConstraints {
+                permitted_subtrees: vec![
+                    GeneralSubtree::DnsName("localhost".to_string()),
+                    GeneralSubtree::IpAddress(CidrSubnet::V4([127, 0, 0, 1], [255; 4])),
+                    GeneralSubtree::IpAddress(CidrSubnet::V6(std::net::Ipv6Addr::LOCALHOST.octets(), [255; 16])),
+                ],
+                excluded_subtrees: Vec::new(),
+            });
+            params.not_before = rcgen::date_time_ymd(year, 1, 1);
+            params.not_after = rcgen::date_time_ymd(year + 10, 1, 1);
+            let key = KeyPair::generate().map_err(failed)?;
+            (params, key)
+        }
+    };
+    let ca_cert = ca_params.self_signed(&ca_key).map_err(failed)?;
+    if new_ca {
+        write_key(&ca_key_path, &ca_key.serialize_pem())?;
+        fs::write(&ca_cert_path, ca_cert.pem())?;
+    }
+
+    // 2. The certificate the service presents, valid for localhost and 127.0.0.1.
+    // Browsers refuse server certificates valid for more than ~2 years.
+    let mut params = CertificateParams::new(vec!["localhost".to_string(), "127.0.0.1".to_string(), "::1".to_string()]).map_err(failed)?;
+    params.distinguished_name.push(DnType::CommonName, "localhost");
+    params.key_usages = vec![KeyUsagePurpose::DigitalSignature, KeyUsagePurpose::KeyEncipherment];
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+    params.not_before = rcgen::date_time_ymd(year, 1, 1);
+    params.not_after = rcgen::date_time_ymd(year + 2, 1, 1);
+    let key = KeyPair::generate().map_err(failed)?;
+    let cert = params.signed_by(&key, &ca_cert, &ca_key).map_err(failed)?;
+
+    // Key first: a running service reloads as soon as the certificate changes
+    write_key(&dir.join(SERVER_KEY), &key.serialize_pem())?;
+    fs::write(dir.join(SERVER_CERT), cert.pem())?;
+    Ok(())
+}
+
+// Private keys are readable by the owner only (where the OS supports it)
+fn write_key(path: &Path, pem: &str) -> Result<(), ServiceError> {
+    fs::write(path, pem)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+// Good enough for certificate validity dates (may be off by a day around New Year)
+fn current_year() -> i32 {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    // Average Gregorian year; two days back so we never start in the future
+    1970 + (seconds.saturating_sub(2 * 86_400) / 31_556_952) as i32
+}
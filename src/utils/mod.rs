@@ -3,13 +3,19 @@ pub fn get_version() -> &'static str {
 }
 
 pub fn log_available_ports() {
-    match serialport::available_ports() {
+    match crate::hardware::transport::ports::list_ports() {
         Ok(ports) => {
             if ports.is_empty() {
                 tracing::info!("No serial ports detected on this system.");
             } else {
                 let port_names: Vec<String> = ports.iter().map(|p| p.port_name.clone()).collect();
                 tracing::info!("Detected Serial Ports: {}", port_names.join(", "));
+                // USB devices can be configured by their ids, which don't change with the USB socket
+                for port in ports.iter().filter(|p| p.usb_connection.is_some()) {
+                    tracing::info!("  {} is USB device {} ({})", port.port_name,
+                        port.usb_connection.as_deref().unwrap_or_default(),
+                        port.product.as_deref().or(port.manufacturer.as_deref()).unwrap_or("unknown product"));
+                }
                 tracing::info!("Hint: Update config.toml with one of these ports if using Serial/USB devices.");
             }
         },